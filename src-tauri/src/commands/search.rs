@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path};
 
+use crate::markdown::parser::MarkdownState;
+
 #[cfg(target_os = "windows")]
 use std::os::windows::fs::MetadataExt;
 
@@ -35,7 +37,7 @@ pub struct NoteSearchResult {
 }
 
 #[tauri::command]
-pub fn scan_directory(path: String) -> Result<FileNode, String> {
+pub fn scan_directory(path: String, state: tauri::State<MarkdownState>) -> Result<FileNode, String> {
     if path.contains("..") {
         return Err("Path traversal not allowed".to_string());
     }
@@ -63,7 +65,28 @@ pub fn scan_directory(path: String) -> Result<FileNode, String> {
         }
     }
 
-    build_tree(&canonical_path)
+    let tree = build_tree(&canonical_path)?;
+
+    // Wikilinks in the preview resolve against the notes of this tree
+    let mut notes = Vec::new();
+    collect_note_paths(&tree, &mut notes);
+    if let Ok(mut parser) = state.gfm.lock() {
+        parser.set_workspace(&tree.path, &notes);
+    }
+
+    Ok(tree)
+}
+
+fn collect_note_paths(node: &FileNode, paths: &mut Vec<String>) {
+    match &node.children {
+        Some(children) => {
+            for child in children {
+                collect_note_paths(child, paths);
+            }
+        }
+        None if !node.is_directory => paths.push(node.path.clone()),
+        None => {}
+    }
 }
 
 fn build_tree(path: &Path) -> Result<FileNode, String> {
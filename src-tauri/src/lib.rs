@@ -1,33 +1,139 @@
 mod markdown;
 mod commands;
 
+use crate::markdown::ast::MarkdownAst;
+use crate::markdown::formatter::FormatOptions;
+use crate::markdown::lint::LintConfig;
+use crate::markdown::parser::{CalloutType, MarkdownState, ParseResult, ParserOptions, TextEdit};
+
 #[tauri::command]
 fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
 #[tauri::command]
-fn parse_markdown_basic(markdown: String) -> Result<crate::markdown::parser::ParseResult, String> {
-    println!("📝 Usando Basic Parser");
-    crate::markdown::basic_parser::parse_basic_markdown_to_html(&markdown)
+fn parse_markdown_gfm(
+    markdown: String,
+    path: Option<String>,
+    blocks: Option<bool>,
+    state: tauri::State<MarkdownState>,
+) -> Result<ParseResult, String> {
+    let mut parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    parser.set_document_path(path);
+    parser.set_block_output(blocks.unwrap_or(false));
+    Ok(parser.render_document(&markdown))
+}
+
+#[tauri::command]
+fn update_markdown_gfm(
+    previous_version: u64,
+    edit: TextEdit,
+    blocks: Option<bool>,
+    state: tauri::State<MarkdownState>,
+) -> Result<ParseResult, String> {
+    let mut parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    parser.set_block_output(blocks.unwrap_or(false));
+    parser.apply_edit(previous_version, &edit)
+}
+
+#[tauri::command]
+fn parse_markdown_ast(markdown: String, state: tauri::State<MarkdownState>) -> Result<MarkdownAst, String> {
+    let parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    Ok(parser.parse_ast(&markdown))
+}
+
+#[tauri::command]
+fn format_markdown(
+    markdown: String,
+    options: Option<FormatOptions>,
+    state: tauri::State<MarkdownState>,
+) -> Result<String, String> {
+    let parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    Ok(parser.format(&markdown, &options.unwrap_or_default()))
+}
+
+// Ticks or unticks the task on `line` of the file, unless the line no longer
+// reads `expected`, and renders the file again
+#[tauri::command]
+fn toggle_task(
+    path: String,
+    line: usize,
+    expected: String,
+    blocks: Option<bool>,
+    state: tauri::State<MarkdownState>,
+) -> Result<ParseResult, String> {
+    let content = commands::files::read_file(path.clone())?;
+    let updated = crate::markdown::tasks::toggle_task(&content, line, &expected)?;
+    commands::files::write_file(path.clone(), updated.clone())?;
+
+    let mut parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    parser.set_document_path(Some(path));
+    parser.set_block_output(blocks.unwrap_or(false));
+    Ok(parser.render_document(&updated))
+}
+
+#[tauri::command]
+fn set_parser_options(options: ParserOptions, state: tauri::State<MarkdownState>) -> Result<(), String> {
+    let mut parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    parser.set_options(options);
+    Ok(())
+}
+
+#[tauri::command]
+fn set_callout_types(types: Vec<CalloutType>, state: tauri::State<MarkdownState>) -> Result<(), String> {
+    let mut parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    parser.set_callout_types(types);
+    Ok(())
 }
 
 #[tauri::command]
-fn parse_markdown_gfm(markdown: String) -> Result<crate::markdown::parser::ParseResult, String> {
-    println!("🚀 Usando GFM Parser");
-    crate::markdown::gfm_parser::parse_gfm_markdown_to_html(&markdown)
+fn set_lint_config(config: LintConfig, state: tauri::State<MarkdownState>) -> Result<(), String> {
+    let mut parser = state
+        .gfm
+        .lock()
+        .map_err(|e| format!("Failed to lock markdown parser: {}", e))?;
+    parser.set_lint_config(config);
+    Ok(())
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
+        .manage(MarkdownState::default())
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
         .invoke_handler(tauri::generate_handler![
             greet,
-            parse_markdown_basic,
             parse_markdown_gfm,
+            update_markdown_gfm,
+            parse_markdown_ast,
+            format_markdown,
+            toggle_task,
+            set_parser_options,
+            set_callout_types,
+            set_lint_config,
             // Config commands
             commands::config::get_app_config_dir,
             commands::config::save_appearance_config,
@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+
+use crate::markdown::gfm_parser::{Alignment, GfmBlock, GfmListItem, GfmToken};
+
+// Bumped whenever a node or field changes shape, so tools built on the AST
+// can tell which layout they are reading. Mirrored in src/types/markdownAst.ts.
+pub const AST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarkdownAst {
+    pub version: u32,
+    // YAML front matter as a JSON object, when the note has a valid one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub front_matter: Option<serde_json::Value>,
+    pub children: Vec<AstNode>,
+}
+
+// A block node. Text fields hold the block's inline markdown as written,
+// without the block syntax around it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AstNode {
+    #[serde(flatten)]
+    pub kind: AstNodeKind,
+    pub position: Position,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<AstNode>,
+}
+
+// Source lines are 1-based and inclusive, like `SourceMapEntry`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Position {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AstNodeKind {
+    Heading { level: u8, text: String },
+    Paragraph { text: String },
+    CodeBlock { language: Option<String>, code: String },
+    Math { tex: String },
+    Html { html: String },
+    List { ordered: bool, start: u32, tight: bool },
+    // `checked` is set for task list items
+    ListItem { checked: Option<bool> },
+    Table { alignments: Vec<CellAlignment>, headers: Vec<String>, rows: Vec<Vec<String>> },
+    Blockquote,
+    Callout { kind: String, title: Option<String>, folded: Option<bool> },
+    FootnoteDefinition { label: String },
+    Embed { links: Vec<EmbedLink> },
+    ThematicBreak,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellAlignment {
+    Left,
+    Center,
+    Right,
+    None,
+}
+
+// One `![[target#heading|alias]]` line of an embed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbedLink {
+    pub target: String,
+    pub heading: Option<String>,
+    pub alias: Option<String>,
+}
+
+impl From<GfmBlock> for AstNode {
+    fn from(block: GfmBlock) -> Self {
+        let position = Position {
+            start_line: block.start_line,
+            end_line: block.end_line,
+        };
+        let (kind, children) = match block.token {
+            GfmToken::Heading { level, text } => (AstNodeKind::Heading { level, text }, Vec::new()),
+            GfmToken::Paragraph(text) => (AstNodeKind::Paragraph { text }, Vec::new()),
+            GfmToken::CodeBlock { language, code, .. } => (AstNodeKind::CodeBlock { language, code }, Vec::new()),
+            GfmToken::Math(tex) => (AstNodeKind::Math { tex }, Vec::new()),
+            GfmToken::HtmlBlock(html) => (AstNodeKind::Html { html }, Vec::new()),
+            GfmToken::List { items, ordered, start, tight } => (
+                AstNodeKind::List { ordered, start, tight },
+                items.into_iter().map(AstNode::from).collect(),
+            ),
+            GfmToken::Table { headers, rows, alignments } => (
+                AstNodeKind::Table {
+                    alignments: alignments.iter().map(CellAlignment::from).collect(),
+                    headers,
+                    rows,
+                },
+                Vec::new(),
+            ),
+            GfmToken::Blockquote(content) => (AstNodeKind::Blockquote, convert_blocks(content)),
+            GfmToken::Callout { kind, title, folded, content } => {
+                (AstNodeKind::Callout { kind, title, folded }, convert_blocks(content))
+            }
+            GfmToken::FootnoteDefinition { label, content } => {
+                (AstNodeKind::FootnoteDefinition { label }, convert_blocks(content))
+            }
+            GfmToken::Embed(links) => (
+                AstNodeKind::Embed {
+                    links: links
+                        .into_iter()
+                        .map(|link| EmbedLink {
+                            target: link.target,
+                            heading: link.heading,
+                            alias: link.alias,
+                        })
+                        .collect(),
+                },
+                Vec::new(),
+            ),
+            GfmToken::HorizontalRule => (AstNodeKind::ThematicBreak, Vec::new()),
+        };
+
+        Self { kind, position, children }
+    }
+}
+
+impl From<GfmListItem> for AstNode {
+    fn from(item: GfmListItem) -> Self {
+        Self {
+            kind: AstNodeKind::ListItem { checked: item.checked },
+            position: Position {
+                start_line: item.start_line,
+                end_line: item.end_line,
+            },
+            children: convert_blocks(item.content),
+        }
+    }
+}
+
+impl From<&Alignment> for CellAlignment {
+    fn from(alignment: &Alignment) -> Self {
+        match alignment {
+            Alignment::Left => CellAlignment::Left,
+            Alignment::Center => CellAlignment::Center,
+            Alignment::Right => CellAlignment::Right,
+            Alignment::None => CellAlignment::None,
+        }
+    }
+}
+
+pub fn convert_blocks(blocks: Vec<GfmBlock>) -> Vec<AstNode> {
+    blocks.into_iter().map(AstNode::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use serde_json::Value;
+
+    use crate::markdown::gfm_parser::GfmMarkdownParser;
+
+    const TYPESCRIPT: &str = include_str!("../../../src/types/markdownAst.ts");
+
+    // Each document is rendered to JSON and compared with the snapshot next
+    // to it. Update a snapshot by pasting the JSON from the failure message.
+    const SNAPSHOTS: &[(&str, &str, &str)] = &[
+        (
+            "blocks",
+            include_str!("testdata/ast/blocks.md"),
+            include_str!("testdata/ast/blocks.json"),
+        ),
+        (
+            "extensions",
+            include_str!("testdata/ast/extensions.md"),
+            include_str!("testdata/ast/extensions.json"),
+        ),
+    ];
+
+    fn serialize(markdown: &str) -> Value {
+        serde_json::to_value(GfmMarkdownParser::new().parse_ast(markdown)).expect("the AST should serialize")
+    }
+
+    #[test]
+    fn ast_snapshots() {
+        for (name, markdown, snapshot) in SNAPSHOTS {
+            let actual = serialize(markdown);
+            let expected: Value = serde_json::from_str(snapshot).expect("snapshots should be valid JSON");
+            assert!(
+                actual == expected,
+                "AST for testdata/ast/{}.md changed:\n{}",
+                name,
+                serde_json::to_string_pretty(&actual).unwrap_or_default()
+            );
+        }
+    }
+
+    // Field names and types of one object type in markdownAst.ts
+    type Fields = BTreeMap<String, String>;
+
+    fn parse_fields(body: &str) -> Fields {
+        body.split([';', '\n'])
+            .filter_map(|field| field.split_once(':'))
+            .map(|(name, ts_type)| (name.trim().to_string(), ts_type.trim().to_string()))
+            .filter(|(name, _)| !name.is_empty() && !name.starts_with("//"))
+            .collect()
+    }
+
+    fn interface(name: &str) -> Fields {
+        let start = TYPESCRIPT
+            .find(&format!("export interface {} {{", name))
+            .unwrap_or_else(|| panic!("markdownAst.ts should declare {}", name));
+        let body = &TYPESCRIPT[start..];
+        parse_fields(&body[body.find('{').unwrap_or(0) + 1..body.find('}').unwrap_or(0)])
+    }
+
+    // The `AstNodeKind` union, keyed by `type`
+    fn node_kinds() -> BTreeMap<String, Fields> {
+        let start = TYPESCRIPT.find("export type AstNodeKind =").expect("markdownAst.ts should declare AstNodeKind");
+        let union = &TYPESCRIPT[start..];
+        let union = &union[..union.find("};").map_or(union.len(), |end| end + 1)];
+        union
+            .split("| {")
+            .skip(1)
+            .map(|variant| {
+                let mut fields = parse_fields(variant.trim_end_matches(['}', ' ', '\n', ';']));
+                let kind = fields.remove("type").unwrap_or_default().trim_matches('\'').to_string();
+                (kind, fields)
+            })
+            .collect()
+    }
+
+    fn assert_ts_type(value: &Value, ts_type: &str, context: &str) {
+        if let Some(inner) = ts_type.strip_suffix(" | null") {
+            if !value.is_null() {
+                assert_ts_type(value, inner, context);
+            }
+            return;
+        }
+        if let Some(inner) = ts_type.strip_suffix("[]") {
+            let items = value.as_array().unwrap_or_else(|| panic!("{} should be an array: {}", context, value));
+            for item in items {
+                assert_ts_type(item, inner, context);
+            }
+            return;
+        }
+
+        let matches = match ts_type {
+            "string" => value.is_string(),
+            "number" => value.is_u64(),
+            "boolean" => value.is_boolean(),
+            "CellAlignment" => {
+                let start = TYPESCRIPT.find("export type CellAlignment =").unwrap_or(0);
+                let declaration = &TYPESCRIPT[start..start + TYPESCRIPT[start..].find(';').unwrap_or(0)];
+                value.as_str().is_some_and(|alignment| declaration.contains(&format!("'{}'", alignment)))
+            }
+            "Record<string, unknown>" => value.is_object(),
+            name => {
+                assert_object(value, &interface(name), &[], context);
+                true
+            }
+        };
+        assert!(matches, "{} should be {}: {}", context, ts_type, value);
+    }
+
+    fn assert_object(value: &Value, fields: &Fields, skip: &[&str], context: &str) {
+        let object = value.as_object().unwrap_or_else(|| panic!("{} should be an object: {}", context, value));
+        let keys: BTreeSet<&str> = object.keys().map(String::as_str).filter(|key| !skip.contains(key)).collect();
+        let declared: BTreeSet<&str> = fields
+            .keys()
+            .filter(|name| !name.ends_with('?') || object.contains_key(name.trim_end_matches('?')))
+            .map(|name| name.trim_end_matches('?'))
+            .filter(|name| !skip.contains(name))
+            .collect();
+        assert_eq!(keys, declared, "fields of {} differ from markdownAst.ts", context);
+
+        for (name, ts_type) in fields {
+            let name = name.trim_end_matches('?');
+            if skip.contains(&name) {
+                continue;
+            }
+            if let Some(field) = object.get(name) {
+                assert_ts_type(field, ts_type, &format!("{}.{}", context, name));
+            }
+        }
+    }
+
+    fn check_node(node: &Value, kinds: &BTreeMap<String, Fields>, seen: &mut BTreeSet<String>) {
+        let kind = node["type"].as_str().expect("nodes should have a type");
+        let fields = kinds
+            .get(kind)
+            .unwrap_or_else(|| panic!("markdownAst.ts has no `{}` node", kind));
+        seen.insert(kind.to_string());
+        assert_object(node, fields, &["type", "position", "children"], kind);
+        assert_object(&node["position"], &interface("AstPosition"), &[], &format!("{}.position", kind));
+
+        for child in node.get("children").and_then(Value::as_array).into_iter().flatten() {
+            check_node(child, kinds, seen);
+        }
+    }
+
+    #[test]
+    fn typescript_types_match_serde_output() {
+        let version = TYPESCRIPT
+            .lines()
+            .find_map(|line| line.strip_prefix("export const MARKDOWN_AST_VERSION = "))
+            .expect("markdownAst.ts should export MARKDOWN_AST_VERSION");
+        assert_eq!(version.trim_end_matches(';'), super::AST_VERSION.to_string());
+
+        let kinds = node_kinds();
+        let mut seen = BTreeSet::new();
+        for (_, markdown, _) in SNAPSHOTS {
+            let ast = serialize(markdown);
+            assert_object(&ast, &interface("MarkdownAst"), &["children"], "MarkdownAst");
+            for node in ast["children"].as_array().into_iter().flatten() {
+                check_node(node, &kinds, &mut seen);
+            }
+        }
+
+        // The snapshots use every kind of node, so none goes unchecked
+        let declared: BTreeSet<String> = kinds.keys().cloned().collect();
+        assert_eq!(seen, declared, "the snapshots should cover every node type");
+    }
+}
@@ -0,0 +1,141 @@
+// Runs every example of the CommonMark spec through the parser with the GFM
+// extensions turned off. The examples in KNOWN_FAILURES differ on purpose
+// (or for a known reason); the test also fails when one of them starts to
+// pass, so the list never goes stale. Every example also goes through the
+// formatter, which must not change how it renders.
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+
+use crate::markdown::formatter::{FormatOptions, LineWrap};
+use crate::markdown::gfm_parser::{strip_source_lines, GfmMarkdownParser};
+use crate::markdown::parser::ParserOptions;
+
+const SPEC: &str = include_str!("testdata/commonmark-0.31.2.json");
+
+// Raw HTML goes through the sanitizer: tags outside its allowlist are
+// escaped and comments are dropped.
+const SANITIZED_HTML: &[u32] = &[
+    21, 31, 148, 149, 150, 151, 152, 153, 154, 155, 156, 157, 158, 159, 160, 161, 162, 163, 164, 165, 166, 167, 168,
+    169, 170, 171, 172, 173, 174, 175, 176, 177, 178, 179, 180, 181, 182, 183, 184, 185, 186, 187, 188, 189, 190, 191,
+    201, 308, 309, 344, 475, 476, 477, 491, 494, 524, 536, 613, 614, 615, 616, 617, 623, 625, 626, 627, 628, 629, 630,
+    631, 642, 643,
+];
+
+// Link destinations with a scheme outside the allowlist get an empty href.
+const UNSAFE_SCHEMES: &[u32] = &[500, 596, 598, 599, 601];
+
+// Labels are matched with lowercase rather than full Unicode case folding,
+// so `ẞ` does not match `SS`.
+const CASE_FOLDING: &[u32] = &[540];
+
+#[derive(Deserialize)]
+struct Example {
+    markdown: String,
+    html: String,
+    example: u32,
+    section: String,
+}
+
+fn commonmark_options() -> ParserOptions {
+    ParserOptions {
+        tables: false,
+        task_lists: false,
+        strikethrough: false,
+        autolinks: false,
+        footnotes: false,
+        alerts: false,
+        math: false,
+        wikilinks: false,
+        tags: false,
+        front_matter: false,
+    }
+}
+
+fn strip_attribute(html: &str, name: &str) -> String {
+    let pattern = format!(" {}=\"", name);
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(pos) = rest.find(&pattern) {
+        out.push_str(&rest[..pos]);
+        let value = &rest[pos + pattern.len()..];
+        rest = value.find('"').map_or("", |end| &value[end + 1..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+// Drops what the preview adds on top of the reference HTML (source lines,
+// heading ids, lazy images) and the spellings that mean the same thing.
+fn normalize(html: &str) -> String {
+    let mut html = html.to_string();
+    for attribute in ["data-source-line", "data-source-end-line", "id", "loading"] {
+        html = strip_attribute(&html, attribute);
+    }
+    html.replace(" />", ">").replace("&#x27;", "'").trim_end().to_string()
+}
+
+#[test]
+fn commonmark_spec_examples() {
+    let examples: Vec<Example> = serde_json::from_str(SPEC).expect("spec examples should be valid JSON");
+    assert_eq!(examples.len(), 652);
+
+    let known: BTreeSet<u32> = [SANITIZED_HTML, UNSAFE_SCHEMES, CASE_FOLDING].concat().into_iter().collect();
+    let mut parser = GfmMarkdownParser::new();
+    parser.set_options(commonmark_options());
+
+    let mut failures = Vec::new();
+    let mut fixed = Vec::new();
+    for example in &examples {
+        let html = parser.render_document(&example.markdown).html;
+        let passed = normalize(&html) == normalize(&example.html);
+        if !passed && !known.contains(&example.example) {
+            failures.push(format!(
+                "example {} ({})\n{:?}\nexpected:\n{}\nactual:\n{}",
+                example.example, example.section, example.markdown, example.html, html
+            ));
+        } else if passed && known.contains(&example.example) {
+            fixed.push(example.example);
+        }
+    }
+
+    assert!(failures.is_empty(), "{} spec examples failed:\n\n{}", failures.len(), failures.join("\n\n"));
+    assert!(fixed.is_empty(), "remove these examples from the known failures: {:?}", fixed);
+}
+
+#[test]
+fn formatting_keeps_how_spec_examples_render() {
+    let examples: Vec<Example> = serde_json::from_str(SPEC).expect("spec examples should be valid JSON");
+
+    // With the extensions on too: `---` can start front matter, for one
+    for parser_options in [commonmark_options(), ParserOptions::default()] {
+        let mut parser = GfmMarkdownParser::new();
+        parser.set_options(parser_options);
+        for wrap in [LineWrap::Preserve, LineWrap::Unwrap, LineWrap::Wrap] {
+            let options = FormatOptions { wrap, line_width: 20 };
+            // Line breaks inside paragraphs render like spaces
+            let render = |parser: &mut GfmMarkdownParser, markdown: &str| {
+                let html = strip_source_lines(&parser.render_document(markdown).html);
+                if wrap == LineWrap::Preserve { html } else { html.replace('\n', " ") }
+            };
+
+            let mut failures = Vec::new();
+            for example in &examples {
+                let formatted = parser.format(&example.markdown, &options);
+                if render(&mut parser, &formatted) != render(&mut parser, &example.markdown) {
+                    failures.push(format!("example {}\n{:?}\nformatted:\n{:?}", example.example, example.markdown, formatted));
+                } else if parser.format(&formatted, &options) != formatted {
+                    failures.push(format!("example {} formats differently twice\n{:?}", example.example, formatted));
+                }
+            }
+            assert!(
+                failures.is_empty(),
+                "{} spec examples changed when formatted with {:?} and {:?}:\n\n{}",
+                failures.len(),
+                wrap,
+                parser_options,
+                failures.join("\n\n")
+            );
+        }
+    }
+}
@@ -0,0 +1,647 @@
+use serde::{Deserialize, Serialize};
+
+use crate::markdown::gfm_parser::{
+    is_thematic_break, strip_source_lines, Alignment, GfmBlock, GfmListItem, GfmMarkdownParser, GfmToken,
+};
+use crate::markdown::parser::ParserOptions;
+
+// What to do with the line breaks inside paragraphs. Hard breaks (two
+// trailing spaces or a backslash) are always kept.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineWrap {
+    #[default]
+    Preserve,
+    Unwrap,
+    Wrap,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FormatOptions {
+    #[serde(default)]
+    pub wrap: LineWrap,
+    // Column paragraphs are wrapped at with `LineWrap::Wrap`
+    #[serde(default = "default_line_width")]
+    pub line_width: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            wrap: LineWrap::default(),
+            line_width: default_line_width(),
+        }
+    }
+}
+
+fn default_line_width() -> usize {
+    80
+}
+
+// Narrowest a wrapped paragraph gets, however deeply it is nested
+const MIN_WRAP_WIDTH: usize = 20;
+
+// Canonical form: ATX headings, `-` bullets and consecutive ordered
+// numbers, `***` breaks, content indented to the marker, aligned tables,
+// fenced code, one blank line between blocks and link reference definitions
+// at the end.
+// Code, math and HTML blocks are copied as they are. Front matter is kept
+// verbatim and `definitions` is the source of every link reference
+// definition, in document order. `parser_options` is the syntax the blocks
+// were parsed with.
+pub fn format_document(
+    front_matter: &[&str],
+    blocks: &[GfmBlock],
+    definitions: &[String],
+    options: &FormatOptions,
+    parser_options: ParserOptions,
+) -> String {
+    let formatter = Formatter { options, parser_options };
+    let mut lines: Vec<String> = front_matter.iter().map(|line| line.to_string()).collect();
+
+    let body = formatter.blocks(blocks, options.line_width, false);
+    if !lines.is_empty() && !body.is_empty() {
+        lines.push(String::new());
+    }
+    lines.extend(body);
+
+    for definition in definitions {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines.extend(definition.lines().map(str::to_string));
+    }
+
+    let mut output = lines.join("\n");
+    output.push('\n');
+    output
+}
+
+struct Formatter<'a> {
+    options: &'a FormatOptions,
+    parser_options: ParserOptions,
+}
+
+impl Formatter<'_> {
+    // Blocks of one container. Tight list items have no blank lines between
+    // their blocks. Lists right after a list of the same kind switch marker,
+    // since the same marker would merge them into one list.
+    fn blocks(&self, blocks: &[GfmBlock], width: usize, tight: bool) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut previous_ordered = None;
+        let mut alternate = false;
+
+        for (index, block) in blocks.iter().enumerate() {
+            if index > 0 && !tight {
+                lines.push(String::new());
+            }
+
+            match &block.token {
+                GfmToken::List { items, ordered, start, tight } => {
+                    alternate = previous_ordered == Some(*ordered) && !alternate;
+                    previous_ordered = Some(*ordered);
+                    lines.extend(self.list(items, *ordered, *start, *tight, alternate, width));
+                }
+                token => {
+                    previous_ordered = None;
+                    lines.extend(self.block(token, width));
+                }
+            }
+        }
+
+        lines
+    }
+
+    fn block(&self, token: &GfmToken, width: usize) -> Vec<String> {
+        match token {
+            // Setext headings over several lines stay setext: joining the
+            // lines would drop hard breaks and change the heading's anchor
+            GfmToken::Heading { level, text } if text.contains('\n') => {
+                let mut lines = preserve_lines(text);
+                lines.push(if *level == 1 { "===" } else { "---" }.to_string());
+                lines
+            }
+            GfmToken::Heading { level, text } => {
+                let text = text.trim();
+                let marker = "#".repeat(*level as usize);
+                if text.is_empty() {
+                    vec![marker]
+                } else {
+                    vec![format!("{} {}", marker, text)]
+                }
+            }
+            GfmToken::Paragraph(text) => self.paragraph(text, width),
+            GfmToken::CodeBlock { info, code, .. } => {
+                // Backtick fences can't have backticks in their info string
+                let fence_char = if info.contains('`') { '~' } else { '`' };
+                let fence = fence_char.to_string().repeat(3.max(longest_run(code, fence_char) + 1));
+
+                let mut lines = vec![format!("{}{}", fence, info.trim())];
+                if let Some(code) = code.strip_suffix('\n') {
+                    lines.extend(code.split('\n').map(str::to_string));
+                } else if !code.is_empty() {
+                    lines.extend(code.split('\n').map(str::to_string));
+                }
+                lines.push(fence);
+                lines
+            }
+            GfmToken::Math(tex) => {
+                let mut lines = vec!["$$".to_string()];
+                lines.extend(tex.lines().map(str::to_string));
+                lines.push("$$".to_string());
+                lines
+            }
+            GfmToken::HtmlBlock(html) => html.trim_end_matches('\n').split('\n').map(str::to_string).collect(),
+            GfmToken::List { items, ordered, start, tight } => {
+                self.list(items, *ordered, *start, *tight, false, width)
+            }
+            GfmToken::Table { headers, rows, alignments } => format_table(headers, rows, alignments),
+            GfmToken::Blockquote(content) => quote(self.blocks(content, width.saturating_sub(2), false)),
+            GfmToken::Callout { kind, title, folded, content } => {
+                let mut header = format!("[!{}]", kind);
+                match folded {
+                    Some(true) => header.push('-'),
+                    Some(false) => header.push('+'),
+                    None => {}
+                }
+                if let Some(title) = title {
+                    header.push(' ');
+                    header.push_str(title);
+                }
+
+                // A paragraph right under the marker line belongs to the
+                // callout anyway; anything else is set apart
+                let mut lines = vec![header];
+                if !content.is_empty() && !matches!(content[0].token, GfmToken::Paragraph(_)) {
+                    lines.push(String::new());
+                }
+                lines.extend(self.blocks(content, width.saturating_sub(2), false));
+                quote(lines)
+            }
+            GfmToken::FootnoteDefinition { label, content } => {
+                let content = self.blocks(content, width.saturating_sub(4), false);
+                indent_under(&format!("[^{}]:", label), 4, content)
+            }
+            GfmToken::Embed(links) => links
+                .iter()
+                .map(|link| {
+                    let mut embed = format!("![[{}", link.target);
+                    if let Some(heading) = &link.heading {
+                        embed.push('#');
+                        embed.push_str(heading);
+                    }
+                    if let Some(alias) = &link.alias {
+                        embed.push('|');
+                        embed.push_str(alias);
+                    }
+                    embed.push_str("]]");
+                    embed
+                })
+                .collect(),
+            // `---` would underline a paragraph right above it, start front
+            // matter at the top of the note or read as a bullet after `- `
+            GfmToken::HorizontalRule => vec!["***".to_string()],
+        }
+    }
+
+    fn list(
+        &self,
+        items: &[GfmListItem],
+        ordered: bool,
+        start: u32,
+        tight: bool,
+        alternate: bool,
+        width: usize,
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 && !tight {
+                lines.push(String::new());
+            }
+
+            let marker = match (ordered, alternate) {
+                (true, false) => format!("{}.", start as usize + index),
+                (true, true) => format!("{})", start as usize + index),
+                (false, false) => "-".to_string(),
+                (false, true) => "*".to_string(),
+            };
+            let indent = marker.len() + 1;
+
+            let mut content = self.blocks(&item.content, width.saturating_sub(indent), tight);
+            if let Some(checked) = item.checked {
+                let task = if checked { "[x]" } else { "[ ]" };
+                match content.first_mut() {
+                    Some(first) if !first.is_empty() => *first = format!("{} {}", task, first),
+                    _ => content.insert(0, task.to_string()),
+                }
+            }
+            lines.extend(indent_under(&marker, indent, content));
+        }
+
+        lines
+    }
+
+    fn paragraph(&self, text: &str, width: usize) -> Vec<String> {
+        if self.options.wrap == LineWrap::Preserve {
+            return preserve_lines(text);
+        }
+
+        let lines: Vec<&str> = text.lines().map(str::trim_start).collect();
+        let in_code = code_span_line_ends(text);
+
+        // Lines between hard breaks are joined, then wrapped again
+        let mut segments = Vec::new();
+        let mut segment = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            if !segment.is_empty() {
+                segment.push(' ');
+            }
+            if has_hard_break(line) && index + 1 < lines.len() {
+                segment.push_str(line);
+                segments.push(std::mem::take(&mut segment));
+            } else if in_code[index] {
+                segment.push_str(line);
+            } else {
+                segment.push_str(line.trim_end());
+            }
+        }
+        if !segment.is_empty() {
+            segments.push(segment);
+        }
+
+        let wrapped: Vec<String> = if self.options.wrap == LineWrap::Unwrap {
+            segments.into_iter().map(escape_block_start).collect()
+        } else {
+            let width = width.max(MIN_WRAP_WIDTH);
+            segments
+                .iter()
+                .flat_map(|segment| wrap_segment(segment, width))
+                .map(escape_block_start)
+                .collect()
+        };
+
+        // Moving a line break can change how the paragraph parses: `<a b>`
+        // is a link destination only on one line, and a line can become a
+        // reference definition or an HTML tag once it ends elsewhere. Such
+        // paragraphs keep their lines.
+        let preserved = preserve_lines(text);
+        if wrapped == preserved || self.render(&wrapped.join("\n")) == self.render(&preserved.join("\n")) {
+            wrapped
+        } else {
+            preserved
+        }
+    }
+
+    // A paragraph rendered on its own, with line breaks read as spaces
+    fn render(&self, paragraph: &str) -> String {
+        let mut parser = GfmMarkdownParser::new();
+        parser.set_options(self.parser_options);
+        strip_source_lines(&parser.render_document(paragraph).html).replace('\n', " ")
+    }
+}
+
+// Lines of a paragraph or heading as written, without their indentation
+fn preserve_lines(text: &str) -> Vec<String> {
+    let in_code = code_span_line_ends(text);
+    text.lines()
+        .map(str::trim_start)
+        .zip(in_code)
+        .map(|(line, in_code)| {
+            if has_hard_break(line) || in_code {
+                line.to_string()
+            } else {
+                line.trim_end().to_string()
+            }
+        })
+        .map(escape_block_start)
+        .collect()
+}
+
+// Whether each line of a paragraph ends inside a code span, where the spaces
+// before the line break are part of the code
+fn code_span_line_ends(text: &str) -> Vec<bool> {
+    let bytes = text.as_bytes();
+    let run_length = |start: usize| bytes[start..].iter().take_while(|&&b| b == b'`').count();
+    let mut spans = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'\\' => pos += 2,
+            b'`' => {
+                // A run of backticks opens a span closed by a run as long
+                let run = run_length(pos);
+                let mut search = pos + run;
+                let mut close = None;
+                while let Some(offset) = text[search..].find('`') {
+                    let length = run_length(search + offset);
+                    if length == run {
+                        close = Some(search + offset);
+                        break;
+                    }
+                    search += offset + length;
+                }
+                match close {
+                    Some(close) => {
+                        spans.push(pos + run..close);
+                        pos = close + run;
+                    }
+                    None => pos += run,
+                }
+            }
+            _ => pos += 1,
+        }
+    }
+
+    let mut line_end = 0;
+    text.lines()
+        .map(|line| {
+            line_end += line.len();
+            let in_code = spans.iter().any(|span| span.contains(&line_end));
+            line_end += 1;
+            in_code
+        })
+        .collect()
+}
+
+// Escapes the marker of a paragraph line that would start a block where the
+// formatter puts it. A lazy `===` under a list item is text, but indented
+// into the item it underlines a heading; `3) d` after a reference definition
+// is text, but starts a list once the definition moves to the end.
+fn escape_block_start(line: String) -> String {
+    let bytes = line.as_bytes();
+    let space_after = |pos: usize| matches!(bytes.get(pos), None | Some(b' ' | b'\t'));
+    let marker = match bytes.first() {
+        Some(b'#') => {
+            let level = bytes.iter().take_while(|&&b| b == b'#').count();
+            (level <= 6 && space_after(level)).then_some(0)
+        }
+        Some(b'>') => Some(0),
+        Some(b'-' | b'+' | b'*') if space_after(1) => Some(0),
+        // Setext underlines and thematic breaks
+        Some(&first @ (b'=' | b'-')) if line.trim_end().bytes().all(|b| b == first) => Some(0),
+        Some(b'-' | b'*' | b'_') if is_thematic_break(&line) => Some(0),
+        Some(b'0'..=b'9') => {
+            let digits = bytes.iter().take_while(|b| b.is_ascii_digit()).count();
+            (digits <= 9 && matches!(bytes.get(digits), Some(b'.' | b')')) && space_after(digits + 1))
+                .then_some(digits)
+        }
+        _ => None,
+    };
+
+    match marker {
+        Some(pos) => format!("{}\\{}", &line[..pos], &line[pos..]),
+        None => line,
+    }
+}
+
+// Two trailing spaces or an unescaped trailing backslash
+fn has_hard_break(line: &str) -> bool {
+    line.ends_with("  ") || (line.len() - line.trim_end_matches('\\').len()) % 2 == 1
+}
+
+// Breaks a line of text at single spaces. A break is never put where it would
+// change the meaning: inside a run of spaces, after a backslash, or before a
+// word that would start another block.
+fn wrap_segment(segment: &str, width: usize) -> Vec<String> {
+    // The hard break is kept at the end of the last line
+    let (text, hard_break) = if segment.ends_with("  ") {
+        (segment.trim_end(), "  ")
+    } else {
+        (segment, "")
+    };
+
+    let words: Vec<&str> = text.split(' ').collect();
+    let mut lines = Vec::new();
+    let mut line = words[0].to_string();
+    let mut line_width = line.chars().count();
+
+    for pair in words.windows(2) {
+        let (previous, word) = (pair[0], pair[1]);
+        let word_width = word.chars().count();
+        let breakable = !word.is_empty()
+            && !previous.is_empty()
+            && !previous.ends_with('\\')
+            && !starts_block(word);
+
+        if breakable && line_width + 1 + word_width > width {
+            lines.push(std::mem::take(&mut line));
+            line_width = 0;
+        } else {
+            line.push(' ');
+            line_width += 1;
+        }
+        line.push_str(word);
+        line_width += word_width;
+    }
+
+    line.push_str(hard_break);
+    lines.push(line);
+    lines
+}
+
+// Words that could be read as a block marker at the start of a line
+fn starts_block(word: &str) -> bool {
+    if word.starts_with(['#', '>', '-', '+', '*', '=', '|', ':', '`', '~', '<', '$', '[']) {
+        return true;
+    }
+    let digits = word.bytes().take_while(u8::is_ascii_digit).count();
+    digits > 0 && matches!(word.as_bytes().get(digits), Some(b'.' | b')'))
+}
+
+// Puts `marker` before the first line and indents the rest to line up with it
+fn indent_under(marker: &str, indent: usize, content: Vec<String>) -> Vec<String> {
+    if content.is_empty() {
+        return vec![marker.to_string()];
+    }
+
+    let padding = " ".repeat(indent);
+    let separator = padding.get(marker.len()..).filter(|rest| !rest.is_empty()).unwrap_or(" ");
+    content
+        .into_iter()
+        .enumerate()
+        .map(|(index, line)| {
+            if index == 0 {
+                if line.is_empty() {
+                    marker.to_string()
+                } else {
+                    format!("{}{}{}", marker, separator, line)
+                }
+            } else if line.is_empty() {
+                line
+            } else {
+                format!("{}{}", padding, line)
+            }
+        })
+        .collect()
+}
+
+fn quote(lines: Vec<String>) -> Vec<String> {
+    // An empty quote is still a quote
+    if lines.is_empty() {
+        return vec![">".to_string()];
+    }
+    lines
+        .into_iter()
+        .map(|line| if line.is_empty() { ">".to_string() } else { format!("> {}", line) })
+        .collect()
+}
+
+fn format_table(headers: &[String], rows: &[Vec<String>], alignments: &[Alignment]) -> Vec<String> {
+    let escape = |cell: &String| cell.replace('|', "\\|");
+    let headers: Vec<String> = headers.iter().map(escape).collect();
+    let rows: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(escape).collect()).collect();
+
+    let mut widths = vec![3; alignments.len()];
+    for row in std::iter::once(&headers).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |row: &[String]| {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(index, &width)| {
+                let cell = row.get(index).map(String::as_str).unwrap_or("");
+                let padding = width - cell.chars().count();
+                match alignments.get(index) {
+                    Some(Alignment::Right) => format!("{}{}", " ".repeat(padding), cell),
+                    Some(Alignment::Center) => {
+                        format!("{}{}{}", " ".repeat(padding / 2), cell, " ".repeat(padding - padding / 2))
+                    }
+                    _ => format!("{}{}", cell, " ".repeat(padding)),
+                }
+            })
+            .collect();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let delimiters: Vec<String> = widths
+        .iter()
+        .zip(alignments)
+        .map(|(&width, alignment)| match alignment {
+            Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+            Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+            Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+            Alignment::None => "-".repeat(width),
+        })
+        .collect();
+
+    let mut lines = vec![format_row(&headers), format!("| {} |", delimiters.join(" | "))];
+    lines.extend(rows.iter().map(|row| format_row(row)));
+    lines
+}
+
+fn longest_run(text: &str, ch: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in text.chars() {
+        if c == ch {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::gfm_parser::{strip_source_lines, GfmMarkdownParser};
+
+    const DOCUMENTS: &[&str] = &[
+        // A lazy line under a list item or quote must not become an underline
+        "- a\n===",
+        "> a\n--",
+        "1. a\n---x",
+        // Text after a definition that moves to the end must stay text
+        "# H\n[r]: http://x \"t\"\n3) d",
+        "[r]: /url\n- not a list\n\n[r]",
+        "[r]: /url\n# not a heading\n\n[r]",
+        "[r]: /url\n> not a quote",
+        // Setext headings over several lines keep their breaks and anchor
+        "b  \n\tTabbed\n---",
+        "Two\nlines\n===",
+        // Markers inside lines are left alone
+        "text with #tag and 1. in the middle\n- item with *emphasis*\n+ other list",
+        "a  \n# b\n\nc\\\n- d",
+        include_str!("testdata/ast/blocks.md"),
+        include_str!("testdata/ast/extensions.md"),
+    ];
+
+    fn render(markdown: &str) -> String {
+        let html = strip_source_lines(&GfmMarkdownParser::new().render_document(markdown).html);
+        // Line breaks inside paragraphs render like spaces
+        html.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn formatting_keeps_the_rendered_output() {
+        let parser = GfmMarkdownParser::new();
+        for wrap in [LineWrap::Preserve, LineWrap::Unwrap, LineWrap::Wrap] {
+            let options = FormatOptions { wrap, line_width: 20 };
+            for markdown in DOCUMENTS {
+                let formatted = parser.format(markdown, &options);
+                assert_eq!(
+                    render(&formatted),
+                    render(markdown),
+                    "{:?} formatted with {:?} renders differently:\n{}",
+                    markdown,
+                    wrap,
+                    formatted
+                );
+                assert_eq!(parser.format(&formatted, &options), formatted, "formatting {:?} twice changes it", markdown);
+            }
+        }
+    }
+
+    #[test]
+    fn block_markers_are_escaped_only_at_line_start() {
+        assert_eq!(escape_block_start("3) d".to_string()), "3\\) d");
+        assert_eq!(escape_block_start("===".to_string()), "\\===");
+        assert_eq!(escape_block_start("## heading".to_string()), "\\## heading");
+        assert_eq!(escape_block_start("#tag".to_string()), "#tag");
+        assert_eq!(escape_block_start("2024 was a year".to_string()), "2024 was a year");
+        assert_eq!(escape_block_start("-1 degrees".to_string()), "-1 degrees");
+    }
+
+    fn format(markdown: &str, wrap: LineWrap) -> String {
+        GfmMarkdownParser::new().format(markdown, &FormatOptions { wrap, line_width: 20 })
+    }
+
+    #[test]
+    fn thematic_breaks_use_asterisks() {
+        // `---` would start front matter, underline `Foo` or become a bullet
+        assert_eq!(format("---\nFoo\n___\n- * * *", LineWrap::Preserve), "***\n\nFoo\n\n***\n\n- ***\n");
+    }
+
+    #[test]
+    fn code_spans_and_empty_quotes_are_kept() {
+        assert_eq!(format("``\nfoo \n``\n\n`a  \nb`", LineWrap::Preserve), "``\nfoo \n``\n\n`a  \nb`\n");
+        assert_eq!(format("`foo   bar \nbaz` and `done`", LineWrap::Unwrap), "`foo   bar  baz` and `done`\n");
+        assert_eq!(format(">\n\n[a]\n\n> [a]: /url", LineWrap::Preserve), ">\n\n[a]\n\n>\n\n[a]: /url\n");
+    }
+
+    #[test]
+    fn line_breaks_that_change_the_paragraph_stay() {
+        for wrap in [LineWrap::Unwrap, LineWrap::Wrap] {
+            // Only on one line is `<foo bar>` a link destination
+            assert_eq!(format("[link](<foo\nbar>) and more words", wrap), "[link](<foo\nbar>) and more words\n");
+            // A whole tag on a line of its own starts an HTML block
+            assert_eq!(format("<a b='c'\nd=\"e\" />", wrap), "<a b='c'\nd=\"e\" />\n");
+        }
+        // Broken after the title, the line would be a reference definition
+        assert_eq!(format("[foo]: /url \"title\" ok", LineWrap::Wrap), "[foo]: /url \"title\" ok\n");
+        assert_eq!(format("plain words\nthat join", LineWrap::Unwrap), "plain words that join\n");
+    }
+
+    #[test]
+    fn multi_line_setext_headings_stay_setext() {
+        let formatted = GfmMarkdownParser::new().format("b  \n\tTabbed\n---\n\nSingle\n===", &FormatOptions::default());
+        assert_eq!(formatted, "b  \nTabbed\n---\n\n# Single\n");
+    }
+}
@@ -0,0 +1,679 @@
+use serde_json::{Map, Number, Value};
+
+// YAML front matter at the top of a note, between `---` lines
+#[derive(Debug)]
+pub struct FrontMatter {
+    // Number of lines taken by the front matter, delimiters included
+    pub line_count: usize,
+    pub data: Result<Value, String>,
+}
+
+// Detects front matter: the first line is `---` and a later `---` or `...`
+// line closes it. Without the closing line the document has none.
+pub fn parse_front_matter(lines: &[&str]) -> Option<FrontMatter> {
+    if lines.first().map(|line| line.trim_end()) != Some("---") {
+        return None;
+    }
+
+    let close = lines
+        .iter()
+        .skip(1)
+        .position(|line| matches!(line.trim_end(), "---" | "..."))?
+        + 1;
+
+    Some(FrontMatter {
+        line_count: close + 1,
+        data: parse_yaml(&lines[1..close], 2),
+    })
+}
+
+// Parses the subset of YAML used in note properties: block mappings and
+// sequences, flow collections, quoted and plain scalars, and `|`/`>` block
+// scalars. Anchors, aliases and tags are not supported. Errors mention line
+// numbers counted from `first_line`.
+pub fn parse_yaml(lines: &[&str], first_line: usize) -> Result<Value, String> {
+    let mut parser = YamlParser {
+        lines: lines
+            .iter()
+            .map(|line| {
+                let content = line.trim_start_matches(' ');
+                (line.len() - content.len(), content.to_string())
+            })
+            .collect(),
+        pos: 0,
+        first_line,
+    };
+
+    let value = match parser.next_significant() {
+        Some(index) => parser.parse_node(parser.lines[index].0)?,
+        None => Value::Object(Map::new()),
+    };
+    if let Some(index) = parser.next_significant() {
+        return Err(format!("Unexpected content on line {}", parser.line_number(index)));
+    }
+    Ok(value)
+}
+
+struct YamlParser {
+    // (indentation, line without its indentation)
+    lines: Vec<(usize, String)>,
+    pos: usize,
+    first_line: usize,
+}
+
+impl YamlParser {
+    fn line_number(&self, index: usize) -> usize {
+        index + self.first_line
+    }
+
+    // Skips blank and comment lines, returning the next line with content
+    fn next_significant(&mut self) -> Option<usize> {
+        while self.pos < self.lines.len() {
+            let content = self.lines[self.pos].1.trim_end();
+            if content.is_empty() || content.starts_with('#') {
+                self.pos += 1;
+            } else {
+                return Some(self.pos);
+            }
+        }
+        None
+    }
+
+    fn parse_node(&mut self, indent: usize) -> Result<Value, String> {
+        let Some(index) = self.next_significant() else {
+            return Ok(Value::Null);
+        };
+        let (line_indent, content) = &self.lines[index];
+        if *line_indent < indent {
+            return Ok(Value::Null);
+        }
+
+        let line_indent = *line_indent;
+        if is_sequence_entry(content) {
+            self.parse_sequence(line_indent)
+        } else if split_key(content).is_some() {
+            self.parse_mapping(line_indent)
+        } else {
+            let content = content.clone();
+            self.pos += 1;
+            self.parse_value(&content, line_indent.saturating_sub(1))
+        }
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value, String> {
+        let mut map = Map::new();
+        while let Some(index) = self.next_significant() {
+            let (line_indent, content) = &self.lines[index];
+            if *line_indent < indent {
+                break;
+            }
+            if *line_indent > indent {
+                return Err(format!("Unexpected indentation on line {}", self.line_number(index)));
+            }
+
+            let Some((key, rest)) = split_key(content) else {
+                return Err(format!("Expected a `key: value` pair on line {}", self.line_number(index)));
+            };
+            if map.contains_key(&key) {
+                return Err(format!("Duplicate key `{}` on line {}", key, self.line_number(index)));
+            }
+            let rest = rest.to_string();
+            self.pos += 1;
+
+            let value = if is_empty_value(&rest) {
+                // A nested block, or a sequence that may sit at the key's own
+                // indentation
+                match self.next_significant() {
+                    Some(next) if self.lines[next].0 > indent => self.parse_node(indent + 1)?,
+                    Some(next) if self.lines[next].0 == indent && is_sequence_entry(&self.lines[next].1) => {
+                        self.parse_sequence(indent)?
+                    }
+                    _ => Value::Null,
+                }
+            } else {
+                self.parse_value(&rest, indent)?
+            };
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value, String> {
+        let mut items = Vec::new();
+        while let Some(index) = self.next_significant() {
+            let (line_indent, content) = &self.lines[index];
+            if *line_indent != indent || !is_sequence_entry(content) {
+                if *line_indent > indent {
+                    return Err(format!("Unexpected indentation on line {}", self.line_number(index)));
+                }
+                break;
+            }
+
+            let rest = content[1..].trim_start_matches(' ');
+            if is_empty_value(rest) {
+                self.pos += 1;
+                items.push(match self.next_significant() {
+                    Some(next) if self.lines[next].0 > indent => self.parse_node(indent + 1)?,
+                    _ => Value::Null,
+                });
+            } else if is_sequence_entry(rest) || split_key(rest).is_some() {
+                // `- key: value` and `- - item` open a block whose first line
+                // shares the dash's line; treat the rest as an indented line
+                let offset = content.len() - rest.len();
+                self.lines[index] = (indent + offset, rest.to_string());
+                items.push(self.parse_node(indent + offset)?);
+            } else {
+                let rest = rest.to_string();
+                self.pos += 1;
+                items.push(self.parse_value(&rest, indent)?);
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    // Parses the value after `key:` or `- `. Continuation lines have to be
+    // indented deeper than `parent_indent`.
+    fn parse_value(&mut self, text: &str, parent_indent: usize) -> Result<Value, String> {
+        // The value's line has already been consumed
+        let line = self.line_number(self.pos - 1);
+        let text = text.trim();
+
+        if text.starts_with('|') || text.starts_with('>') {
+            return Ok(Value::String(self.parse_block_scalar(text, parent_indent)?));
+        }
+
+        if text.starts_with('[') || text.starts_with('{') {
+            // Flow collections may span several lines
+            let mut source = text.to_string();
+            loop {
+                match FlowParser::new(&source).parse_document() {
+                    Ok(value) => return Ok(value),
+                    Err(FlowError::Eof) => match self.next_significant() {
+                        Some(next) if self.lines[next].0 > parent_indent => {
+                            source.push(' ');
+                            source.push_str(self.lines[next].1.trim());
+                            self.pos += 1;
+                        }
+                        _ => return Err(format!("Unterminated flow collection on line {}", line)),
+                    },
+                    Err(FlowError::Invalid(message)) => return Err(format!("{} on line {}", message, line)),
+                }
+            }
+        }
+
+        if text.starts_with('"') || text.starts_with('\'') {
+            let (value, consumed) = parse_quoted(text).ok_or_else(|| format!("Unterminated string on line {}", line))?;
+            let rest = text[consumed..].trim_start();
+            if !rest.is_empty() && !rest.starts_with('#') {
+                return Err(format!("Unexpected text after string on line {}", line));
+            }
+            return Ok(Value::String(value));
+        }
+
+        // Plain scalars continue on more indented lines, folded with spaces
+        let mut plain = strip_comment(text).to_string();
+        while let Some(next) = self.next_significant() {
+            let (next_indent, content) = &self.lines[next];
+            if *next_indent <= parent_indent || split_key(content).is_some() || is_sequence_entry(content) {
+                break;
+            }
+            plain.push(' ');
+            plain.push_str(strip_comment(content.trim()));
+            self.pos += 1;
+        }
+        Ok(resolve_plain(&plain))
+    }
+
+    fn parse_block_scalar(&mut self, header: &str, parent_indent: usize) -> Result<String, String> {
+        let folded = header.starts_with('>');
+        let header = strip_comment(&header[1..]);
+        let chomping = if header.contains('-') {
+            Chomping::Strip
+        } else if header.contains('+') {
+            Chomping::Keep
+        } else {
+            Chomping::Clip
+        };
+        let explicit_indent = header
+            .chars()
+            .find_map(|ch| ch.to_digit(10))
+            .map(|digit| parent_indent + digit as usize);
+
+        let mut block_indent = explicit_indent;
+        let mut lines = Vec::new();
+        while self.pos < self.lines.len() {
+            let (indent, content) = &self.lines[self.pos];
+            if content.trim().is_empty() {
+                lines.push(String::new());
+                self.pos += 1;
+                continue;
+            }
+            if *indent <= parent_indent || block_indent.is_some_and(|block_indent| *indent < block_indent) {
+                break;
+            }
+            let block_indent = *block_indent.get_or_insert(*indent);
+            lines.push(format!("{}{}", " ".repeat(indent - block_indent), content));
+            self.pos += 1;
+        }
+
+        // Trailing blank lines belong to the block only for chomping
+        let trailing = lines.iter().rev().take_while(|line| line.is_empty()).count();
+        // Blank lines before the next key were consumed but are not the block's
+        lines.truncate(lines.len() - trailing);
+
+        let mut text = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                let previous = &lines[index - 1];
+                let previous_folds = folded && !previous.is_empty() && !previous.starts_with(' ');
+                if !previous_folds || line.starts_with(' ') {
+                    text.push('\n');
+                } else if !line.is_empty() {
+                    text.push(' ');
+                }
+                // Otherwise the blank line stands for the line break
+            }
+            text.push_str(line);
+        }
+
+        match chomping {
+            Chomping::Strip => {}
+            Chomping::Clip if !text.is_empty() => text.push('\n'),
+            Chomping::Clip => {}
+            Chomping::Keep => {
+                text.push('\n');
+                text.push_str(&"\n".repeat(trailing));
+            }
+        }
+        Ok(text)
+    }
+}
+
+enum Chomping {
+    Clip,
+    Strip,
+    Keep,
+}
+
+enum FlowError {
+    // Ran out of input inside a collection, which may continue on the next line
+    Eof,
+    Invalid(String),
+}
+
+// Parser for `[a, b]` and `{key: value}` collections
+struct FlowParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> FlowParser<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn parse_document(&mut self) -> Result<Value, FlowError> {
+        let value = self.parse_value()?;
+        self.skip_spaces();
+        let rest = &self.text[self.pos..];
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(FlowError::Invalid("Unexpected text after flow collection".to_string()));
+        }
+        Ok(value)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.text[self.pos..].starts_with([' ', '\t']) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn parse_value(&mut self) -> Result<Value, FlowError> {
+        self.skip_spaces();
+        match self.peek() {
+            None => Err(FlowError::Eof),
+            Some('[') => self.parse_sequence(),
+            Some('{') => self.parse_mapping(),
+            Some('"' | '\'') => {
+                let (value, consumed) = parse_quoted(&self.text[self.pos..]).ok_or(FlowError::Eof)?;
+                self.pos += consumed;
+                Ok(Value::String(value))
+            }
+            Some(_) => {
+                let start = self.pos;
+                while let Some(ch) = self.peek() {
+                    // `:` ends a key before a space or the end of the collection
+                    let next = self.text[self.pos + 1..].chars().next();
+                    let ends_key = ch == ':' && matches!(next, None | Some(' ' | '\t' | ',' | ']' | '}'));
+                    if matches!(ch, ',' | ']' | '}') || ends_key {
+                        break;
+                    }
+                    self.pos += ch.len_utf8();
+                }
+                Ok(resolve_plain(self.text[start..self.pos].trim()))
+            }
+        }
+    }
+
+    fn parse_sequence(&mut self) -> Result<Value, FlowError> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None => return Err(FlowError::Eof),
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => {}
+            }
+            items.push(self.parse_value()?);
+            if !self.parse_separator(']')? {
+                self.pos += 1;
+                return Ok(Value::Array(items));
+            }
+        }
+    }
+
+    fn parse_mapping(&mut self) -> Result<Value, FlowError> {
+        self.pos += 1;
+        let mut map = Map::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None => return Err(FlowError::Eof),
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Value::Object(map));
+                }
+                _ => {}
+            }
+
+            let key = match self.parse_value()? {
+                Value::String(key) => key,
+                Value::Null => String::new(),
+                other => other.to_string(),
+            };
+            if map.contains_key(&key) {
+                return Err(FlowError::Invalid(format!("Duplicate key `{}`", key)));
+            }
+            self.skip_spaces();
+            let value = if self.peek() == Some(':') {
+                self.pos += 1;
+                match self.parse_value()? {
+                    Value::String(text) if text.is_empty() => Value::Null,
+                    value => value,
+                }
+            } else {
+                Value::Null
+            };
+            map.insert(key, value);
+
+            if !self.parse_separator('}')? {
+                self.pos += 1;
+                return Ok(Value::Object(map));
+            }
+        }
+    }
+
+    // Consumes a `,` and returns true, or returns false at `close`
+    fn parse_separator(&mut self, close: char) -> Result<bool, FlowError> {
+        self.skip_spaces();
+        match self.peek() {
+            None => Err(FlowError::Eof),
+            Some(',') => {
+                self.pos += 1;
+                Ok(true)
+            }
+            Some(ch) if ch == close => Ok(false),
+            Some(ch) => Err(FlowError::Invalid(format!("Unexpected `{}` in flow collection", ch))),
+        }
+    }
+}
+
+fn is_sequence_entry(content: &str) -> bool {
+    content == "-" || content.starts_with("- ") || content.starts_with("-\t")
+}
+
+fn is_empty_value(text: &str) -> bool {
+    let text = text.trim();
+    text.is_empty() || text.starts_with('#')
+}
+
+// Splits `key: value` into the key and the text after the colon
+fn split_key(content: &str) -> Option<(String, &str)> {
+    if content.starts_with(['"', '\'']) {
+        let (key, consumed) = parse_quoted(content)?;
+        let rest = content[consumed..].trim_start_matches(' ');
+        let rest = rest.strip_prefix(':')?;
+        return (rest.is_empty() || rest.starts_with([' ', '\t'])).then_some((key, rest));
+    }
+
+    if content.starts_with(['[', '{', '#', '|', '>', '-']) && !content.starts_with("-:") {
+        return None;
+    }
+
+    let bytes = content.as_bytes();
+    for (index, &byte) in bytes.iter().enumerate() {
+        match byte {
+            b'#' if index > 0 && bytes[index - 1] == b' ' => return None,
+            b':' if matches!(bytes.get(index + 1), None | Some(b' ' | b'\t')) => {
+                let key = content[..index].trim_end();
+                return Some((key.to_string(), &content[index + 1..]));
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Returns the unquoted string and the number of bytes the quoted form took
+fn parse_quoted(text: &str) -> Option<(String, usize)> {
+    let quote = text.chars().next()?;
+    let mut value = String::new();
+    let mut chars = text.char_indices().skip(1);
+
+    while let Some((index, ch)) = chars.next() {
+        match ch {
+            '\'' if quote == '\'' => {
+                if text[index + 1..].starts_with('\'') {
+                    chars.next();
+                    value.push('\'');
+                } else {
+                    return Some((value, index + 1));
+                }
+            }
+            '"' if quote == '"' => return Some((value, index + 1)),
+            '\\' if quote == '"' => {
+                let (_, escape) = chars.next()?;
+                match escape {
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'r' => value.push('\r'),
+                    '0' => value.push('\0'),
+                    'x' | 'u' | 'U' => {
+                        let digits = match escape {
+                            'x' => 2,
+                            'u' => 4,
+                            _ => 8,
+                        };
+                        let hex: String = (0..digits).filter_map(|_| chars.next().map(|(_, ch)| ch)).collect();
+                        let code = u32::from_str_radix(&hex, 16).ok()?;
+                        value.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    other => value.push(other),
+                }
+            }
+            _ => value.push(ch),
+        }
+    }
+    None
+}
+
+fn strip_comment(text: &str) -> &str {
+    match text.find(" #").or_else(|| text.find("\t#")) {
+        Some(index) => text[..index].trim_end(),
+        None => text.trim_end(),
+    }
+}
+
+// Resolves a plain scalar to null, a boolean, a number or a string. Dates
+// stay strings, since JSON has no date type, and so do `.inf` and `.nan`.
+fn resolve_plain(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        _ => {}
+    }
+
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+    if digits.starts_with(|ch: char| ch.is_ascii_digit() || ch == '.') {
+        let radix = if let Some(hex) = digits.strip_prefix("0x") {
+            Some((hex, 16))
+        } else {
+            digits.strip_prefix("0o").map(|octal| (octal, 8))
+        };
+        if let Some((digits, radix)) = radix {
+            if let Ok(number) = i64::from_str_radix(digits, radix) {
+                return Value::Number(Number::from(if text.starts_with('-') { -number } else { number }));
+            }
+        } else if digits.bytes().all(|byte| byte.is_ascii_digit()) {
+            if let Ok(number) = text.parse::<i64>() {
+                return Value::Number(Number::from(number));
+            }
+        } else if digits.bytes().all(|byte| byte.is_ascii_digit() || matches!(byte, b'.' | b'e' | b'E' | b'-' | b'+')) {
+            if let Some(number) = text.parse::<f64>().ok().and_then(Number::from_f64) {
+                return Value::Number(number);
+            }
+        }
+    }
+
+    Value::String(text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn yaml(text: &str) -> Result<Value, String> {
+        let lines: Vec<&str> = text.split('\n').collect();
+        parse_yaml(&lines, 2)
+    }
+
+    #[test]
+    fn quoted_scalars() {
+        assert_eq!(
+            yaml(r##"a: 'it''s \n'
+b: "tab\there \"q\" \u00e9 \x41 \\"
+"c d": "# not a comment" # but this is"##),
+            Ok(json!({"a": "it's \\n", "b": "tab\there \"q\" é A \\", "c d": "# not a comment"}))
+        );
+        assert_eq!(yaml("a: 'x' y"), Err("Unexpected text after string on line 2".to_string()));
+    }
+
+    #[test]
+    fn block_scalars() {
+        let text = "lit: |\n  one\n    two\n\nclip: |\n  x\n\n\nkeep: |+\n  x\n\n\nstrip: |-\n  y\n\n\
+                    fold: >\n  a\n  b\n\n  c\n    d\nindented: |2\n    four\nend: 1";
+        assert_eq!(
+            yaml(text),
+            Ok(json!({
+                "lit": "one\n  two\n",
+                "clip": "x\n",
+                "keep": "x\n\n\n",
+                "strip": "y",
+                "fold": "a b\nc\n  d\n",
+                "indented": "  four\n",
+                "end": 1,
+            }))
+        );
+        assert_eq!(yaml("k: >-\n    folded\n    lines\n"), Ok(json!({"k": "folded lines"})));
+    }
+
+    #[test]
+    fn flow_collections() {
+        assert_eq!(
+            yaml("list: [a, \"b, c\", 1, [x]]\nmap: {k: v, n: 2, e: {}, empty:}\nlong: [1,\n  2]"),
+            Ok(json!({
+                "list": ["a", "b, c", 1, ["x"]],
+                "map": {"k": "v", "n": 2, "e": {}, "empty": null},
+                "long": [1, 2],
+            }))
+        );
+        assert_eq!(yaml("a: [1, 2"), Err("Unterminated flow collection on line 2".to_string()));
+        assert_eq!(yaml("k: {a: [1, 2}"), Err("Unexpected `}` in flow collection on line 2".to_string()));
+    }
+
+    #[test]
+    fn block_collections_and_comments() {
+        assert_eq!(
+            yaml("# top\ntags:\n- a # first\n-   b\nnested:\n  - x: 1\n    y: two words\n  # between\n  - [z]\nc: x#y"),
+            Ok(json!({"tags": ["a", "b"], "nested": [{"x": 1, "y": "two words"}, ["z"]], "c": "x#y"}))
+        );
+        assert_eq!(yaml("- a\n- b"), Ok(json!(["a", "b"])));
+        assert_eq!(yaml("# only comments\n\n"), Ok(json!({})));
+    }
+
+    #[test]
+    fn plain_scalar_types() {
+        assert_eq!(
+            yaml("ints: [1, -2, +3, 0x1F, -0x10, 0o17]\nfloats: [1.5, -0.25, 1e3, 2.5E-1]\n\
+                  special: [.inf, -.inf, .nan, 1_000, 1.2.3, 2024-01-31]\n\
+                  bools: [true, False, TRUE, yes, no]\nnulls: [null, ~, Null, NULL]\nempty:"),
+            Ok(json!({
+                "ints": [1, -2, 3, 31, -16, 15],
+                "floats": [1.5, -0.25, 1000.0, 0.25],
+                // JSON has no infinities, and YAML 1.2 has no underscores or yes/no
+                "special": [".inf", "-.inf", ".nan", "1_000", "1.2.3", "2024-01-31"],
+                "bools": [true, false, true, "yes", "no"],
+                "nulls": [null, null, null, null],
+                "empty": null,
+            }))
+        );
+    }
+
+    #[test]
+    fn duplicate_keys_are_errors() {
+        assert_eq!(yaml("a: 1\nb: 2\na: 3"), Err("Duplicate key `a` on line 4".to_string()));
+        assert_eq!(yaml("m: {a: 1, a: 2}"), Err("Duplicate key `a` on line 2".to_string()));
+        // The same key in different mappings is fine
+        assert_eq!(yaml("a: {a: 1}\nb:\n  a: 2"), Ok(json!({"a": {"a": 1}, "b": {"a": 2}})));
+    }
+
+    #[test]
+    fn invalid_yaml_is_an_error() {
+        assert_eq!(yaml("a: \"open"), Err("Unterminated string on line 2".to_string()));
+        assert_eq!(yaml("key: value\n  extra: bad"), Err("Unexpected indentation on line 3".to_string()));
+        assert_eq!(yaml("  bad\nindent: x"), Err("Unexpected content on line 3".to_string()));
+        assert!(yaml("a: \"\\x4\"").is_err());
+        assert!(yaml("- a\nb: c").is_err());
+    }
+
+    #[test]
+    fn front_matter_needs_both_delimiters() {
+        let front_matter = parse_front_matter(&["---", "title: Note", "...", "body"]).unwrap();
+        assert_eq!(front_matter.line_count, 3);
+        assert_eq!(front_matter.data, Ok(json!({"title": "Note"})));
+        assert!(parse_front_matter(&["---", "title: Note", "", "body"]).is_none());
+        assert!(parse_front_matter(&["text", "---", "a: 1", "---"]).is_none());
+        assert_eq!(parse_front_matter(&["---", "---"]).unwrap().data, Ok(json!({})));
+    }
+
+    #[test]
+    fn broken_front_matter_is_left_out_of_the_result() {
+        use crate::markdown::gfm_parser::GfmMarkdownParser;
+        let mut parser = GfmMarkdownParser::new();
+        let result = parser.render_document("---\ntitle: [unclosed\n---\n# Body");
+        assert_eq!(result.front_matter, None);
+        assert!(result.html.starts_with("<h1"), "{}", result.html);
+        let result = parser.render_document("---\ntitle: Note\n---\n# Body");
+        assert_eq!(result.front_matter, Some(json!({"title": "Note"})));
+    }
+}
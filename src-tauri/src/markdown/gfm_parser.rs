@@ -1,24 +1,61 @@
-use std::collections::{VecDeque, HashMap};
+use std::collections::{VecDeque, HashMap, HashSet};
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use crate::markdown::ast::{convert_blocks, MarkdownAst, AST_VERSION};
+use crate::markdown::formatter::{format_document, FormatOptions};
+use crate::markdown::front_matter::{parse_front_matter, FrontMatter};
+use crate::markdown::highlight::highlight;
+use crate::markdown::inline::{
+    self, escape_html, footnote_reference_id, normalize_label, slugify, unescape_string, Inline, InlineParser,
+    LinkReference,
+};
+use crate::markdown::lint::{BlockLint, Diagnostic, LintConfig, Linter, Severity};
+use crate::markdown::math::render_math;
+use crate::markdown::parser::{
+    CalloutType, HtmlBlock, ParseResult, ParserOptions, SourceMapEntry, TextEdit, TocEntry,
+};
+use crate::markdown::sanitize::sanitize_html;
+use crate::markdown::stats::DocumentStats;
+use crate::markdown::wikilinks::{NoteIndex, WikiLink};
 
 #[derive(Debug, Clone)]
 pub enum GfmToken {
     Heading { level: u8, text: String },
     Paragraph(String),
-    CodeBlock { language: Option<String>, code: String },
-    List { items: Vec<GfmListItem>, ordered: bool },
+    // `info` is the whole info string, `language` its first word
+    CodeBlock { language: Option<String>, info: String, code: String },
+    Math(String),
+    HtmlBlock(String),
+    List { items: Vec<GfmListItem>, ordered: bool, start: u32, tight: bool },
     Table { headers: Vec<String>, rows: Vec<Vec<String>>, alignments: Vec<Alignment> },
-    Blockquote(String),
-    Alert { alert_type: AlertType, content: String },
+    Blockquote(Vec<GfmBlock>),
+    // `> [!type]` quotes. `title` is inline markdown, `folded` is set for
+    // foldable callouts (`-` folded, `+` open).
+    Callout { kind: String, title: Option<String>, folded: Option<bool>, content: Vec<GfmBlock> },
+    FootnoteDefinition { label: String, content: Vec<GfmBlock> },
+    // A paragraph made only of `![[...]]` lines, one embed per line
+    Embed(Vec<WikiLink>),
     HorizontalRule,
 }
 
+// A token together with the source lines (1-based, inclusive) it came from
+#[derive(Debug, Clone)]
+pub struct GfmBlock {
+    pub token: GfmToken,
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct GfmListItem {
-    pub content: String,
-    pub level: u8,
+    pub content: Vec<GfmBlock>,
     pub checked: Option<bool>,
+    pub start_line: usize,
+    pub end_line: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -29,18 +66,117 @@ pub enum Alignment {
     None,
 }
 
-#[derive(Debug, Clone)]
-pub enum AlertType {
-    Note,
-    Tip,
-    Important,
-    Warning,
-    Caution,
-}
-
 pub struct GfmMarkdownParser {
+    options: ParserOptions,
     buffer_pool: VecDeque<String>,
-    html_cache: HashMap<u64, String>,
+    // Inline HTML with the statistics of its plain text
+    html_cache: HashMap<u64, (String, DocumentStats)>,
+    block_cache: HashMap<u64, RenderedBlock>,
+    // Lint results of the top-level blocks, keyed like `block_cache`
+    lint_cache: HashMap<u64, BlockLint>,
+    footnotes: Footnotes,
+    // Link reference definitions of the document being rendered
+    references: HashMap<String, LinkReference>,
+    // Top-level blocks of the stored document, parsed
+    stored_blocks: Vec<StoredBlock>,
+    // How many lines of the stored document are front matter
+    front_matter_lines: usize,
+    // Notes read for `![[...]]` since the last block was collected, with
+    // when they were last modified
+    embedded_files: Vec<(String, Option<SystemTime>)>,
+    // Notes of the open workspace, for resolving `[[...]]` links
+    notes: Arc<NoteIndex>,
+    // Notes being embedded around the document this parser renders, outermost
+    // first, so `![[...]]` can stop at cycles
+    embed_stack: Vec<String>,
+    // Tags found by `process_inline_formatting` since the last block was
+    // collected
+    tags: Vec<String>,
+    // Statistics of the text processed since the last block was collected
+    stats: DocumentStats,
+    // User-defined callout types by lowercased name
+    callout_types: HashMap<String, CalloutType>,
+    lint: LintConfig,
+    // Where the stored document is saved, for checking its relative links
+    document_path: Option<PathBuf>,
+    // Whether renders return `HtmlBlock`s instead of a single HTML string
+    block_output: bool,
+    // Bumped whenever the caches are dropped because output may change
+    // without the source changing, so block keys change with it
+    generation: u64,
+    document: String,
+    version: u64,
+}
+
+// Footnotes of the document being rendered. References are numbered in the
+// order they first appear, like GitHub does.
+#[derive(Default)]
+struct Footnotes {
+    // Normalized labels of every footnote definition in the document
+    defined: HashSet<String>,
+    // Referenced labels in order of first reference, with how many times each
+    // has been referenced so far
+    referenced: Vec<(String, usize)>,
+    // Every reference made, in order, so cached blocks can replay theirs
+    sequence: Vec<String>,
+}
+
+impl Footnotes {
+    // Records a reference and returns the footnote number and which
+    // reference to it this is.
+    fn reference(&mut self, label: &str) -> (usize, usize) {
+        self.sequence.push(label.to_string());
+        match self.referenced.iter().position(|(referenced, _)| referenced == label) {
+            Some(position) => {
+                self.referenced[position].1 += 1;
+                (position + 1, self.referenced[position].1)
+            }
+            None => {
+                self.referenced.push((label.to_string(), 1));
+                (self.referenced.len(), 1)
+            }
+        }
+    }
+
+    fn resolve(&mut self, inlines: &mut [Inline]) {
+        for inline in inlines {
+            match inline {
+                Inline::FootnoteReference { label, number, index } => {
+                    (*number, *index) = self.reference(label);
+                }
+                Inline::Emphasis(children)
+                | Inline::Strong(children)
+                | Inline::Strikethrough(children)
+                | Inline::Link { children, .. }
+                | Inline::Image { children, .. } => self.resolve(children),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Rendered output of one top-level block, keyed by the hash of its source
+// lines. `start_line` is where the block was when it was rendered, so a cached
+// block that has moved only needs its line numbers shifted.
+#[derive(Default, Clone)]
+struct RenderedBlock {
+    html: String,
+    stats: DocumentStats,
+    source_map: Vec<SourceMapEntry>,
+    toc: Vec<TocEntry>,
+    footnote_references: Vec<String>,
+    tags: Vec<String>,
+    // Notes embedded in the block, which can change without the block
+    // changing, with when they were last modified
+    embedded_files: Vec<(String, Option<SystemTime>)>,
+    start_line: usize,
+}
+
+impl RenderedBlock {
+    // Whether none of the notes it embeds changed since it was rendered
+    fn embeds_unchanged(&self) -> bool {
+        self.embedded_files.iter().all(|(path, modified)| modified_time(path) == *modified)
+    }
 }
 
 impl Default for GfmMarkdownParser {
@@ -52,14 +188,33 @@ impl Default for GfmMarkdownParser {
 impl GfmMarkdownParser {
     pub fn new() -> Self {
         let mut buffer_pool = VecDeque::with_capacity(8);
-        
+
         for _ in 0..6 {
             buffer_pool.push_back(String::with_capacity(1024));
         }
-        
-        Self { 
+
+        Self {
+            options: ParserOptions::default(),
             buffer_pool,
             html_cache: HashMap::with_capacity(64),
+            block_cache: HashMap::with_capacity(64),
+            lint_cache: HashMap::with_capacity(64),
+            footnotes: Footnotes::default(),
+            references: HashMap::new(),
+            stored_blocks: Vec::new(),
+            front_matter_lines: 0,
+            embedded_files: Vec::new(),
+            notes: Arc::default(),
+            embed_stack: Vec::new(),
+            tags: Vec::new(),
+            stats: DocumentStats::default(),
+            callout_types: HashMap::new(),
+            lint: LintConfig::default(),
+            document_path: None,
+            block_output: false,
+            generation: 0,
+            document: String::new(),
+            version: 0,
         }
     }
 
@@ -84,465 +239,729 @@ impl GfmMarkdownParser {
         hasher.finish()
     }
 
-    pub fn parse(&mut self, markdown: &str) -> Vec<GfmToken> {
-        if markdown.is_empty() {
-            return Vec::new();
+    // Replaces the workspace notes wikilinks resolve against. Cached output
+    // may link to notes that no longer exist, so it is dropped.
+    pub fn set_workspace(&mut self, root: &str, paths: &[String]) {
+        self.notes = Arc::new(NoteIndex::new(root, paths));
+        self.clear_caches();
+    }
+
+    // Block tree of a document with source lines, for features that need its
+    // structure rather than HTML. Leaves the stored document alone.
+    pub fn parse_ast(&self, markdown: &str) -> MarkdownAst {
+        let mut lines = split_lines(markdown);
+        let front_matter = self.front_matter(&lines);
+        if let Some(front_matter) = &front_matter {
+            lines[..front_matter.line_count].fill("");
         }
+        let block_parser = parse_blocks(&lines, self.options);
 
-        // Optimized cache management
-        if self.html_cache.len() > 512 {
-            let target_size = self.html_cache.len() / 3;
-            let keys_to_remove: Vec<_> = self.html_cache.keys().take(target_size).copied().collect();
-            for key in keys_to_remove {
-                self.html_cache.remove(&key);
-            }
+        MarkdownAst {
+            version: AST_VERSION,
+            front_matter: front_matter
+                .and_then(|front_matter| front_matter.data.ok())
+                .filter(|data| data.is_object()),
+            children: convert_blocks(self.convert_children(&block_parser.blocks, DOCUMENT)),
         }
+    }
 
-        let mut tokens = Vec::with_capacity(markdown.len() / 50);
-        let lines: Vec<&str> = markdown.lines().collect();
-        let mut i = 0;
+    // Re-emits a document in canonical form, see `formatter`
+    pub fn format(&self, markdown: &str, options: &FormatOptions) -> String {
+        let mut lines = split_lines(markdown);
+        let front_matter_lines = match self.front_matter(&lines) {
+            Some(front_matter) => lines[..front_matter.line_count].to_vec(),
+            None => Vec::new(),
+        };
+        lines[..front_matter_lines.len()].fill("");
+        let block_parser = parse_blocks(&lines, self.options);
+        let blocks = self.convert_children(&block_parser.blocks, DOCUMENT);
+        format_document(&front_matter_lines, &blocks, &block_parser.definitions, options, self.options)
+    }
 
-        while i < lines.len() {
-            let line = lines[i].trim_end();
-            
-            if line.trim().is_empty() {
-                i += 1;
-                continue;
-            }
+    // Changes which syntax is recognized. Everything cached was parsed with
+    // the previous options, so it is dropped.
+    pub fn set_options(&mut self, options: ParserOptions) {
+        if options != self.options {
+            self.options = options;
+            self.clear_caches();
+        }
+    }
 
-            if line.starts_with("```") {
-                let (code_token, consumed) = self.parse_code_block(&lines, i);
-                tokens.push(code_token);
-                i += consumed;
-                continue;
-            }
+    // Replaces the user-defined callout types. They change how cached
+    // callouts look, so cached output is dropped.
+    pub fn set_callout_types(&mut self, types: Vec<CalloutType>) {
+        self.callout_types = types
+            .into_iter()
+            .map(|callout| (callout.name.to_lowercase(), callout))
+            .collect();
+        self.clear_caches();
+    }
 
-            if self.is_potential_table_line(line) && i + 1 < lines.len() {
-                if let Some((table_token, consumed)) = self.parse_table(&lines, i) {
-                    tokens.push(table_token);
-                    i += consumed;
-                    continue;
-                }
-            }
+    // Renders return the output block by block from now on, see `HtmlBlock`
+    pub fn set_block_output(&mut self, enabled: bool) {
+        self.block_output = enabled;
+    }
 
-            if let Some(token) = self.parse_heading(line) {
-                tokens.push(token);
-                i += 1;
-                continue;
-            }
+    fn clear_caches(&mut self) {
+        self.html_cache.clear();
+        self.block_cache.clear();
+        self.lint_cache.clear();
+        self.stored_blocks.clear();
+        self.generation += 1;
+    }
 
-            if self.is_horizontal_rule(line) {
-                tokens.push(GfmToken::HorizontalRule);
-                i += 1;
-                continue;
-            }
+    // Rules checked on every render from now on
+    pub fn set_lint_config(&mut self, config: LintConfig) {
+        self.lint = config;
+        self.lint_cache.clear();
+    }
 
-            if self.is_list_line(line) {
-                let (list_token, consumed) = self.parse_list(&lines, i);
-                tokens.push(list_token);
-                i += consumed;
-                continue;
-            }
+    // Path of the note the next documents come from; `None` for unsaved ones
+    pub fn set_document_path(&mut self, path: Option<String>) {
+        let path = path.map(PathBuf::from);
+        // Relative links are checked against the note's folder, and embeds
+        // of the note itself are cycles
+        if path != self.document_path {
+            self.clear_caches();
+        }
+        self.document_path = path;
+    }
 
-            if line.trim_start().starts_with("> ") {
-                if let Some((alert_token, consumed)) = self.parse_alert(&lines, i) {
-                    tokens.push(alert_token);
-                    i += consumed;
-                    continue;
-                }
-                
-                let text = &line.trim_start()[2..];
-                let processed_text = self.process_inline_formatting(text);
-                tokens.push(GfmToken::Blockquote(processed_text));
-                i += 1;
-                continue;
-            }
+    // Replaces the stored document and renders it, reusing the output of every
+    // top-level block whose source is unchanged since the previous render.
+    pub fn render_document(&mut self, markdown: &str) -> ParseResult {
+        let previous = std::mem::replace(&mut self.document, markdown.to_string());
+        self.version += 1;
+        self.render_stored_document(&previous)
+    }
+
+    // Applies a line edit to the document stored by the last render and
+    // re-renders it. Fails when the caller's version is out of date, in which
+    // case the whole document has to be sent again through `render_document`.
+    pub fn apply_edit(&mut self, previous_version: u64, edit: &TextEdit) -> Result<ParseResult, String> {
+        if previous_version != self.version {
+            return Err(format!(
+                "Document version mismatch: expected {}, got {}",
+                self.version, previous_version
+            ));
+        }
 
-            let processed_text = self.process_inline_formatting(line);
-            tokens.push(GfmToken::Paragraph(processed_text));
-            i += 1;
+        let mut lines: Vec<&str> = self.document.split('\n').collect();
+        if edit.start_line > edit.end_line || edit.end_line > lines.len() {
+            return Err(format!(
+                "Edit range {}..{} is outside the document ({} lines)",
+                edit.start_line,
+                edit.end_line,
+                lines.len()
+            ));
         }
 
-        tokens
+        lines.splice(edit.start_line..edit.end_line, edit.lines.iter().map(String::as_str));
+        let document = lines.join("\n");
+        let previous = std::mem::replace(&mut self.document, document);
+        self.version += 1;
+        Ok(self.render_stored_document(&previous))
     }
 
-    #[inline]
-    fn is_list_line(&self, line: &str) -> bool {
-        let trimmed = line.trim_start();
-        
-        // Quick length check
-        if trimmed.len() < 2 {
-            return false;
-        }
-        
-        // Unordered lists - direct byte comparison
-        let bytes = trimmed.as_bytes();
-        if matches!(bytes[0], b'-' | b'*' | b'+') && bytes[1] == b' ' {
-            return true;
+    // Renders the stored document, given the one rendered before it. Only the
+    // blocks around the lines that changed are parsed again, and only blocks
+    // whose source changed are rendered again.
+    fn render_stored_document(&mut self, previous: &str) -> ParseResult {
+        self.trim_html_cache();
+        self.embedded_files.clear();
+
+        let document = std::mem::take(&mut self.document);
+        let mut lines = split_lines(&document);
+        // Front matter is blanked out rather than removed so line numbers
+        // still match the editor
+        let front_matter = self.front_matter(&lines);
+        if let Some(front_matter) = &front_matter {
+            lines[..front_matter.line_count].fill("");
         }
-        
-        // Ordered lists - optimized
-        if let Some(dot_pos) = trimmed.find(". ") {
-            if dot_pos > 0 && dot_pos <= 4 {
-                return bytes[..dot_pos].iter().all(|&b| b.is_ascii_digit());
+        let mut previous_lines = split_lines(previous);
+        let previous_front_matter = self.front_matter_lines.min(previous_lines.len());
+        previous_lines[..previous_front_matter].fill("");
+        self.front_matter_lines = front_matter.as_ref().map_or(0, |front_matter| front_matter.line_count);
+        let stored = reparse(std::mem::take(&mut self.stored_blocks), &previous_lines, &lines, self.options);
+
+        // Cached inline HTML may use definitions that have changed since.
+        // The first definition of each label wins.
+        let mut references = HashMap::new();
+        for block in &stored {
+            for (label, reference) in &block.references {
+                references.entry(label.clone()).or_insert_with(|| reference.clone());
             }
         }
-        
-        false
-    }
+        if references != self.references {
+            self.html_cache.clear();
+            self.references = references;
+        }
+        let references_hash = {
+            let mut sorted: Vec<_> = self.references.iter().collect();
+            sorted.sort_by(|a, b| a.0.cmp(b.0));
+            let mut hasher = DefaultHasher::new();
+            sorted.hash(&mut hasher);
+            hasher.finish()
+        };
 
-    #[inline]
-    fn get_list_indent_level(&self, line: &str) -> u8 {
-        let mut level = 0u8;
-        for byte in line.bytes() {
-            match byte {
-                b' ' => level = level.saturating_add(1),
-                b'\t' => level = level.saturating_add(4),
-                _ => break,
+        // First definition of each label wins, by top-level block and block
+        // inside it
+        let mut definitions = HashMap::new();
+        for (position, block) in stored.iter().enumerate() {
+            for (label, index) in &block.footnote_definitions {
+                definitions.entry(label.clone()).or_insert((position, *index));
             }
         }
-        (level / 2).min(20)
-    }
+        self.footnotes = Footnotes {
+            defined: definitions.keys().cloned().collect(),
+            ..Footnotes::default()
+        };
+        let mut defined_labels: Vec<&String> = definitions.keys().collect();
+        defined_labels.sort();
 
-    fn parse_list(&mut self, lines: &[&str], start: usize) -> (GfmToken, usize) {
-        let mut items = Vec::with_capacity(8);
-        let mut consumed = 0;
-        let first_line = lines[start];
-        let is_ordered = first_line.trim_start().bytes().next().unwrap_or(b' ').is_ascii_digit();
-        
-        let base_level = self.get_list_indent_level(first_line);
-        
-        let mut i = start;
-        while i < lines.len() {
-            let line = lines[i];
-            
-            if line.trim().is_empty() {
-                i += 1;
-                consumed += 1;
-                continue;
+        let mut rendered_blocks: Vec<(u64, RenderedBlock)> = Vec::with_capacity(stored.len());
+        // Where each key was first rendered in this pass, for repeated blocks
+        let mut rendered_keys: HashMap<u64, usize> = HashMap::with_capacity(stored.len());
+        for block in &stored {
+            // Keyed by the whole span, definitions included, and the kind of
+            // block, since the same lines can end up in a different block
+            let mut hasher = DefaultHasher::new();
+            block.source_hash.hash(&mut hasher);
+            // Footnote numbers depend on the references made before the block
+            if !definitions.is_empty() && block.mentions_footnotes {
+                defined_labels.hash(&mut hasher);
+                self.footnotes.referenced.hash(&mut hasher);
             }
-            
-            if !self.is_list_line(line) {
-                break;
+            // So do links to reference definitions anywhere in the document
+            if !self.references.is_empty() && block.mentions_links {
+                references_hash.hash(&mut hasher);
             }
-            
-            let absolute_level = self.get_list_indent_level(line);
-            let level = absolute_level.saturating_sub(base_level);
-            let trimmed = line.trim_start();
-            
-            let content = if is_ordered {
-                if let Some(pos) = trimmed.find(". ") {
-                    &trimmed[pos + 2..]
-                } else {
-                    ""
-                }
-            } else {
-                &trimmed[2..]
+            let key = hasher.finish();
+
+            let cached = match rendered_keys.get(&key) {
+                Some(&position) => Some(rendered_blocks[position].1.clone()),
+                None => self.block_cache.remove(&key),
             };
-            
-            let (checked, final_content) = if content.len() >= 3 {
-                let bytes = content.as_bytes();
-                if bytes[0] == b'[' && bytes[2] == b']' {
-                    match bytes[1] {
-                        b' ' => (Some(false), &content[3..]),
-                        b'x' | b'X' => (Some(true), &content[3..]),
-                        _ => (None, content),
+            let start_line = block.blocks[0].start_line;
+            let rendered = match cached.filter(RenderedBlock::embeds_unchanged) {
+                Some(mut rendered) => {
+                    if rendered.start_line != start_line {
+                        shift_source_lines(&mut rendered, start_line);
                     }
-                } else {
-                    (None, content)
+                    for label in &rendered.footnote_references {
+                        self.footnotes.reference(label);
+                    }
+                    rendered
+                }
+                None => {
+                    let mut rendered = RenderedBlock {
+                        start_line,
+                        ..RenderedBlock::default()
+                    };
+                    let references_before = self.footnotes.sequence.len();
+                    if let Some(converted) = self.convert_block(&block.blocks, 0) {
+                        self.render_tokens(&[converted], false, &mut rendered);
+                    }
+                    rendered.footnote_references = self.footnotes.sequence[references_before..].to_vec();
+                    rendered.tags = std::mem::take(&mut self.tags);
+                    rendered.stats = std::mem::take(&mut self.stats);
+                    rendered.embedded_files = std::mem::take(&mut self.embedded_files);
+                    rendered
                 }
-            } else {
-                (None, content)
             };
-            
-            let processed_content = self.process_inline_formatting(final_content.trim());
-            
-            items.push(GfmListItem {
-                content: processed_content,
-                level,
-                checked,
-            });
-            
-            i += 1;
-            consumed += 1;
+            rendered_keys.entry(key).or_insert(rendered_blocks.len());
+            rendered_blocks.push((key, rendered));
         }
-        
-        (GfmToken::List { items, ordered: is_ordered }, consumed)
-    }
 
-    #[inline]
-    fn is_potential_table_line(&self, line: &str) -> bool {
-        line.len() > 2 && line.as_bytes().iter().any(|&b| b == b'|')
-    }
+        let mut footnote_section =
+            (!self.footnotes.referenced.is_empty()).then(|| self.render_footnotes(&stored, &definitions));
 
-    fn parse_table(&mut self, lines: &[&str], start: usize) -> Option<(GfmToken, usize)> {
-        if start + 1 >= lines.len() {
-            return None;
+        // Headings are rendered with the anchor of their own text; repeated
+        // ones only get their suffix here, so a block doesn't have to be
+        // rendered again when the headings before it change
+        let mut slugs = HashMap::new();
+        for rendered in rendered_blocks.iter_mut().map(|(_, rendered)| rendered).chain(&mut footnote_section) {
+            assign_anchors(rendered, &mut slugs);
         }
-        
-        let header_line = lines[start];
-        let separator_line = lines[start + 1];
-        
-        // Verificar se segunda linha é separador válido
-        if !self.is_table_separator(separator_line) {
-            return None;
+
+        let capacity = if self.block_output { 0 } else { document.len() + (document.len() >> 1) };
+        let mut html = String::with_capacity(capacity);
+        let mut html_blocks = Vec::new();
+        let mut stats = DocumentStats::default();
+        let mut source_map = Vec::with_capacity(stored.len());
+        let mut toc = Vec::new();
+        let mut tags = Vec::new();
+        let mut block_cache = HashMap::with_capacity(rendered_blocks.len());
+        let block_keys: Vec<u64> = rendered_blocks.iter().map(|(key, _)| *key).collect();
+
+        for (key, rendered) in rendered_blocks {
+            if self.block_output && !rendered.html.is_empty() {
+                // Anchors and embedded notes can change while the source
+                // doesn't, so they go into the key too
+                let mut hasher = DefaultHasher::new();
+                (key, self.generation).hash(&mut hasher);
+                for entry in &rendered.toc {
+                    entry.slug.hash(&mut hasher);
+                }
+                rendered.embedded_files.hash(&mut hasher);
+                html_blocks.push(HtmlBlock {
+                    key: format!("{:016x}", hasher.finish()),
+                    start_line: rendered.start_line,
+                    html: rendered.html.clone(),
+                });
+            } else if !self.block_output {
+                html.push_str(&rendered.html);
+            }
+            stats.add(&rendered.stats);
+            source_map.extend_from_slice(&rendered.source_map);
+            tags.extend_from_slice(&rendered.tags);
+            toc.extend_from_slice(&rendered.toc);
+            block_cache.insert(key, rendered);
         }
-        
-        let mut headers = Vec::new();
-        let mut alignments = Vec::new();
-        
-        let header_cells: Vec<&str> = if header_line.starts_with('|') && header_line.ends_with('|') {
-            header_line[1..header_line.len()-1].split('|').collect()
-        } else {
-            header_line.split('|').collect()
-        };
-        
-        for cell in header_cells {
-            let trimmed = cell.trim();
-            if !trimmed.is_empty() {
-                headers.push(self.process_inline_formatting(trimmed));
+
+        if let Some(section) = footnote_section {
+            if self.block_output {
+                html_blocks.push(HtmlBlock {
+                    key: format!("{:016x}", self.hash_string(&section.html)),
+                    start_line: section.start_line,
+                    html: section.html,
+                });
+            } else {
+                html.push_str(&section.html);
             }
+            source_map.extend(section.source_map);
+            toc.extend(section.toc);
+            tags.append(&mut self.tags);
+            stats.add(&std::mem::take(&mut self.stats));
         }
-        
-        if headers.is_empty() {
-            return None;
+
+        let front_matter = front_matter
+            .and_then(|front_matter| front_matter.data.ok())
+            .filter(|data| data.is_object());
+        if let Some(data) = &front_matter {
+            tags.splice(0..0, front_matter_tags(data));
         }
-        
-        let separator_cells: Vec<&str> = if separator_line.starts_with('|') && separator_line.ends_with('|') {
-            separator_line[1..separator_line.len()-1].split('|').collect()
+
+        let diagnostics = if self.lint.enabled {
+            self.lint_document(&stored, &lines, &block_keys)
         } else {
-            separator_line.split('|').collect()
+            self.lint_cache.clear();
+            Vec::new()
         };
-        
-        for cell in separator_cells {
-            let trimmed = cell.trim();
-            if !trimmed.is_empty() && trimmed.contains('-') {
-                let starts_colon = trimmed.starts_with(':');
-                let ends_colon = trimmed.ends_with(':');
-                
-                let alignment = match (starts_colon, ends_colon) {
-                    (true, true) => Alignment::Center,
-                    (false, true) => Alignment::Right,
-                    (true, false) => Alignment::Left,
-                    (false, false) => Alignment::None,
-                };
-                alignments.push(alignment);
-            } else {
-                alignments.push(Alignment::None);
-            }
+
+        // Only blocks from the current document are kept around
+        self.block_cache = block_cache;
+        self.stored_blocks = stored;
+        self.document = document;
+
+        ParseResult {
+            html,
+            word_count: stats.words,
+            stats,
+            source_map,
+            toc,
+            tags: unique_tags(tags),
+            front_matter,
+            version: Some(self.version),
+            blocks: html_blocks,
+            error: lint_errors(&diagnostics),
+            diagnostics,
         }
-        
-        let mut rows = Vec::new();
-        let mut consumed = 2;
-        
-        let mut i = start + 2;
-        while i < lines.len() {
-            let line = lines[i];
-            if !line.contains('|') || line.trim().is_empty() {
-                break;
+    }
+
+    fn front_matter(&self, lines: &[&str]) -> Option<FrontMatter> {
+        if self.options.front_matter {
+            parse_front_matter(lines)
+        } else {
+            None
+        }
+    }
+
+    fn trim_html_cache(&mut self) {
+        // Optimized cache management
+        if self.html_cache.len() > 512 {
+            let target_size = self.html_cache.len() / 3;
+            let keys_to_remove: Vec<_> = self.html_cache.keys().take(target_size).copied().collect();
+            for key in keys_to_remove {
+                self.html_cache.remove(&key);
             }
-            
-            let row_cells: Vec<&str> = if line.starts_with('|') && line.ends_with('|') {
-                line[1..line.len()-1].split('|').collect()
-            } else {
-                line.split('|').collect()
+        }
+    }
+
+    // Renders the referenced footnotes, in reference order, as the section at
+    // the end of the document. Footnotes may reference further footnotes, so
+    // the list can grow while it is being rendered.
+    fn render_footnotes(
+        &mut self,
+        stored: &[StoredBlock],
+        definitions: &HashMap<String, (usize, usize)>,
+    ) -> RenderedBlock {
+        let mut bodies = Vec::new();
+        while bodies.len() < self.footnotes.referenced.len() {
+            let (position, index) = definitions[&self.footnotes.referenced[bodies.len()].0];
+            let blocks = &stored[position].blocks;
+            let lines = SourceMapEntry {
+                start_line: blocks[index].start_line,
+                end_line: blocks[index].end_line,
             };
-            
-            let mut row = Vec::with_capacity(headers.len());
-            for cell in row_cells {
-                let trimmed = cell.trim();
-                row.push(self.process_inline_formatting(trimmed));
+
+            let mut body = RenderedBlock::default();
+            if let Some(GfmBlock { token: GfmToken::FootnoteDefinition { content, .. }, .. }) =
+                self.convert_block(blocks, index)
+            {
+                self.render_tokens(&content, false, &mut body);
+            }
+            bodies.push((lines, body));
+        }
+
+        let mut section = RenderedBlock::default();
+        section.html.push_str("<section class=\"footnotes\" data-footnotes>\n<ol>\n");
+        for (position, (lines, body)) in bodies.into_iter().enumerate() {
+            let number = position + 1;
+            let (_, count) = self.footnotes.referenced[position];
+            let mut backlinks = String::new();
+            for index in 1..=count {
+                backlinks.push_str(&format!(
+                    " <a href=\"#{}\" class=\"footnote-backref\" data-footnote-backref aria-label=\"Back to reference {}\">↩{}</a>",
+                    footnote_reference_id(number, index),
+                    number,
+                    if index > 1 { format!("<sup>{}</sup>", index) } else { String::new() }
+                ));
             }
-            
-            if !row.is_empty() {
-                rows.push(row);
-                consumed += 1;
+
+            section.html.push_str(&format!(
+                "<li id=\"fn-{}\"{}>\n",
+                number,
+                source_line_attributes(lines.start_line, lines.end_line)
+            ));
+            // Backlinks go at the end of the last paragraph when there is one
+            match body.html.strip_suffix("</p>\n") {
+                Some(content) => {
+                    section.html.push_str(content);
+                    section.html.push_str(&backlinks);
+                    section.html.push_str("</p>\n");
+                }
+                None => {
+                    section.html.push_str(&body.html);
+                    section.html.push_str(&format!("<p>{}</p>\n", backlinks.trim_start()));
+                }
             }
-            
-            i += 1;
+            section.html.push_str("</li>\n");
+            section.source_map.push(lines);
+            section.source_map.extend(body.source_map);
+            section.toc.extend(body.toc);
         }
-        
-        Some((GfmToken::Table { headers, rows, alignments }, consumed))
+        section.html.push_str("</ol>\n</section>\n");
+        section
     }
 
-    fn is_table_separator(&self, line: &str) -> bool {
-        let trimmed = line.trim();
-        if trimmed.len() < 3 || !trimmed.contains('|') {
-            return false;
+    // Renders the note, or the heading section or `^id` block of it, that
+    // `![[...]]` points to inside a container carrying its path. Embedding
+    // stops at cycles and past `MAX_EMBED_DEPTH`, showing just a link to the
+    // note instead.
+    fn render_embed(&mut self, embed: &WikiLink, lines: &str, out: &mut String) {
+        let path = (!embed.target.is_empty())
+            .then(|| self.notes.resolve(&embed.target))
+            .flatten()
+            .map(str::to_string);
+        let anchor = embed.heading.as_deref().map(slugify);
+
+        let mut link = String::new();
+        inline::render_html(
+            &[Inline::WikiLink {
+                target: embed.target.clone(),
+                path: path.clone(),
+                anchor: anchor.clone(),
+                label: embed.label(),
+            }],
+            &mut link,
+        );
+
+        let Some(path) = path else {
+            out.push_str(&format!("<div class=\"embed embed-unresolved\"{}>{}</div>\n", lines, link));
+            return;
+        };
+
+        let mut embed_stack = self.embed_stack.clone();
+        // The note being rendered is the outermost one
+        if embed_stack.is_empty() {
+            embed_stack.extend(self.document_path.as_ref().map(|path| path.to_string_lossy().into_owned()));
         }
-        
-        let cells: Vec<&str> = if trimmed.starts_with('|') && trimmed.ends_with('|') {
-            trimmed[1..trimmed.len()-1].split('|').collect()
+
+        let content = if embed_stack.iter().any(|embedding| Path::new(embedding) == Path::new(&path)) {
+            Err("Circular embed".to_string())
+        } else if embed_stack.len() >= MAX_EMBED_DEPTH {
+            Err("Embeds are nested too deeply".to_string())
         } else {
-            trimmed.split('|').collect()
+            self.embedded_files.push((path.clone(), modified_time(&path)));
+            std::fs::read_to_string(&path)
+                .map_err(|e| format!("Cannot read note: {}", e))
+                .and_then(|markdown| match embed.heading.as_deref() {
+                    Some(heading) => match heading.strip_prefix('^') {
+                        Some(id) => block_reference(&markdown, id, self.options)
+                            .ok_or_else(|| format!("Block \"^{}\" not found", id)),
+                        None => heading_section(&markdown, heading, self.options)
+                            .ok_or_else(|| format!("Heading \"{}\" not found", heading)),
+                    },
+                    None => Ok(markdown),
+                })
         };
-        
-        let mut found_valid_separator = false;
-        
-        for cell in cells {
-            let cell_trimmed = cell.trim();
-            if cell_trimmed.is_empty() {
-                continue;
-            }
-            
-            if !cell_trimmed.bytes().all(|b| matches!(b, b':' | b'-' | b' ')) {
-                return false;
+
+        let content = match content {
+            Ok(markdown) => {
+                embed_stack.push(path.clone());
+                let mut parser = GfmMarkdownParser {
+                    options: self.options,
+                    notes: Arc::clone(&self.notes),
+                    embed_stack,
+                    callout_types: self.callout_types.clone(),
+                    // Diagnostics belong to the note they are in
+                    lint: LintConfig {
+                        enabled: false,
+                        ..LintConfig::default()
+                    },
+                    ..GfmMarkdownParser::new()
+                };
+                // Line numbers of another note would confuse the source map,
+                // and its anchors could clash with the ones around the embed
+                let html = strip_source_lines(&parser.render_document(&markdown).html);
+                let stem = Path::new(&path).file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+                let html = prefix_anchors(&html, &format!("embed-{}-", slugify(&stem)));
+                // Notes embedded in the embedded note matter as much
+                self.embedded_files.append(&mut parser.embedded_files);
+                for rendered in parser.block_cache.values_mut() {
+                    self.embedded_files.append(&mut rendered.embedded_files);
+                }
+                html
             }
-            
-            if cell_trimmed.contains('-') {
-                found_valid_separator = true;
+            Err(message) => {
+                out.push_str(&format!(
+                    "<div class=\"embed embed-error\"{}>{} <span class=\"embed-message\">{}</span></div>\n",
+                    lines,
+                    link,
+                    escape_html(&message)
+                ));
+                return;
             }
-        }
-        
-        found_valid_separator
-    }
+        };
 
-    fn parse_heading(&self, line: &str) -> Option<GfmToken> {
-        if !line.starts_with('#') {
-            return None;
+        out.push_str(&format!("<div class=\"embed\" data-path=\"{}\"", escape_html(&path)));
+        if let Some(anchor) = &anchor {
+            out.push_str(&format!(" data-heading=\"{}\"", escape_html(anchor)));
         }
+        out.push_str(&format!(
+            "{}>\n<div class=\"embed-title\">{}</div>\n<div class=\"embed-content\">\n{}</div>\n</div>\n",
+            lines, link, content
+        ));
+    }
 
-        let mut level = 0u8;
-        let mut chars = line.chars();
-        
-        while let Some(ch) = chars.next() {
-            if ch == '#' && level < 6 {
-                level += 1;
-            } else if ch == ' ' {
-                break;
-            } else {
-                return None;
-            }
+    // Lints the top-level blocks, given with their cache keys, reusing the
+    // results of every block that hasn't changed since the previous render
+    fn lint_document(&mut self, stored: &[StoredBlock], lines: &[&str], block_keys: &[u64]) -> Vec<Diagnostic> {
+        let mut lint_cache = std::mem::take(&mut self.lint_cache);
+        let mut linter = Linter::new(&self.lint, lines).with_references(&self.references);
+        if self.options.wikilinks {
+            linter = linter.with_notes(&self.notes);
         }
-
-        if level == 0 || level > 6 {
-            return None;
+        if let Some(path) = &self.document_path {
+            linter = linter.with_document_path(path);
         }
 
-        let text = line[level as usize..].trim().to_string();
-        if text.is_empty() {
-            return None;
+        let mut linted: Vec<(u64, BlockLint)> = Vec::with_capacity(block_keys.len());
+        // Where each key was first linted in this pass, for repeated blocks
+        let mut linted_keys: HashMap<u64, usize> = HashMap::with_capacity(block_keys.len());
+        for (block, &key) in stored.iter().zip(block_keys) {
+            let first_line = block.first_line();
+            let cached = match linted_keys.get(&key) {
+                Some(&position) => Some(linted[position].1.clone()),
+                None => lint_cache.remove(&key),
+            };
+            let lint = match cached {
+                Some(mut lint) => {
+                    lint.shift(first_line);
+                    lint
+                }
+                None => {
+                    let converted = self.convert_block(&block.blocks, 0);
+                    linter.lint_block(converted.as_ref(), first_line, block.blocks[0].end_line.min(lines.len()))
+                }
+            };
+            linted_keys.entry(key).or_insert(linted.len());
+            linted.push((key, lint));
         }
+        let diagnostics = linter.finish(linted.iter().map(|(_, lint)| lint));
 
-        Some(GfmToken::Heading { level, text })
+        // Files can appear or go away while the source stays the same
+        self.lint_cache = linted.into_iter().filter(|(_, lint)| !lint.checks_files()).collect();
+        diagnostics
     }
 
-    fn parse_code_block(&mut self, lines: &[&str], start: usize) -> (GfmToken, usize) {
-        let first_line = lines[start];
-        let language = if first_line.len() > 3 {
-            let lang = first_line[3..].trim();
-            if lang.is_empty() { None } else { Some(lang.to_string()) }
-        } else {
-            None
-        };
-
-        let mut code = self.get_buffer();
-        code.reserve(lines.len() * 50);
-        let mut consumed = 1;
-
-        for i in (start + 1)..lines.len() {
-            let line = lines[i];
-            if line.trim() == "```" {
-                consumed = i - start + 1;
-                break;
+    fn convert_children(&self, blocks: &[Block], parent: usize) -> Vec<GfmBlock> {
+        let mut converted = Vec::with_capacity(blocks[parent].children.len());
+        for &child in &blocks[parent].children {
+            if let Some(block) = self.convert_block(blocks, child) {
+                converted.push(block);
             }
-            
-            if !code.is_empty() {
-                code.push('\n');
-            }
-            code.push_str(line);
         }
+        converted
+    }
 
-        let token = GfmToken::CodeBlock {
-            language,
-            code: code.clone(),
+    fn convert_block(&self, blocks: &[Block], index: usize) -> Option<GfmBlock> {
+        let block = &blocks[index];
+        let token = match &block.kind {
+            BlockKind::Document | BlockKind::Item { .. } => return None,
+            BlockKind::Paragraph => {
+                let text = block.content.trim();
+                if text.is_empty() {
+                    return None;
+                }
+                match parse_embeds(text).filter(|_| self.options.wikilinks) {
+                    Some(embeds) => GfmToken::Embed(embeds),
+                    None => GfmToken::Paragraph(text.to_string()),
+                }
+            }
+            BlockKind::Heading { level } => GfmToken::Heading {
+                level: *level,
+                text: block.content.trim().to_string(),
+            },
+            BlockKind::ThematicBreak => GfmToken::HorizontalRule,
+            BlockKind::CodeBlock { info, .. } => {
+                let language = info.split_whitespace().next().map(|lang| lang.to_string());
+                GfmToken::CodeBlock {
+                    language,
+                    info: info.clone(),
+                    code: block.content.clone(),
+                }
+            }
+            BlockKind::MathBlock => GfmToken::Math(block.content.trim().to_string()),
+            BlockKind::HtmlBlock { .. } => GfmToken::HtmlBlock(block.content.clone()),
+            BlockKind::FootnoteDefinition { label } => GfmToken::FootnoteDefinition {
+                label: label.clone(),
+                content: self.convert_children(blocks, index),
+            },
+            BlockKind::BlockQuote => {
+                if self.options.alerts {
+                    if let Some(callout) = self.convert_callout(blocks, index) {
+                        return Some(callout);
+                    }
+                }
+                GfmToken::Blockquote(self.convert_children(blocks, index))
+            }
+            BlockKind::List { data, tight } => {
+                let items = block
+                    .children
+                    .iter()
+                    .map(|&item| self.convert_list_item(blocks, item))
+                    .collect();
+                GfmToken::List {
+                    items,
+                    ordered: data.ordered,
+                    start: data.start,
+                    tight: *tight,
+                }
+            }
+            BlockKind::Table { alignments } => {
+                let mut lines = block.content.lines().filter(|line| !line.trim().is_empty());
+                let headers = split_table_row(lines.next().unwrap_or(""));
+                let rows = lines
+                    .map(|line| {
+                        let mut cells = split_table_row(line);
+                        cells.resize(alignments.len(), String::new());
+                        cells
+                    })
+                    .collect();
+                GfmToken::Table {
+                    headers,
+                    rows,
+                    alignments: alignments.clone(),
+                }
+            }
         };
 
-        self.return_buffer(code);
-        (token, consumed)
+        Some(GfmBlock {
+            token,
+            start_line: block.start_line,
+            end_line: block.end_line,
+        })
     }
 
-    #[inline]
-    fn parse_alert_type(&self, text: &str) -> Option<AlertType> {
-        match text {
-            "[!NOTE]" => Some(AlertType::Note),
-            "[!TIP]" => Some(AlertType::Tip),
-            "[!IMPORTANT]" => Some(AlertType::Important),
-            "[!WARNING]" => Some(AlertType::Warning),
-            "[!CAUTION]" => Some(AlertType::Caution),
-            _ => None,
-        }
-    }
-
-    fn parse_alert(&mut self, lines: &[&str], start: usize) -> Option<(GfmToken, usize)> {
-        let first_line = lines[start];
-        let trimmed = first_line.trim_start();
-        
-        if !trimmed.starts_with("> [!") {
-            return None;
-        }
-        
-        let alert_text = &trimmed[2..];
-        
-        if let Some(close_bracket) = alert_text.find(']') {
-            let alert_type_str = &alert_text[..close_bracket + 1];
-            
-            if let Some(alert_type) = self.parse_alert_type(alert_type_str) {
-                let mut content = String::with_capacity(256);
-                let mut consumed = 1;
-                
-                // Adicionar conteúdo da primeira linha após o tipo
-                let remaining_first = &alert_text[close_bracket + 1..].trim();
-                if !remaining_first.is_empty() {
-                    content.push_str(&self.process_inline_formatting(remaining_first));
-                }
-                
-                // Processar linhas subsequentes
-                for i in (start + 1)..lines.len() {
-                    let line = lines[i];
-                    let trimmed_line = line.trim_start();
-                    
-                    if trimmed_line.starts_with("> ") {
-                        let line_content = &trimmed_line[2..];
-                        
-                        // Parar se encontrar outro alert
-                        if line_content.starts_with("[!") {
-                            break;
-                        }
-                        
-                        if !content.is_empty() {
-                            content.push(' ');
-                        }
-                        content.push_str(&self.process_inline_formatting(line_content));
-                        consumed += 1;
-                    } else if trimmed_line.trim().is_empty() {
-                        // Linha vazia - continuar mas não incrementar consumed se não há conteúdo
-                        consumed += 1;
-                    } else {
-                        break;
-                    }
+    fn convert_list_item(&self, blocks: &[Block], item: usize) -> GfmListItem {
+        let mut content = self.convert_children(blocks, item);
+        let mut checked = None;
+
+        if let Some(GfmBlock { token: GfmToken::Paragraph(text), .. }) =
+            content.first_mut().filter(|_| self.options.task_lists)
+        {
+            let bytes = text.as_bytes();
+            let followed_by_space = bytes.len() > 3 && matches!(bytes[3], b' ' | b'\t' | b'\n');
+            if bytes.len() >= 3 && bytes[0] == b'[' && bytes[2] == b']' && followed_by_space {
+                checked = match bytes[1] {
+                    b' ' => Some(false),
+                    b'x' | b'X' => Some(true),
+                    _ => None,
+                };
+                if checked.is_some() {
+                    *text = text[3..].trim_start().to_string();
                 }
-                
-                return Some((GfmToken::Alert { alert_type, content }, consumed));
             }
         }
-        
-        None
+
+        GfmListItem {
+            content,
+            checked,
+            start_line: blocks[item].start_line,
+            end_line: blocks[item].end_line,
+        }
     }
 
-    #[inline]
-    fn is_horizontal_rule(&self, line: &str) -> bool {
-        let trimmed = line.trim();
-        if trimmed.len() < 3 {
-            return false;
+    fn convert_callout(&self, blocks: &[Block], quote: usize) -> Option<GfmBlock> {
+        let &first = blocks[quote].children.first()?;
+        if !matches!(blocks[first].kind, BlockKind::Paragraph) {
+            return None;
+        }
+
+        let paragraph = blocks[first].content.trim();
+        let (first_line, rest) = paragraph.split_once('\n').unwrap_or((paragraph, ""));
+        let (kind, folded, title) = parse_callout_marker(first_line.trim())?;
+
+        // The rest of the first line is the title; the following lines are the content
+        let mut content = self.convert_children(blocks, quote);
+        if rest.trim().is_empty() {
+            content.remove(0);
+        } else {
+            content[0].token = GfmToken::Paragraph(rest.to_string());
+            content[0].start_line += 1;
         }
 
-        let first_char = trimmed.bytes().next().unwrap_or(0);
-        if !matches!(first_char, b'-' | b'*') {
-            return false;
+        Some(GfmBlock {
+            token: GfmToken::Callout {
+                kind,
+                title: Some(title.to_string()).filter(|title| !title.is_empty()),
+                folded,
+                content,
+            },
+            start_line: blocks[quote].start_line,
+            end_line: blocks[quote].end_line,
+        })
+    }
+
+    // Colour class and icon of a callout type: the user's own types first,
+    // then the built-in ones, falling back to a note
+    fn callout_style(&self, kind: &str) -> (String, String) {
+        if let Some(callout) = self.callout_types.get(kind) {
+            let class: String = callout
+                .class
+                .chars()
+                .filter(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_'))
+                .collect();
+            return (class, escape_html(&callout.icon));
         }
 
-        trimmed.bytes().all(|b| b == first_char) && trimmed.len() >= 3
+        let (_, class, icon) = BUILTIN_CALLOUTS
+            .iter()
+            .find(|(name, _, _)| *name == kind)
+            .unwrap_or(&BUILTIN_CALLOUTS[0]);
+        (class.to_string(), icon.to_string())
     }
 
     fn process_inline_formatting(&mut self, text: &str) -> String {
@@ -550,394 +969,1824 @@ impl GfmMarkdownParser {
             return String::new();
         }
 
+        // Footnote numbers depend on where the text is, and tags have to be
+        // collected again, so such text can't be cached
+        let has_footnotes = !self.footnotes.defined.is_empty() && text.contains("[^");
+        let uncacheable = has_footnotes || text.contains('#');
+
         let hash = self.hash_string(text);
-        if let Some(cached) = self.html_cache.get(&hash) {
-            return cached.clone();
+        if !uncacheable {
+            if let Some((html, stats)) = self.html_cache.get(&hash) {
+                self.stats.add(stats);
+                return html.clone();
+            }
         }
 
-        // Fast path for text without markdown formatting
-        if !text.as_bytes().iter().any(|&b| matches!(b, b'*' | b'`' | b'[' | b'!' | b'~' | b'_')) {
-            let result = text.to_string();
+        // Fast path for text without markdown formatting or autolinks
+        if !text.as_bytes().iter().any(|&b| {
+            matches!(b, b'*' | b'`' | b'[' | b']' | b'!' | b'~' | b'_' | b'\\' | b'<' | b'&' | b'$' | b'#' | b':' | b'@' | b'\n')
+        }) && !text.contains("www.") {
+            let result = escape_html(text.trim());
+            let mut stats = DocumentStats::default();
+            stats.add_text(text.trim());
+            self.stats.add(&stats);
             if self.html_cache.len() < 512 {
-                self.html_cache.insert(hash, result.clone());
+                self.html_cache.insert(hash, (result.clone(), stats));
             }
             return result;
         }
 
         let mut result = self.get_buffer();
         result.reserve(text.len() + (text.len() >> 2));
-        let mut chars = text.chars().peekable();
-        
-        while let Some(ch) = chars.next() {
-            match ch {
-                '~' if chars.peek() == Some(&'~') => {
-                    chars.next();
-                    if let Some(strike_text) = self.extract_until(&mut chars, "~~") {
-                        result.push_str("<del>");
-                        result.push_str(&strike_text);
-                        result.push_str("</del>");
-                    } else {
-                        result.push_str("~~");
-                    }
-                }
-                '*' if chars.peek() == Some(&'*') => {
-                    chars.next();
-                    if let Some(bold_text) = self.extract_until(&mut chars, "**") {
-                        result.push_str("<strong>");
-                        result.push_str(&bold_text);
-                        result.push_str("</strong>");
-                    } else {
-                        result.push_str("**");
-                    }
-                }
-                '*' => {
-                    if let Some(italic_text) = self.extract_until(&mut chars, "*") {
-                        result.push_str("<em>");
-                        result.push_str(&italic_text);
-                        result.push_str("</em>");
-                    } else {
-                        result.push(ch);
-                    }
-                }
-                '_' if chars.peek() == Some(&'_') => {
-                    chars.next();
-                    if let Some(bold_text) = self.extract_until(&mut chars, "__") {
-                        result.push_str("<strong>");
-                        result.push_str(&bold_text);
-                        result.push_str("</strong>");
-                    } else {
-                        result.push_str("__");
-                    }
-                }
-                '_' => {
-                    if let Some(italic_text) = self.extract_until(&mut chars, "_") {
-                        result.push_str("<em>");
-                        result.push_str(&italic_text);
-                        result.push_str("</em>");
-                    } else {
-                        result.push(ch);
-                    }
-                }
-                '`' => {
-                    if let Some(code_text) = self.extract_until(&mut chars, "`") {
-                        result.push_str("<code>");
-                        result.push_str(&self.escape_html(&code_text));
-                        result.push_str("</code>");
-                    } else {
-                        result.push(ch);
-                    }
-                }
-                '!' if chars.peek() == Some(&'[') => {
-                    chars.next();
-                    let mut temp_chars = chars.clone();
-                    if let Some((alt_text, url)) = self.extract_link(&mut temp_chars) {
-                        chars = temp_chars;
-                        result.push_str("<img src=\"");
-                        result.push_str(&self.escape_html(&url));
-                        result.push_str("\" alt=\"");
-                        result.push_str(&self.escape_html(&alt_text));
-                        result.push_str("\" loading=\"lazy\">");
-                    } else {
-                        result.push('!');
-                    }
-                }
-                '[' => {
-                    let mut temp_chars = chars.clone();
-                    if let Some((link_text, url)) = self.extract_link(&mut temp_chars) {
-                        chars = temp_chars;
-                        result.push_str("<a href=\"");
-                        result.push_str(&self.escape_html(&url));
-                        result.push_str("\">");
-                        result.push_str(&self.escape_html(&link_text));
-                        result.push_str("</a>");
-                    } else {
-                        result.push(ch);
-                    }
-                }
-                _ => result.push(ch),
-            }
+        let mut inlines = InlineParser::new(text)
+            .with_footnotes(&self.footnotes.defined)
+            .with_references(&self.references)
+            .with_notes(&self.notes)
+            .with_options(&self.options)
+            .parse();
+        if has_footnotes {
+            self.footnotes.resolve(&mut inlines);
         }
+        collect_tags(&inlines, &mut self.tags);
+        inline::render_html(&inlines, &mut result);
+
+        let mut plain = String::new();
+        inline::plain_text(&inlines, &mut plain);
+        let mut stats = DocumentStats::default();
+        stats.add_text(&plain);
+        self.stats.add(&stats);
 
         let output = result.clone();
         self.return_buffer(result);
-        
-        if self.html_cache.len() < 256 {
-            self.html_cache.insert(hash, output.clone());
+
+        if !uncacheable && self.html_cache.len() < 256 {
+            self.html_cache.insert(hash, (output.clone(), stats));
         }
         output
     }
 
-    fn extract_until(&self, chars: &mut std::iter::Peekable<std::str::Chars>, delimiter: &str) -> Option<String> {
-        let mut content = String::with_capacity(64);
-        let max_len = match delimiter {
-            "~~" | "**" | "__" => 300,
-            "*" | "`" | "_" => 200,
-            _ => return None,
-        };
-        
-        match delimiter {
-            "*" => {
-                while let Some(&ch) = chars.peek() {
-                    if ch == '*' {
-                        chars.next();
-                        return Some(content);
+    fn render_tokens(&mut self, blocks: &[GfmBlock], tight: bool, out: &mut RenderedBlock) {
+        for block in blocks {
+            let lines = source_line_attributes(block.start_line, block.end_line);
+            let entry = SourceMapEntry {
+                start_line: block.start_line,
+                end_line: block.end_line,
+            };
+
+            match &block.token {
+                GfmToken::Heading { level, text } => {
+                    let content = self.process_inline_formatting(text);
+
+                    let mut heading = String::new();
+                    let inlines = InlineParser::new(text)
+                        .with_footnotes(&self.footnotes.defined)
+                        .with_references(&self.references)
+                        .with_notes(&self.notes)
+                        .with_options(&self.options)
+                        .parse();
+                    inline::plain_text(&inlines, &mut heading);
+                    // Made unique across the document by `assign_anchors`
+                    let slug = slugify(&heading);
+
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    out.html.push_str(&format!("<h{0} id=\"{1}\"{2}>{3}</h{0}>\n", level, slug, lines, content));
+                    out.toc.push(TocEntry {
+                        level: *level,
+                        text: heading,
+                        slug,
+                        line: block.start_line,
+                    });
+                },
+                GfmToken::Paragraph(text) => {
+                    let content = self.process_inline_formatting(text);
+                    if tight {
+                        out.html.push_str(&content);
+                    } else {
+                        ensure_newline(&mut out.html);
+                        out.source_map.push(entry);
+                        out.html.push_str(&format!("<p{}>{}</p>\n", lines, content));
                     }
-                    chars.next();
-                    content.push(ch);
-                    if content.len() > max_len { return None; }
-                }
-            }
-            "**" => {
-                while let Some(&ch) = chars.peek() {
-                    if ch == '*' {
-                        chars.next();
-                        if chars.peek() == Some(&'*') {
-                            chars.next();
-                            return Some(content);
-                        } else {
-                            content.push(ch);
-                        }
+                },
+                GfmToken::Embed(embeds) => {
+                    ensure_newline(&mut out.html);
+                    for (offset, embed) in embeds.iter().enumerate() {
+                        let line = block.start_line + offset;
+                        out.source_map.push(SourceMapEntry {
+                            start_line: line,
+                            end_line: line,
+                        });
+                        self.render_embed(embed, &source_line_attributes(line, line), &mut out.html);
+                    }
+                },
+                GfmToken::CodeBlock { language, code, .. } => {
+                    self.stats.add_text(code);
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    if let Some(lang) = language {
+                        let lang = unescape_string(lang);
+                        let code = highlight(&lang, code).unwrap_or_else(|| escape_html(code));
+                        out.html.push_str(&format!(
+                            "<pre{}><code class=\"language-{}\">{}</code></pre>\n",
+                            lines,
+                            escape_html(&lang),
+                            code
+                        ));
                     } else {
-                        chars.next();
-                        content.push(ch);
+                        out.html.push_str(&format!("<pre{}><code>{}</code></pre>\n", lines, escape_html(code)));
                     }
-                    if content.len() > max_len { return None; }
-                }
-            }
-            "~~" => {
-                while let Some(&ch) = chars.peek() {
-                    if ch == '~' {
-                        chars.next();
-                        if chars.peek() == Some(&'~') {
-                            chars.next();
-                            return Some(content);
-                        } else {
-                            content.push(ch);
-                        }
+                },
+                GfmToken::Math(tex) => {
+                    self.stats.add_text(tex);
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    out.html.push_str(&format!("<div class=\"math-block\"{}>", lines));
+                    render_math(tex, true, &mut out.html);
+                    out.html.push_str("</div>\n");
+                },
+                GfmToken::HtmlBlock(raw) => {
+                    // Raw HTML has no element of ours to carry the source lines
+                    ensure_newline(&mut out.html);
+                    sanitize_html(raw, &mut out.html);
+                    out.html.push('\n');
+                },
+                GfmToken::List { items, ordered, start, tight } => {
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    if *ordered && *start != 1 {
+                        out.html.push_str(&format!("<ol{} start=\"{}\">\n", lines, start));
+                    } else if *ordered {
+                        out.html.push_str(&format!("<ol{}>\n", lines));
                     } else {
-                        chars.next();
-                        content.push(ch);
+                        out.html.push_str(&format!("<ul{}>\n", lines));
                     }
-                    if content.len() > max_len { return None; }
-                }
-            }
-            "_" => {
-                while let Some(&ch) = chars.peek() {
-                    if ch == '_' {
-                        chars.next();
-                        return Some(content);
+
+                    self.render_gfm_list_items(items, *tight, out);
+
+                    out.html.push_str(if *ordered { "</ol>\n" } else { "</ul>\n" });
+                },
+                GfmToken::Table { headers, rows, alignments } => {
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    out.html.push_str(&format!("<table{}>", lines));
+
+                    out.html.push_str("<thead><tr>");
+                    for (i, header) in headers.iter().enumerate() {
+                        let align = get_align_style(alignments, i);
+                        let content = self.process_inline_formatting(header);
+                        out.html.push_str(&format!("<th{}>{}</th>", align, content));
                     }
-                    chars.next();
-                    content.push(ch);
-                    if content.len() > max_len { return None; }
-                }
-            }
-            "__" => {
-                while let Some(&ch) = chars.peek() {
-                    if ch == '_' {
-                        chars.next();
-                        if chars.peek() == Some(&'_') {
-                            chars.next();
-                            return Some(content);
-                        } else {
-                            content.push(ch);
+                    out.html.push_str("</tr></thead>");
+
+                    if !rows.is_empty() {
+                        out.html.push_str("<tbody>");
+                        for row in rows {
+                            out.html.push_str("<tr>");
+                            for (i, cell) in row.iter().enumerate() {
+                                let align = get_align_style(alignments, i);
+                                let content = self.process_inline_formatting(cell);
+                                out.html.push_str(&format!("<td{}>{}</td>", align, content));
+                            }
+                            out.html.push_str("</tr>");
                         }
-                    } else {
-                        chars.next();
-                        content.push(ch);
+                        out.html.push_str("</tbody>");
                     }
-                    if content.len() > max_len { return None; }
-                }
+
+                    out.html.push_str("</table>\n");
+                },
+                GfmToken::Blockquote(children) => {
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    out.html.push_str(&format!("<blockquote{}>\n", lines));
+                    self.render_tokens(children, false, out);
+                    out.html.push_str("</blockquote>\n");
+                },
+                GfmToken::Callout { kind, title, folded, content } => {
+                    let (class, icon) = self.callout_style(kind);
+                    let title = match title {
+                        Some(title) => self.process_inline_formatting(title),
+                        None => escape_html(&default_callout_title(kind)),
+                    };
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    match folded {
+                        Some(folded) => out.html.push_str(&format!(
+                            "<details class=\"alert alert-{}\" data-callout=\"{}\"{}{}><summary class=\"alert-summary\"><span class=\"alert-icon\">{}</span><span class=\"alert-title\">{}</span></summary><div class=\"alert-content\">\n",
+                            class, escape_html(kind), if *folded { "" } else { " open" }, lines, icon, title
+                        )),
+                        None => out.html.push_str(&format!(
+                            "<div class=\"alert alert-{}\" data-callout=\"{}\"{}><div class=\"alert-icon\">{}</div><div class=\"alert-content\"><div class=\"alert-title\">{}</div>\n",
+                            class, escape_html(kind), lines, icon, title
+                        )),
+                    }
+                    self.render_tokens(content, false, out);
+                    out.html.push_str(if folded.is_some() { "</div></details>\n" } else { "</div></div>\n" });
+                },
+                // Definitions are rendered in the footnotes section instead
+                GfmToken::FootnoteDefinition { .. } => {},
+                GfmToken::HorizontalRule => {
+                    ensure_newline(&mut out.html);
+                    out.source_map.push(entry);
+                    out.html.push_str(&format!("<hr{}>\n", lines));
+                },
+            }
+        }
+    }
+
+    fn render_gfm_list_items(&mut self, items: &[GfmListItem], tight: bool, out: &mut RenderedBlock) {
+        for item in items {
+            let lines = source_line_attributes(item.start_line, item.end_line);
+            out.source_map.push(SourceMapEntry {
+                start_line: item.start_line,
+                end_line: item.end_line,
+            });
+
+            // Add the list item with checkbox support
+            if let Some(checked) = item.checked {
+                let checkbox = if checked {
+                    r#"<span class="task-list-item-checkbox checked" role="checkbox" aria-checked="true">
+                    <svg width="16" height="16" viewBox="0 0 16 16" fill="none">
+                        <rect x="1" y="1" width="14" height="14" rx="3" fill="var(--theme-accent)" stroke="var(--theme-accent)" stroke-width="2"/>
+                        <path d="M4 8l2.5 2.5L12 5" stroke="white" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"/>
+                    </svg>
+                  </span> "#
+                } else {
+                    r#"<span class="task-list-item-checkbox unchecked" role="checkbox" aria-checked="false">
+                    <svg width="16" height="16" viewBox="0 0 16 16" fill="none">
+                        <rect x="1" y="1" width="14" height="14" rx="3" fill="transparent" stroke="var(--theme-muted-foreground)" stroke-width="2"/>
+                    </svg>
+                  </span> "#
+                };
+                out.html.push_str(&format!("<li class=\"task-list-item\"{}>", lines));
+                out.html.push_str(checkbox);
+            } else {
+                out.html.push_str(&format!("<li{}>", lines));
+            }
+
+            self.render_tokens(&item.content, tight, out);
+            out.html.push_str("</li>\n");
+        }
+    }
+}
+
+// Block structure parsing, following the CommonMark algorithm: every line is
+// matched against the open container blocks (block quotes, list items) from
+// the document down, then checked for new block starts, and whatever is left
+// is added to the innermost open leaf block. Blocks live in an arena that is
+// converted into `GfmToken`s once the whole document has been consumed.
+
+const DOCUMENT: usize = 0;
+// How many notes, the one being rendered included, can embed each other
+const MAX_EMBED_DEPTH: usize = 4;
+const CODE_INDENT: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+struct ListData {
+    ordered: bool,
+    bullet_char: u8,
+    start: u32,
+    delimiter: u8,
+    marker_offset: usize,
+    padding: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CodeFence {
+    ch: u8,
+    length: usize,
+    offset: usize,
+}
+
+#[derive(Debug)]
+enum BlockKind {
+    Document,
+    BlockQuote,
+    List { data: ListData, tight: bool },
+    Item { data: ListData },
+    Paragraph,
+    Heading { level: u8 },
+    ThematicBreak,
+    CodeBlock { fence: Option<CodeFence>, info: String },
+    MathBlock,
+    HtmlBlock { html_type: u8 },
+    Table { alignments: Vec<Alignment> },
+    FootnoteDefinition { label: String },
+}
+
+#[derive(Debug)]
+struct Block {
+    kind: BlockKind,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    open: bool,
+    content: String,
+    start_line: usize,
+    end_line: usize,
+    // Lines of reference definitions taken from the start of a paragraph;
+    // `start_line` points past them
+    definition_lines: usize,
+    // Set on top-level blocks that start where no other block was left open,
+    // so that parsing can start over from their first line
+    clean_start: bool,
+}
+
+enum Continuation {
+    Matched,
+    NotMatched,
+    LineConsumed,
+}
+
+enum BlockStart {
+    None,
+    Container,
+    Leaf,
+}
+
+struct BlockParser {
+    blocks: Vec<Block>,
+    tip: usize,
+    old_tip: usize,
+    last_matched_container: usize,
+    line: String,
+    line_number: usize,
+    offset: usize,
+    column: usize,
+    next_nonspace: usize,
+    next_nonspace_column: usize,
+    indent: usize,
+    indented: bool,
+    blank: bool,
+    partially_consumed_tab: bool,
+    all_closed: bool,
+    // Whether the current line started with no block left open
+    clean_start: bool,
+    // Link reference definitions by normalized label, in document order,
+    // with the block each was taken from
+    references: Vec<(usize, String, LinkReference)>,
+    // Source of every run of definitions, in document order
+    definitions: Vec<String>,
+    options: ParserOptions,
+}
+
+impl BlockParser {
+    fn new(options: ParserOptions) -> Self {
+        let document = Block {
+            kind: BlockKind::Document,
+            parent: None,
+            children: Vec::new(),
+            open: true,
+            content: String::new(),
+            start_line: 1,
+            end_line: 0,
+            definition_lines: 0,
+            clean_start: false,
+        };
+
+        Self {
+            blocks: vec![document],
+            tip: DOCUMENT,
+            old_tip: DOCUMENT,
+            last_matched_container: DOCUMENT,
+            line: String::new(),
+            line_number: 0,
+            offset: 0,
+            column: 0,
+            next_nonspace: 0,
+            next_nonspace_column: 0,
+            indent: 0,
+            indented: false,
+            blank: false,
+            partially_consumed_tab: false,
+            all_closed: true,
+            clean_start: true,
+            references: Vec::new(),
+            definitions: Vec::new(),
+            options,
+        }
+    }
+
+    fn finish(&mut self) {
+        loop {
+            let tip = self.tip;
+            self.finalize(tip, self.line_number);
+            if tip == DOCUMENT {
+                break;
+            }
+        }
+    }
+
+    // Whether no block is open that the next line could continue, which
+    // leaves the parser as if it were starting on a new document
+    fn at_clean_start(&self) -> bool {
+        match self.blocks[DOCUMENT].children.last() {
+            // Headings and thematic breaks are closed by whatever comes next
+            Some(&last) => {
+                !self.blocks[last].open
+                    || matches!(self.blocks[last].kind, BlockKind::Heading { .. } | BlockKind::ThematicBreak)
+            }
+            None => true,
+        }
+    }
+
+    fn incorporate_line(&mut self, line: &str) {
+        self.clean_start = self.at_clean_start();
+        self.old_tip = self.tip;
+        self.offset = 0;
+        self.column = 0;
+        self.blank = false;
+        self.partially_consumed_tab = false;
+        self.line_number += 1;
+        self.line = if line.contains('\0') {
+            line.replace('\0', "\u{FFFD}")
+        } else {
+            line.to_string()
+        };
+
+        // Match the line against every open container, innermost last
+        let mut container = DOCUMENT;
+        while let Some(&last_child) = self.blocks[container].children.last() {
+            if !self.blocks[last_child].open {
+                break;
+            }
+            container = last_child;
+            self.find_next_nonspace();
+
+            match self.continue_block(container) {
+                Continuation::Matched => {}
+                Continuation::NotMatched => {
+                    container = self.blocks[container].parent.unwrap_or(DOCUMENT);
+                    break;
+                }
+                Continuation::LineConsumed => return,
+            }
+        }
+
+        self.all_closed = container == self.old_tip;
+        self.last_matched_container = container;
+
+        let mut matched_leaf = !self.is_paragraph_like(container) && self.accepts_lines(container);
+        while !matched_leaf {
+            self.find_next_nonspace();
+
+            if !self.indented && !is_maybe_special(self.byte_at(self.next_nonspace)) {
+                self.advance_next_nonspace();
+                break;
+            }
+
+            match self.try_block_starts(container) {
+                BlockStart::Container => container = self.tip,
+                BlockStart::Leaf => {
+                    container = self.tip;
+                    matched_leaf = true;
+                }
+                BlockStart::None => {
+                    self.advance_next_nonspace();
+                    break;
+                }
+            }
+        }
+
+        if !self.all_closed && !self.blank && matches!(self.blocks[self.tip].kind, BlockKind::Paragraph) {
+            // Lazy continuation line
+            self.add_line();
+            return;
+        }
+
+        self.close_unmatched_blocks();
+
+        if self.accepts_lines(container) {
+            self.add_line();
+            if let BlockKind::HtmlBlock { html_type } = self.blocks[container].kind {
+                if (1..=5).contains(&html_type) && html_block_ends(html_type, &self.line[self.offset..]) {
+                    self.finalize(container, self.line_number);
+                }
+            }
+        } else if self.offset < self.line.len() && !self.blank {
+            self.add_child(BlockKind::Paragraph);
+            self.advance_next_nonspace();
+            self.add_line();
+        }
+    }
+
+    fn continue_block(&mut self, container: usize) -> Continuation {
+        match &self.blocks[container].kind {
+            BlockKind::Document | BlockKind::List { .. } => Continuation::Matched,
+            BlockKind::BlockQuote => {
+                if !self.indented && self.byte_at(self.next_nonspace) == Some(b'>') {
+                    self.advance_next_nonspace();
+                    self.advance_offset(1, false);
+                    if matches!(self.byte_at(self.offset), Some(b' ') | Some(b'\t')) {
+                        self.advance_offset(1, true);
+                    }
+                    Continuation::Matched
+                } else {
+                    Continuation::NotMatched
+                }
+            }
+            BlockKind::Item { data } => {
+                let item_indent = data.marker_offset + data.padding;
+                if self.blank {
+                    if self.blocks[container].children.is_empty() {
+                        // A list item can begin with at most one blank line
+                        return Continuation::NotMatched;
+                    }
+                    self.advance_next_nonspace();
+                    Continuation::Matched
+                } else if self.indent >= item_indent {
+                    self.advance_offset(item_indent, true);
+                    Continuation::Matched
+                } else {
+                    Continuation::NotMatched
+                }
+            }
+            BlockKind::FootnoteDefinition { .. } => {
+                if self.indent >= CODE_INDENT {
+                    self.advance_offset(CODE_INDENT, true);
+                    Continuation::Matched
+                } else if self.blank {
+                    self.advance_next_nonspace();
+                    Continuation::Matched
+                } else {
+                    Continuation::NotMatched
+                }
+            }
+            BlockKind::Heading { .. } | BlockKind::ThematicBreak => Continuation::NotMatched,
+            BlockKind::CodeBlock { fence: Some(fence), .. } => {
+                let fence = *fence;
+                if self.indent <= 3 && self.byte_at(self.next_nonspace) == Some(fence.ch) {
+                    let rest = &self.line.as_bytes()[self.next_nonspace..];
+                    let run = rest.iter().take_while(|&&b| b == fence.ch).count();
+                    let only_whitespace_after = rest[run..].iter().all(|&b| b == b' ' || b == b'\t');
+                    if run >= fence.length && only_whitespace_after {
+                        self.finalize(container, self.line_number);
+                        return Continuation::LineConsumed;
+                    }
+                }
+
+                let mut remaining = fence.offset;
+                while remaining > 0 && matches!(self.byte_at(self.offset), Some(b' ') | Some(b'\t')) {
+                    self.advance_offset(1, true);
+                    remaining -= 1;
+                }
+                Continuation::Matched
+            }
+            BlockKind::MathBlock => {
+                // The closing `$$` may end a line of the formula
+                let rest = self.line[self.next_nonspace..].trim_end();
+                if let Some(tex) = rest.strip_suffix("$$") {
+                    let tex = format!("{}\n", tex);
+                    self.blocks[container].content.push_str(&tex);
+                    self.finalize(container, self.line_number);
+                    return Continuation::LineConsumed;
+                }
+                Continuation::Matched
+            }
+            BlockKind::CodeBlock { fence: None, .. } => {
+                if self.indent >= CODE_INDENT {
+                    self.advance_offset(CODE_INDENT, true);
+                    Continuation::Matched
+                } else if self.blank {
+                    self.advance_next_nonspace();
+                    Continuation::Matched
+                } else {
+                    Continuation::NotMatched
+                }
+            }
+            BlockKind::HtmlBlock { html_type } => {
+                if self.blank && (*html_type == 6 || *html_type == 7) {
+                    Continuation::NotMatched
+                } else {
+                    Continuation::Matched
+                }
+            }
+            BlockKind::Paragraph | BlockKind::Table { .. } => {
+                if self.blank {
+                    Continuation::NotMatched
+                } else {
+                    Continuation::Matched
+                }
+            }
+        }
+    }
+
+    fn try_block_starts(&mut self, container: usize) -> BlockStart {
+        let starts: [fn(&mut Self, usize) -> BlockStart; 11] = [
+            Self::start_block_quote,
+            Self::start_footnote_definition,
+            Self::start_atx_heading,
+            Self::start_fenced_code,
+            Self::start_math_block,
+            Self::start_html_block,
+            Self::start_setext_heading,
+            Self::start_table,
+            Self::start_thematic_break,
+            Self::start_list_item,
+            Self::start_indented_code,
+        ];
+
+        for start in starts {
+            match start(self, container) {
+                BlockStart::None => continue,
+                result => return result,
+            }
+        }
+        BlockStart::None
+    }
+
+    fn start_block_quote(&mut self, _container: usize) -> BlockStart {
+        if self.indented || self.byte_at(self.next_nonspace) != Some(b'>') {
+            return BlockStart::None;
+        }
+
+        self.advance_next_nonspace();
+        self.advance_offset(1, false);
+        if matches!(self.byte_at(self.offset), Some(b' ') | Some(b'\t')) {
+            self.advance_offset(1, true);
+        }
+        self.close_unmatched_blocks();
+        self.add_child(BlockKind::BlockQuote);
+        BlockStart::Container
+    }
+
+    fn start_footnote_definition(&mut self, _container: usize) -> BlockStart {
+        if self.indented || !self.options.footnotes {
+            return BlockStart::None;
+        }
+        let Some((label, marker_len)) = scan_footnote_definition(&self.line[self.next_nonspace..]) else {
+            return BlockStart::None;
+        };
+
+        self.advance_next_nonspace();
+        self.advance_offset(marker_len, false);
+        self.close_unmatched_blocks();
+        self.add_child(BlockKind::FootnoteDefinition { label });
+        BlockStart::Container
+    }
+
+    fn start_atx_heading(&mut self, _container: usize) -> BlockStart {
+        if self.indented {
+            return BlockStart::None;
+        }
+
+        let rest = &self.line.as_bytes()[self.next_nonspace..];
+        let level = rest.iter().take_while(|&&b| b == b'#').count();
+        if level == 0 || level > 6 || !matches!(rest.get(level), None | Some(b' ') | Some(b'\t')) {
+            return BlockStart::None;
+        }
+        let marker_len = level + rest[level..].iter().take_while(|&&b| b == b' ' || b == b'\t').count();
+
+        self.advance_next_nonspace();
+        self.advance_offset(marker_len, false);
+        self.close_unmatched_blocks();
+
+        let text = strip_closing_hashes(&self.line[self.offset..]).to_string();
+        let heading = self.add_child(BlockKind::Heading { level: level as u8 });
+        self.blocks[heading].content = text;
+        self.advance_offset(self.line.len() - self.offset, false);
+        BlockStart::Leaf
+    }
+
+    fn start_fenced_code(&mut self, _container: usize) -> BlockStart {
+        if self.indented {
+            return BlockStart::None;
+        }
+
+        let rest = &self.line.as_bytes()[self.next_nonspace..];
+        let ch = match rest.first() {
+            Some(&c) if c == b'`' || c == b'~' => c,
+            _ => return BlockStart::None,
+        };
+        let length = rest.iter().take_while(|&&b| b == ch).count();
+        if length < 3 || (ch == b'`' && rest[length..].contains(&b'`')) {
+            return BlockStart::None;
+        }
+
+        self.close_unmatched_blocks();
+        let fence = CodeFence { ch, length, offset: self.indent };
+        self.add_child(BlockKind::CodeBlock { fence: Some(fence), info: String::new() });
+        self.advance_next_nonspace();
+        self.advance_offset(length, false);
+        BlockStart::Leaf
+    }
+
+    // `$$` at the start of a line opens display math that runs until a line
+    // ending in `$$`. Formulas opened and closed on the same line are left to
+    // the inline parser.
+    fn start_math_block(&mut self, _container: usize) -> BlockStart {
+        if self.indented || !self.options.math {
+            return BlockStart::None;
+        }
+        let Some(rest) = self.line[self.next_nonspace..].strip_prefix("$$") else {
+            return BlockStart::None;
+        };
+        if rest.contains("$$") {
+            return BlockStart::None;
+        }
+
+        self.close_unmatched_blocks();
+        self.add_child(BlockKind::MathBlock);
+        self.advance_next_nonspace();
+        self.advance_offset(2, false);
+        BlockStart::Leaf
+    }
+
+    fn start_html_block(&mut self, container: usize) -> BlockStart {
+        if self.indented || self.byte_at(self.next_nonspace) != Some(b'<') {
+            return BlockStart::None;
+        }
+
+        let rest = &self.line[self.next_nonspace..];
+        let lazy_paragraph = !self.all_closed && !self.blank && matches!(self.blocks[self.tip].kind, BlockKind::Paragraph);
+        let can_interrupt = !self.is_paragraph_like(container) && !lazy_paragraph;
+
+        for html_type in 1..=7u8 {
+            if html_block_starts(html_type, rest) && (html_type < 7 || can_interrupt) {
+                self.close_unmatched_blocks();
+                self.add_child(BlockKind::HtmlBlock { html_type });
+                return BlockStart::Leaf;
+            }
+        }
+        BlockStart::None
+    }
+
+    fn start_setext_heading(&mut self, container: usize) -> BlockStart {
+        if self.indented || !matches!(self.blocks[container].kind, BlockKind::Paragraph) {
+            return BlockStart::None;
+        }
+
+        let rest = self.line[self.next_nonspace..].trim_end_matches([' ', '\t']);
+        let level = match rest.as_bytes().first() {
+            Some(b'=') if rest.bytes().all(|b| b == b'=') => 1,
+            Some(b'-') if rest.bytes().all(|b| b == b'-') => 2,
+            _ => return BlockStart::None,
+        };
+
+        self.close_unmatched_blocks();
+        self.extract_reference_definitions(container);
+        if self.blocks[container].content.trim().is_empty() {
+            return BlockStart::None;
+        }
+
+        self.blocks[container].kind = BlockKind::Heading { level };
+        self.advance_offset(self.line.len() - self.offset, false);
+        BlockStart::Leaf
+    }
+
+    fn start_table(&mut self, container: usize) -> BlockStart {
+        if self.indented || !self.options.tables || !matches!(self.blocks[container].kind, BlockKind::Paragraph) {
+            return BlockStart::None;
+        }
+
+        let Some(alignments) = parse_table_delimiter_row(&self.line[self.next_nonspace..]) else {
+            return BlockStart::None;
+        };
+
+        // The last line of the open paragraph becomes the header row
+        let content = self.blocks[container].content.trim_end_matches('\n');
+        let (before, header) = match content.rfind('\n') {
+            Some(pos) => (&content[..pos + 1], &content[pos + 1..]),
+            None => ("", content),
+        };
+        if split_table_row(header).len() != alignments.len() {
+            return BlockStart::None;
+        }
+        let header = format!("{}\n", header);
+        let before = before.to_string();
+
+        self.close_unmatched_blocks();
+        if before.trim().is_empty() {
+            let block = &mut self.blocks[container];
+            block.kind = BlockKind::Table { alignments };
+            block.content = header;
+            block.start_line = self.line_number - 1;
+        } else {
+            self.blocks[container].content = before;
+            self.finalize(container, self.line_number - 2);
+            let table = self.add_child(BlockKind::Table { alignments });
+            self.blocks[table].content = header;
+            self.blocks[table].start_line = self.line_number - 1;
+        }
+
+        self.advance_offset(self.line.len() - self.offset, false);
+        BlockStart::Leaf
+    }
+
+    fn start_thematic_break(&mut self, _container: usize) -> BlockStart {
+        if self.indented || !is_thematic_break(&self.line[self.next_nonspace..]) {
+            return BlockStart::None;
+        }
+
+        self.close_unmatched_blocks();
+        self.add_child(BlockKind::ThematicBreak);
+        self.advance_offset(self.line.len() - self.offset, false);
+        BlockStart::Leaf
+    }
+
+    fn start_list_item(&mut self, container: usize) -> BlockStart {
+        let in_list = matches!(self.blocks[container].kind, BlockKind::List { .. });
+        if self.indented && !in_list {
+            return BlockStart::None;
+        }
+        let Some(data) = self.parse_list_marker(container) else {
+            return BlockStart::None;
+        };
+
+        self.close_unmatched_blocks();
+
+        let continues_list = match &self.blocks[self.tip].kind {
+            BlockKind::List { data: list_data, .. } => {
+                list_data.ordered == data.ordered
+                    && list_data.delimiter == data.delimiter
+                    && list_data.bullet_char == data.bullet_char
+            }
+            _ => false,
+        };
+        if !continues_list {
+            self.add_child(BlockKind::List { data: data.clone(), tight: true });
+        }
+
+        self.add_child(BlockKind::Item { data });
+        BlockStart::Container
+    }
+
+    fn start_indented_code(&mut self, _container: usize) -> BlockStart {
+        if !self.indented || self.is_paragraph_like(self.tip) || self.blank {
+            return BlockStart::None;
+        }
+
+        self.advance_offset(CODE_INDENT, true);
+        self.close_unmatched_blocks();
+        self.add_child(BlockKind::CodeBlock { fence: None, info: String::new() });
+        BlockStart::Leaf
+    }
+
+    fn parse_list_marker(&mut self, container: usize) -> Option<ListData> {
+        if self.indent >= CODE_INDENT {
+            return None;
+        }
+
+        let interrupts_paragraph = self.is_paragraph_like(container);
+        let rest = &self.line.as_bytes()[self.next_nonspace..];
+        let mut data = ListData {
+            ordered: false,
+            bullet_char: 0,
+            start: 1,
+            delimiter: 0,
+            marker_offset: self.indent,
+            padding: 0,
+        };
+
+        let marker_len = match rest.first() {
+            Some(&c) if matches!(c, b'*' | b'+' | b'-') => {
+                data.bullet_char = c;
+                1
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+                let delimiter = *rest.get(digits)?;
+                if digits > 9 || !matches!(delimiter, b'.' | b')') {
+                    return None;
+                }
+                let start: u32 = std::str::from_utf8(&rest[..digits]).ok()?.parse().ok()?;
+                // Only lists starting at 1 may interrupt a paragraph
+                if interrupts_paragraph && start != 1 {
+                    return None;
+                }
+                data.ordered = true;
+                data.start = start;
+                data.delimiter = delimiter;
+                digits + 1
+            }
+            _ => return None,
+        };
+
+        if !matches!(rest.get(marker_len), None | Some(b' ') | Some(b'\t')) {
+            return None;
+        }
+
+        // An empty list item cannot interrupt a paragraph
+        if interrupts_paragraph && rest[marker_len..].iter().all(|&b| b == b' ' || b == b'\t') {
+            return None;
+        }
+
+        self.advance_next_nonspace();
+        self.advance_offset(marker_len, true);
+        let spaces_start_column = self.column;
+        let spaces_start_offset = self.offset;
+        loop {
+            self.advance_offset(1, true);
+            let next = self.byte_at(self.offset);
+            if self.column - spaces_start_column >= 5 || !matches!(next, Some(b' ') | Some(b'\t')) {
+                break;
+            }
+        }
+
+        let blank_item = self.byte_at(self.offset).is_none();
+        let spaces_after_marker = self.column - spaces_start_column;
+        if !(1..5).contains(&spaces_after_marker) || blank_item {
+            // Content starting with five or more spaces is an indented code block
+            data.padding = marker_len + 1;
+            self.column = spaces_start_column;
+            self.offset = spaces_start_offset;
+            if matches!(self.byte_at(self.offset), Some(b' ') | Some(b'\t')) {
+                self.advance_offset(1, true);
+            }
+        } else {
+            data.padding = marker_len + spaces_after_marker;
+        }
+
+        Some(data)
+    }
+
+    fn finalize(&mut self, index: usize, line_number: usize) {
+        let parent = self.blocks[index].parent;
+        self.blocks[index].open = false;
+        self.blocks[index].end_line = line_number;
+
+        match &self.blocks[index].kind {
+            BlockKind::CodeBlock { fence: Some(_), .. } => {
+                let content = std::mem::take(&mut self.blocks[index].content);
+                let (first_line, rest) = content.split_once('\n').unwrap_or((&content, ""));
+                let info = unescape_string(first_line.trim());
+                let rest = rest.to_string();
+                let block = &mut self.blocks[index];
+                block.kind = BlockKind::CodeBlock {
+                    fence: match &block.kind {
+                        BlockKind::CodeBlock { fence, .. } => *fence,
+                        _ => None,
+                    },
+                    info,
+                };
+                block.content = rest;
+            }
+            BlockKind::CodeBlock { fence: None, .. } => {
+                let block = &mut self.blocks[index];
+                let mut lines: Vec<&str> = block.content.split('\n').collect();
+                while lines.last().is_some_and(|line| line.trim_matches([' ', '\t']).is_empty()) {
+                    lines.pop();
+                }
+                block.end_line = block.start_line + lines.len().saturating_sub(1);
+                let mut literal = lines.join("\n");
+                literal.push('\n');
+                block.content = literal;
+            }
+            BlockKind::Paragraph => self.extract_reference_definitions(index),
+            BlockKind::HtmlBlock { .. } => {
+                let block = &mut self.blocks[index];
+                if block.content.ends_with('\n') {
+                    block.content.pop();
+                }
+            }
+            BlockKind::Item { .. } | BlockKind::FootnoteDefinition { .. } => {
+                let block = &self.blocks[index];
+                let end_line = match block.children.last() {
+                    Some(&last) => self.blocks[last].end_line,
+                    None => block.start_line,
+                };
+                self.blocks[index].end_line = end_line;
+            }
+            BlockKind::List { .. } => {
+                let tight = self.list_is_tight(index);
+                if let Some(&last) = self.blocks[index].children.last() {
+                    self.blocks[index].end_line = self.blocks[last].end_line;
+                }
+                if let BlockKind::List { tight: list_tight, .. } = &mut self.blocks[index].kind {
+                    *list_tight = tight;
+                }
+            }
+            _ => {}
+        }
+
+        self.tip = parent.unwrap_or(DOCUMENT);
+    }
+
+    // A list is loose when any of its items, or any blocks directly inside an
+    // item, are separated by a blank line.
+    fn list_is_tight(&self, list: usize) -> bool {
+        let items = &self.blocks[list].children;
+        for (i, &item) in items.iter().enumerate() {
+            if let Some(&next) = items.get(i + 1) {
+                if self.blocks[item].end_line + 1 != self.blocks[next].start_line {
+                    return false;
+                }
+            }
+
+            let children = &self.blocks[item].children;
+            for pair in children.windows(2) {
+                if self.blocks[pair[0]].end_line + 1 != self.blocks[pair[1]].start_line {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    fn add_child(&mut self, kind: BlockKind) -> usize {
+        while !can_contain(&self.blocks[self.tip].kind, &kind) {
+            self.finalize(self.tip, self.line_number - 1);
+        }
+
+        let index = self.blocks.len();
+        self.blocks.push(Block {
+            kind,
+            parent: Some(self.tip),
+            children: Vec::new(),
+            open: true,
+            content: String::new(),
+            start_line: self.line_number,
+            end_line: self.line_number,
+            definition_lines: 0,
+            clean_start: self.tip == DOCUMENT && self.clean_start,
+        });
+        self.blocks[self.tip].children.push(index);
+        self.tip = index;
+        index
+    }
+
+    fn close_unmatched_blocks(&mut self) {
+        if self.all_closed {
+            return;
+        }
+        while self.old_tip != self.last_matched_container {
+            let parent = self.blocks[self.old_tip].parent.unwrap_or(DOCUMENT);
+            self.finalize(self.old_tip, self.line_number - 1);
+            self.old_tip = parent;
+        }
+        self.all_closed = true;
+    }
+
+    // Moves link reference definitions at the start of a paragraph into
+    // `references`; a paragraph of only definitions ends up empty
+    fn extract_reference_definitions(&mut self, index: usize) {
+        let block = &mut self.blocks[index];
+        if !block.content.starts_with('[') {
+            return;
+        }
+
+        let mut consumed = 0;
+        while let Some((label, reference, len)) = inline::parse_reference_definition(&block.content[consumed..]) {
+            self.references.push((index, label, reference));
+            consumed += len;
+        }
+        if consumed > 0 {
+            self.definitions.push(block.content[..consumed].trim().to_string());
+            let definition_lines = block.content[..consumed].matches('\n').count();
+            block.definition_lines += definition_lines;
+            block.start_line += definition_lines;
+            block.content.drain(..consumed);
+        }
+    }
+
+    fn add_line(&mut self) {
+        if self.partially_consumed_tab {
+            // Skip over the tab and add the columns it still covers
+            self.offset += 1;
+            let chars_to_tab = 4 - (self.column % 4);
+            self.blocks[self.tip].content.push_str(&" ".repeat(chars_to_tab));
+        }
+        let rest = self.line.get(self.offset..).unwrap_or("");
+        let content = &mut self.blocks[self.tip].content;
+        content.push_str(rest);
+        content.push('\n');
+    }
+
+    fn find_next_nonspace(&mut self) {
+        let bytes = self.line.as_bytes();
+        let mut i = self.offset;
+        let mut columns = self.column;
+
+        while let Some(&b) = bytes.get(i) {
+            match b {
+                b' ' => {
+                    i += 1;
+                    columns += 1;
+                }
+                b'\t' => {
+                    i += 1;
+                    columns += 4 - (columns % 4);
+                }
+                _ => break,
+            }
+        }
+
+        self.blank = i >= bytes.len();
+        self.next_nonspace = i;
+        self.next_nonspace_column = columns;
+        self.indent = columns - self.column;
+        self.indented = self.indent >= CODE_INDENT;
+    }
+
+    fn advance_next_nonspace(&mut self) {
+        self.offset = self.next_nonspace;
+        self.column = self.next_nonspace_column;
+        self.partially_consumed_tab = false;
+    }
+
+    // Advances by `count` bytes, or by `count` columns when `columns` is set,
+    // in which case a tab may be only partially consumed.
+    fn advance_offset(&mut self, mut count: usize, columns: bool) {
+        let bytes = self.line.as_bytes();
+        while count > 0 {
+            let Some(&b) = bytes.get(self.offset) else {
+                break;
+            };
+            if b == b'\t' {
+                let chars_to_tab = 4 - (self.column % 4);
+                if columns {
+                    self.partially_consumed_tab = chars_to_tab > count;
+                    let chars_to_advance = chars_to_tab.min(count);
+                    self.column += chars_to_advance;
+                    if !self.partially_consumed_tab {
+                        self.offset += 1;
+                    }
+                    count -= chars_to_advance;
+                } else {
+                    self.partially_consumed_tab = false;
+                    self.column += chars_to_tab;
+                    self.offset += 1;
+                    count -= 1;
+                }
+            } else {
+                self.partially_consumed_tab = false;
+                self.offset += 1;
+                self.column += 1;
+                count -= 1;
+            }
+        }
+    }
+
+    #[inline]
+    fn byte_at(&self, index: usize) -> Option<u8> {
+        self.line.as_bytes().get(index).copied()
+    }
+
+    #[inline]
+    fn accepts_lines(&self, index: usize) -> bool {
+        matches!(
+            self.blocks[index].kind,
+            BlockKind::Paragraph
+                | BlockKind::CodeBlock { .. }
+                | BlockKind::MathBlock
+                | BlockKind::HtmlBlock { .. }
+                | BlockKind::Table { .. }
+        )
+    }
+
+    // Tables are continued like paragraphs: new blocks may start inside them,
+    // under the same rules that govern interrupting a paragraph.
+    #[inline]
+    fn is_paragraph_like(&self, index: usize) -> bool {
+        matches!(self.blocks[index].kind, BlockKind::Paragraph | BlockKind::Table { .. })
+    }
+}
+
+#[inline]
+fn can_contain(parent: &BlockKind, child: &BlockKind) -> bool {
+    match parent {
+        BlockKind::Document
+        | BlockKind::BlockQuote
+        | BlockKind::Item { .. }
+        | BlockKind::FootnoteDefinition { .. } => !matches!(child, BlockKind::Item { .. }),
+        BlockKind::List { .. } => matches!(child, BlockKind::Item { .. }),
+        _ => false,
+    }
+}
+
+#[inline]
+fn is_maybe_special(byte: Option<u8>) -> bool {
+    matches!(
+        byte,
+        Some(b'#' | b'`' | b'~' | b'*' | b'+' | b'_' | b'=' | b'<' | b'>' | b'-' | b'|' | b':' | b'[' | b'$' | b'0'..=b'9')
+    )
+}
+
+fn parse_blocks(lines: &[&str], options: ParserOptions) -> BlockParser {
+    let mut block_parser = BlockParser::new(options);
+    for line in lines {
+        block_parser.incorporate_line(line);
+    }
+    block_parser.finish();
+    block_parser
+}
+
+// A top-level block of the stored document with the blocks inside it. They
+// are kept between renders, so an edit only has to parse the blocks around it.
+struct StoredBlock {
+    // The top-level block comes first, then the blocks inside it in document
+    // order, indexed from the first
+    blocks: Vec<Block>,
+    // Hash of the block's kind and of its source lines, definitions included
+    source_hash: u64,
+    // Whether the source mentions footnotes or links, whose output depends on
+    // the rest of the document
+    mentions_footnotes: bool,
+    mentions_links: bool,
+    // Link reference definitions in the block, in order
+    references: Vec<(String, LinkReference)>,
+    // Footnote definitions in the block by label, with their index in `blocks`
+    footnote_definitions: Vec<(String, usize)>,
+}
+
+impl StoredBlock {
+    // First source line, reference definitions included
+    fn first_line(&self) -> usize {
+        self.blocks[0].start_line - self.blocks[0].definition_lines
+    }
+
+    fn shift(&mut self, delta: isize) {
+        for block in &mut self.blocks {
+            block.start_line = block.start_line.wrapping_add_signed(delta);
+            block.end_line = block.end_line.wrapping_add_signed(delta);
+        }
+    }
+}
+
+// Splits what was parsed from `lines` into one `StoredBlock` per top-level
+// block. Every top-level block and the blocks inside it take up a run of the
+// arena, since nothing is added to a block once the next one has started.
+fn store_blocks(block_parser: BlockParser, lines: &[&str]) -> Vec<StoredBlock> {
+    let BlockParser { mut blocks, references, .. } = block_parser;
+    let roots = std::mem::take(&mut blocks[DOCUMENT].children);
+
+    let mut root_references = vec![Vec::new(); roots.len()];
+    for (index, label, reference) in references {
+        let root = roots.partition_point(|&root| root <= index) - 1;
+        root_references[root].push((label, reference));
+    }
+
+    let mut stored = Vec::with_capacity(roots.len());
+    for (&root, references) in roots.iter().zip(root_references).rev() {
+        let mut subtree = blocks.split_off(root);
+        let mut footnote_definitions = Vec::new();
+        for (index, block) in subtree.iter_mut().enumerate() {
+            block.parent = block.parent.filter(|&parent| parent != DOCUMENT).map(|parent| parent - root);
+            for child in &mut block.children {
+                *child -= root;
+            }
+            if let BlockKind::FootnoteDefinition { label } = &block.kind {
+                footnote_definitions.push((label.clone(), index));
+            }
+        }
+
+        let first_line = subtree[0].start_line - subtree[0].definition_lines;
+        let source = &lines[first_line - 1..subtree[0].end_line.min(lines.len())];
+        let mut hasher = DefaultHasher::new();
+        std::mem::discriminant(&subtree[0].kind).hash(&mut hasher);
+        source.hash(&mut hasher);
+
+        stored.push(StoredBlock {
+            source_hash: hasher.finish(),
+            mentions_footnotes: source.iter().any(|line| line.contains("[^")),
+            mentions_links: source.iter().any(|line| line.contains('[')),
+            references,
+            footnote_definitions,
+            blocks: subtree,
+        });
+    }
+    stored.reverse();
+    stored
+}
+
+// Parses `lines` into top-level blocks, reusing the blocks of `previous`, which
+// were parsed from `previous_lines`, away from the lines that changed. Parsing
+// starts over at the last block before the change that began with nothing
+// left open, and stops once it reaches such a block of `previous` after the
+// change with nothing left open either; from there on the previous blocks
+// are the same, only moved.
+fn reparse(
+    mut previous: Vec<StoredBlock>,
+    previous_lines: &[&str],
+    lines: &[&str],
+    options: ParserOptions,
+) -> Vec<StoredBlock> {
+    let unchanged_before = if previous.is_empty() {
+        0
+    } else {
+        previous_lines.iter().zip(lines).take_while(|(old, new)| old == new).count()
+    };
+    if unchanged_before == lines.len() && unchanged_before == previous_lines.len() && !previous.is_empty() {
+        return previous;
+    }
+    let unchanged_after = previous_lines[unchanged_before..]
+        .iter()
+        .rev()
+        .zip(lines[unchanged_before..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    // Last changed line of the new document, before the first changed one
+    // when lines were only removed
+    let changed_end = lines.len() - unchanged_after;
+    let delta = lines.len() as isize - previous_lines.len() as isize;
+
+    let candidates = previous.partition_point(|block| block.first_line() <= unchanged_before + 1);
+    let restart = (0..candidates).rev().find(|&index| previous[index].blocks[0].clean_start);
+    let (kept, first_line) = match restart {
+        Some(index) => (index, previous[index].first_line()),
+        None => (0, 1),
+    };
+    let following = previous.split_off(kept);
+
+    let mut block_parser = BlockParser::new(options);
+    block_parser.line_number = first_line - 1;
+    let mut next = 0;
+    let mut in_step = None;
+    for line_number in first_line..=lines.len() {
+        if line_number > changed_end {
+            let previous_line = line_number.wrapping_add_signed(-delta);
+            while following.get(next).is_some_and(|block| block.first_line() < previous_line) {
+                next += 1;
+            }
+            let starts_block = following
+                .get(next)
+                .is_some_and(|block| block.first_line() == previous_line && block.blocks[0].clean_start);
+            if starts_block && block_parser.at_clean_start() {
+                in_step = Some(next);
+                break;
+            }
+        }
+        block_parser.incorporate_line(lines[line_number - 1]);
+    }
+    block_parser.finish();
+
+    previous.extend(store_blocks(block_parser, lines));
+    if let Some(next) = in_step {
+        for mut block in following.into_iter().skip(next) {
+            block.shift(delta);
+            previous.push(block);
+        }
+    }
+    previous
+}
+
+fn split_lines(markdown: &str) -> Vec<&str> {
+    let mut lines = Vec::with_capacity(markdown.len() / 40 + 1);
+    let bytes = markdown.as_bytes();
+    let mut start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                lines.push(&markdown[start..i]);
+                i += 1;
+                start = i;
             }
-            "`" => {
-                while let Some(&ch) = chars.peek() {
-                    if ch == '`' {
-                        chars.next();
-                        return Some(content);
-                    }
-                    chars.next();
-                    content.push(ch);
-                    if content.len() > max_len { return None; }
+            b'\r' => {
+                lines.push(&markdown[start..i]);
+                i += if bytes.get(i + 1) == Some(&b'\n') { 2 } else { 1 };
+                start = i;
+            }
+            _ => i += 1,
+        }
+    }
+
+    // A trailing line ending does not start another (empty) line
+    if start < bytes.len() {
+        lines.push(&markdown[start..]);
+    }
+    lines
+}
+
+pub fn is_thematic_break(line: &str) -> bool {
+    let mut marker = None;
+    let mut count = 0;
+
+    for b in line.bytes() {
+        match b {
+            b' ' | b'\t' => continue,
+            b'*' | b'-' | b'_' => {
+                if marker.is_some_and(|m| m != b) {
+                    return false;
                 }
+                marker = Some(b);
+                count += 1;
             }
-            _ => return None,
+            _ => return false,
         }
+    }
+    count >= 3
+}
 
-        None
+// Matches the `[^label]:` that opens a footnote definition, returning the
+// normalized label and the length of the marker.
+fn scan_footnote_definition(line: &str) -> Option<(String, usize)> {
+    let rest = line.strip_prefix("[^")?;
+    let close = rest.find(']')?;
+    let label = &rest[..close];
+    if label.is_empty() || label.contains(|ch: char| ch.is_whitespace() || ch == '[') {
+        return None;
     }
+    if rest.as_bytes().get(close + 1) != Some(&b':') {
+        return None;
+    }
+    Some((normalize_label(label), close + 4))
+}
 
-    fn extract_link(&self, chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<(String, String)> {
-        let mut link_text = String::with_capacity(32);
-        
-        while let Some(&ch) = chars.peek() {
-            chars.next();
-            if ch == ']' {
-                break;
+fn strip_closing_hashes(text: &str) -> &str {
+    let trimmed = text.trim_end_matches([' ', '\t']);
+    let without_hashes = trimmed.trim_end_matches('#');
+
+    if without_hashes.is_empty() {
+        return "";
+    }
+    if without_hashes.len() == trimmed.len() {
+        return trimmed;
+    }
+    // The closing sequence must be preceded by a space or tab
+    if without_hashes.ends_with([' ', '\t']) {
+        without_hashes.trim_end_matches([' ', '\t'])
+    } else {
+        trimmed
+    }
+}
+
+const HTML_BLOCK_TAGS: &[&str] = &[
+    "address", "article", "aside", "base", "basefont", "blockquote", "body", "caption", "center",
+    "col", "colgroup", "dd", "details", "dialog", "dir", "div", "dl", "dt", "fieldset",
+    "figcaption", "figure", "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5",
+    "h6", "head", "header", "hr", "html", "iframe", "legend", "li", "link", "main", "menu",
+    "menuitem", "nav", "noframes", "ol", "optgroup", "option", "p", "param", "search", "section",
+    "summary", "table", "tbody", "td", "tfoot", "th", "thead", "title", "tr", "track", "ul",
+];
+
+const HTML_RAW_TAGS: &[&str] = &["script", "pre", "textarea", "style"];
+
+fn html_block_starts(html_type: u8, line: &str) -> bool {
+    let bytes = line.as_bytes();
+    match html_type {
+        1 => {
+            let name_len = bytes[1..].iter().take_while(|b| b.is_ascii_alphabetic()).count();
+            let name = line[1..1 + name_len].to_ascii_lowercase();
+            HTML_RAW_TAGS.contains(&name.as_str())
+                && matches!(bytes.get(1 + name_len), None | Some(b' ' | b'\t' | b'>'))
+        }
+        2 => line.starts_with("<!--"),
+        3 => line.starts_with("<?"),
+        4 => bytes.len() > 2 && bytes[1] == b'!' && bytes[2].is_ascii_alphabetic(),
+        5 => line.starts_with("<![CDATA["),
+        6 => {
+            let name_start = if bytes.get(1) == Some(&b'/') { 2 } else { 1 };
+            let name_len = bytes[name_start..].iter().take_while(|b| b.is_ascii_alphanumeric()).count();
+            let name = line[name_start..name_start + name_len].to_ascii_lowercase();
+            if !HTML_BLOCK_TAGS.contains(&name.as_str()) {
+                return false;
+            }
+            match bytes.get(name_start + name_len) {
+                None | Some(b' ' | b'\t' | b'>') => true,
+                Some(b'/') => bytes.get(name_start + name_len + 1) == Some(&b'>'),
+                _ => false,
             }
-            link_text.push(ch);
-            if link_text.len() > 200 {
-                return None;
+        }
+        7 => {
+            let name_start = if bytes.get(1) == Some(&b'/') { 2 } else { 1 };
+            let name_len = bytes[name_start..].iter().take_while(|b| b.is_ascii_alphanumeric() || **b == b'-').count();
+            let name = line[name_start..name_start + name_len].to_ascii_lowercase();
+            if HTML_RAW_TAGS.contains(&name.as_str()) {
+                return false;
             }
+            match inline::scan_open_or_closing_tag(bytes) {
+                Some(len) => bytes[len..].iter().all(|&b| b == b' ' || b == b'\t'),
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+fn html_block_ends(html_type: u8, line: &str) -> bool {
+    match html_type {
+        1 => {
+            let lower = line.to_ascii_lowercase();
+            HTML_RAW_TAGS.iter().any(|tag| lower.contains(&format!("</{}>", tag)))
         }
+        2 => line.contains("-->"),
+        3 => line.contains("?>"),
+        4 => line.contains('>'),
+        5 => line.contains("]]>"),
+        _ => false,
+    }
+}
+
+fn parse_table_delimiter_row(line: &str) -> Option<Vec<Alignment>> {
+    let trimmed = line.trim();
+    if !trimmed.contains('|') {
+        return None;
+    }
 
-        if chars.peek() != Some(&'(') {
+    let mut alignments = Vec::new();
+    for cell in split_table_row(trimmed) {
+        let starts_colon = cell.starts_with(':');
+        let ends_colon = cell.len() > 1 && cell.ends_with(':');
+        let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+        if dashes.is_empty() || !dashes.bytes().all(|b| b == b'-') {
             return None;
         }
-        chars.next();
 
-        let mut url = String::with_capacity(128);
-        
-        while let Some(&ch) = chars.peek() {
-            chars.next();
-            if ch == ')' {
-                return Some((link_text, url));
+        alignments.push(match (starts_colon, ends_colon) {
+            (true, true) => Alignment::Center,
+            (false, true) => Alignment::Right,
+            (true, false) => Alignment::Left,
+            (false, false) => Alignment::None,
+        });
+    }
+    Some(alignments)
+}
+
+// Splits a table row on unescaped pipes, dropping the optional outer pipes.
+pub fn split_table_row(line: &str) -> Vec<String> {
+    let mut row = line.trim();
+    if let Some(stripped) = row.strip_prefix('|') {
+        row = stripped;
+    }
+    if row.ends_with('|') && !row.ends_with("\\|") {
+        row = &row[..row.len() - 1];
+    }
+
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut chars = row.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' if chars.peek() == Some(&'|') => {
+                cell.push('|');
+                chars.next();
+            }
+            '|' => cells.push(std::mem::take(&mut cell).trim().to_string()),
+            _ => cell.push(ch),
+        }
+    }
+    cells.push(cell.trim().to_string());
+    cells
+}
+
+// `Some` when every line of a paragraph is a single `![[...]]`
+fn parse_embeds(text: &str) -> Option<Vec<WikiLink>> {
+    if !text.starts_with("![[") {
+        return None;
+    }
+    text.lines()
+        .map(|line| {
+            let inner = line.trim().strip_prefix("![[")?.strip_suffix("]]")?;
+            WikiLink::parse(inner)
+        })
+        .collect()
+}
+
+// Source of the section under the heading whose anchor matches `heading`,
+// up to the next heading of the same or a higher level
+fn heading_section(markdown: &str, heading: &str, options: ParserOptions) -> Option<String> {
+    let mut lines = split_lines(markdown);
+    if let Some(front_matter) = parse_front_matter(&lines).filter(|_| options.front_matter) {
+        lines[..front_matter.line_count].fill("");
+    }
+    let block_parser = parse_blocks(&lines, options);
+    let blocks = &block_parser.blocks;
+    let slug = slugify(heading);
+
+    let mut section: Option<(u8, usize)> = None;
+    for &index in &blocks[DOCUMENT].children {
+        let BlockKind::Heading { level } = blocks[index].kind else {
+            continue;
+        };
+        match section {
+            None if slugify(blocks[index].content.trim()) == slug => {
+                section = Some((level, blocks[index].start_line));
             }
-            url.push(ch);
-            if url.len() > 500 {
-                return None;
+            Some((section_level, start_line)) if level <= section_level => {
+                return Some(lines[start_line - 1..blocks[index].start_line - 1].join("\n"));
             }
+            _ => {}
         }
+    }
+    section.map(|(_, start_line)| lines[start_line - 1..].join("\n"))
+}
 
-        None
+// Source of the top-level block whose last line ends with ` ^id`, the way
+// Obsidian marks blocks for `[[Note#^id]]`, without the marker
+fn block_reference(markdown: &str, id: &str, options: ParserOptions) -> Option<String> {
+    let mut lines = split_lines(markdown);
+    if let Some(front_matter) = parse_front_matter(&lines).filter(|_| options.front_matter) {
+        lines[..front_matter.line_count].fill("");
     }
+    let marker = format!("^{}", id);
+    let block_parser = parse_blocks(&lines, options);
+    let blocks = &block_parser.blocks;
+
+    blocks[DOCUMENT].children.iter().find_map(|&index| {
+        let source = &lines[blocks[index].start_line - 1..blocks[index].end_line.min(lines.len())];
+        let last = source.iter().rposition(|line| !line.trim().is_empty())?;
+        let text = source[last].trim_end().strip_suffix(&marker)?;
+        if !text.ends_with([' ', '\t']) {
+            return None;
+        }
+        let mut source = source[..=last].to_vec();
+        source[last] = text.trim_end();
+        Some(source.join("\n"))
+    })
+}
+
+// Prefixes the ids in an embedded note's HTML, and the `#...` links to them
+fn prefix_anchors(html: &str, prefix: &str) -> String {
+    html.replace(" id=\"", &format!(" id=\"{}", prefix))
+        .replace(" href=\"#", &format!(" href=\"#{}", prefix))
+}
 
+#[inline]
+fn source_line_attributes(start_line: usize, end_line: usize) -> String {
+    format!(" data-source-line=\"{}\" data-source-end-line=\"{}\"", start_line, end_line)
+}
 
-    #[inline]
-    fn escape_html(&self, text: &str) -> String {
-        if !text.as_bytes().iter().any(|&b| matches!(b, b'&' | b'<' | b'>' | b'"' | b'\'')) {
-            return text.to_string();
+// Moves the line numbers of a cached block, both in its source map and in
+// the `data-source-*` attributes of its HTML, so it starts at `start_line`.
+fn shift_source_lines(rendered: &mut RenderedBlock, start_line: usize) {
+    let previous_start = rendered.start_line;
+    let shift = |line: usize| line - previous_start + start_line;
+
+    let mut html = String::with_capacity(rendered.html.len());
+    let mut rest = rendered.html.as_str();
+    while let Some(pos) = rest.find(" data-source-") {
+        let attribute_end = rest[pos..].find("=\"").map(|index| pos + index + 2).unwrap_or(rest.len());
+        html.push_str(&rest[..attribute_end]);
+        rest = &rest[attribute_end..];
+
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        if let Ok(line) = rest[..digits].parse::<usize>() {
+            html.push_str(&shift(line).to_string());
+            rest = &rest[digits..];
         }
-        
-        let mut result = String::with_capacity(text.len() + (text.len() >> 3));
-        for ch in text.chars() {
-            match ch {
-                '&' => result.push_str("&amp;"),
-                '<' => result.push_str("&lt;"),
-                '>' => result.push_str("&gt;"),
-                '"' => result.push_str("&quot;"),
-                '\'' => result.push_str("&#x27;"),
-                _ => result.push(ch),
+    }
+    html.push_str(rest);
+
+    for entry in &mut rendered.source_map {
+        entry.start_line = shift(entry.start_line);
+        entry.end_line = shift(entry.end_line);
+    }
+    for entry in &mut rendered.toc {
+        entry.line = shift(entry.line);
+    }
+    rendered.html = html;
+    rendered.start_line = start_line;
+}
+
+// Gives the headings of a rendered block anchors that are unique in the
+// document so far, patching the ones it was rendered or cached with
+fn assign_anchors(rendered: &mut RenderedBlock, slugs: &mut HashMap<String, usize>) {
+    for entry in &mut rendered.toc {
+        let slug = unique_slug(slugs, &slugify(&entry.text));
+        if slug != entry.slug {
+            let attributes = |slug: &str| format!(" id=\"{}\" data-source-line=\"{}\"", slug, entry.line);
+            let previous = attributes(&entry.slug);
+            if let Some(position) = rendered.html.find(&previous) {
+                rendered.html.replace_range(position..position + previous.len(), &attributes(&slug));
             }
+            entry.slug = slug;
         }
-        result
     }
 }
 
-use crate::markdown::parser::ParseResult;
-
-pub fn parse_gfm_markdown_to_html(markdown: &str) -> Result<ParseResult, String> {
-    let mut parser = GfmMarkdownParser::new();
-    let tokens = parser.parse(markdown);
-    
-    let mut html = String::with_capacity(markdown.len() + (markdown.len() >> 1));
-    let mut word_count = 0;
-    
-    for token in tokens {
-        match token {
-            GfmToken::Heading { level, text } => {
-                word_count += count_words(&text);
-                html.push_str(&format!("<h{0}>{1}</h{0}>", level, text));
-            },
-            GfmToken::Paragraph(text) => {
-                word_count += count_words(&text);
-                html.push_str(&format!("<p>{}</p>", text));
-            },
-            GfmToken::CodeBlock { language, code } => {
-                word_count += count_words(&code);
-                if let Some(lang) = language {
-                    html.push_str(&format!("<pre><code class=\"language-{}\">{}</code></pre>", lang, code));
-                } else {
-                    html.push_str(&format!("<pre><code>{}</code></pre>", code));
-                }
-            },
-            GfmToken::List { items, ordered } => {
-                let tag = if ordered { "ol" } else { "ul" };
-                html.push_str(&format!("<{}>", tag));
-                
-                render_gfm_list_items(&items, &mut html, &mut word_count);
-                
-                html.push_str(&format!("</{}>", tag));
-            },
-            GfmToken::Table { headers, rows, alignments } => {
-                html.push_str("<table>");
-                
-                html.push_str("<thead><tr>");
-                for (i, header) in headers.iter().enumerate() {
-                    let align = get_align_style(&alignments, i);
-                    html.push_str(&format!("<th{}>{}</th>", align, header));
-                    word_count += count_words(header);
-                }
-                html.push_str("</tr></thead>");
-                
-                if !rows.is_empty() {
-                    html.push_str("<tbody>");
-                    for row in rows {
-                        html.push_str("<tr>");
-                        for (i, cell) in row.iter().enumerate() {
-                            let align = get_align_style(&alignments, i);
-                            html.push_str(&format!("<td{}>{}</td>", align, cell));
-                            word_count += count_words(cell);
-                        }
-                        html.push_str("</tr>");
-                    }
-                    html.push_str("</tbody>");
-                }
-                
-                html.push_str("</table>");
-            },
-            GfmToken::Blockquote(text) => {
-                word_count += count_words(&text);
-                html.push_str(&format!("<blockquote><p>{}</p></blockquote>", text));
-            },
-            GfmToken::Alert { alert_type, content } => {
-                word_count += count_words(&content);
-                let (class, icon, title) = get_alert_config(&alert_type);
-                html.push_str(&format!(
-                    "<div class=\"alert alert-{}\"><div class=\"alert-icon\">{}</div><div class=\"alert-content\"><div class=\"alert-title\">{}</div><p>{}</p></div></div>", 
-                    class, icon, title, content
-                ));
-            },
-            GfmToken::HorizontalRule => {
-                html.push_str("<hr>");
-            },
+// Repeated anchors get `-1`, `-2`, ... appended, the way GitHub does it
+fn unique_slug(slugs: &mut HashMap<String, usize>, base: &str) -> String {
+    let mut slug = base.to_string();
+    while slugs.contains_key(&slug) {
+        let repeats = slugs.entry(base.to_string()).or_insert(0);
+        *repeats += 1;
+        slug = format!("{}-{}", base, repeats);
+    }
+    slugs.insert(slug.clone(), 0);
+    slug
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn collect_tags(inlines: &[Inline], tags: &mut Vec<String>) {
+    for inline in inlines {
+        match inline {
+            Inline::Tag(tag) => tags.push(tag.clone()),
+            Inline::Emphasis(children)
+            | Inline::Strong(children)
+            | Inline::Strikethrough(children)
+            | Inline::Link { children, .. }
+            | Inline::Image { children, .. } => collect_tags(children, tags),
+            _ => {}
         }
     }
-    
-    Ok(ParseResult {
-        html,
-        word_count,
-        error: None,
-    })
+}
+
+// The `tags` property of front matter, either a list or a string of tags
+// separated by commas or spaces
+fn front_matter_tags(data: &serde_json::Value) -> Vec<String> {
+    let tags: Vec<&str> = match data.get("tags") {
+        Some(serde_json::Value::Array(items)) => items.iter().filter_map(|item| item.as_str()).collect(),
+        Some(serde_json::Value::String(text)) => text.split([',', ' ']).collect(),
+        _ => Vec::new(),
+    };
+    tags.into_iter()
+        .map(|tag| tag.trim().trim_start_matches('#'))
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Tags in order of first appearance; tags differing only in case are the
+// same tag, spelled as it first appeared
+// Error-level findings, one `Line N: message` per line
+fn lint_errors(diagnostics: &[Diagnostic]) -> Option<String> {
+    let errors: Vec<String> = diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(|diagnostic| format!("Line {}: {}", diagnostic.line, diagnostic.message))
+        .collect();
+    (!errors.is_empty()).then(|| errors.join("\n"))
+}
+
+fn unique_tags(tags: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    tags.into_iter().filter(|tag| seen.insert(tag.to_lowercase())).collect()
+}
+
+// Drops the `data-source-*` attributes from rendered HTML
+pub fn strip_source_lines(html: &str) -> String {
+    let mut stripped = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(pos) = rest.find(" data-source-") {
+        stripped.push_str(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        let value_end = rest
+            .find("=\"")
+            .and_then(|start| rest[start + 2..].find('"').map(|end| start + 2 + end + 1))
+            .unwrap_or(rest.len());
+        rest = &rest[value_end..];
+    }
+    stripped.push_str(rest);
+    stripped
 }
 
 #[inline]
-fn get_alert_config(alert_type: &AlertType) -> (&'static str, &'static str, &'static str) {
-    match alert_type {
-        AlertType::Note => (
-            "note", 
-            "ℹ️", 
-            "Note"
-        ),
-        AlertType::Tip => (
-            "tip", 
-            "💡", 
-            "Tip"
-        ),
-        AlertType::Important => (
-            "important", 
-            "❗", 
-            "Important"
-        ),
-        AlertType::Warning => (
-            "warning", 
-            "⚠️", 
-            "Warning"
-        ),
-        AlertType::Caution => (
-            "caution", 
-            "⛔", 
-            "Caution"
-        ),
+fn ensure_newline(html: &mut String) {
+    if !html.is_empty() && !html.ends_with('\n') {
+        html.push('\n');
+    }
+}
+
+// Built-in callout types as (type, colour class, icon): GitHub's five alerts
+// plus Obsidian's types and their aliases
+const BUILTIN_CALLOUTS: [(&str, &str, &str); 27] = [
+    ("note", "note", "ℹ️"),
+    ("tip", "tip", "💡"),
+    ("important", "important", "❗"),
+    ("warning", "warning", "⚠️"),
+    ("caution", "caution", "⛔"),
+    ("abstract", "tip", "📋"),
+    ("summary", "tip", "📋"),
+    ("tldr", "tip", "📋"),
+    ("info", "note", "ℹ️"),
+    ("todo", "note", "☑️"),
+    ("hint", "tip", "💡"),
+    ("success", "tip", "✅"),
+    ("check", "tip", "✅"),
+    ("done", "tip", "✅"),
+    ("question", "important", "❓"),
+    ("help", "important", "❓"),
+    ("faq", "important", "❓"),
+    ("attention", "warning", "⚠️"),
+    ("failure", "warning", "❌"),
+    ("fail", "warning", "❌"),
+    ("missing", "warning", "❌"),
+    ("danger", "caution", "⚡"),
+    ("error", "caution", "⚡"),
+    ("bug", "caution", "🐛"),
+    ("example", "note", "📝"),
+    ("quote", "note", "💬"),
+    ("cite", "note", "💬"),
+];
+
+// Splits `[!type]-  Title` into the lowercased type, the fold marker and the
+// title text
+fn parse_callout_marker(line: &str) -> Option<(String, Option<bool>, &str)> {
+    let rest = line.strip_prefix("[!")?;
+    let close = rest.find(']')?;
+    let kind = &rest[..close];
+    if kind.is_empty() || !kind.chars().all(|ch| ch.is_alphanumeric() || matches!(ch, '-' | '_')) {
+        return None;
+    }
+
+    let rest = &rest[close + 1..];
+    let (folded, title) = match rest.as_bytes().first() {
+        Some(b'-') => (Some(true), &rest[1..]),
+        Some(b'+') => (Some(false), &rest[1..]),
+        _ => (None, rest),
+    };
+    Some((kind.to_lowercase(), folded, title.trim()))
+}
+
+// Obsidian titles untitled callouts with their type, capitalized
+fn default_callout_title(kind: &str) -> String {
+    let mut chars = kind.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
     }
 }
 
@@ -951,87 +2800,733 @@ fn get_align_style(alignments: &[Alignment], index: usize) -> &'static str {
     }
 }
 
-fn render_gfm_list_items(items: &[GfmListItem], html: &mut String, word_count: &mut usize) {
-    if items.is_empty() {
-        return;
-    }
-    
-    let mut current_level = 0u8;
-    let mut stack = Vec::with_capacity(8);
-    
-    for (i, item) in items.iter().enumerate() {
-        *word_count += count_words(&item.content);
-        
-        // Adjust nesting level
-        if item.level > current_level {
-            // Going deeper - open new nested lists
-            for _ in current_level..item.level {
-                html.push_str("<ul>");
-                stack.push("</ul>");
-            }
-        } else if item.level < current_level {
-            // Going shallower - close nested lists
-            for _ in item.level..current_level {
-                html.push_str("</li>");
-                if let Some(close_tag) = stack.pop() {
-                    html.push_str(close_tag);
-                }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn edit(parser: &mut GfmMarkdownParser, start_line: usize, end_line: usize, lines: &[&str]) -> ParseResult {
+        let edit = TextEdit {
+            start_line,
+            end_line,
+            lines: lines.iter().map(|line| line.to_string()).collect(),
+        };
+        let version = parser.version;
+        parser.apply_edit(version, &edit).expect("edit should apply")
+    }
+
+    fn assert_matches_full_render(parser: &GfmMarkdownParser, result: &ParseResult) {
+        let full = GfmMarkdownParser::new().render_document(&parser.document);
+        assert_eq!(result.html, full.html, "incremental render differs for {:?}", parser.document);
+        assert_eq!(result.source_map, full.source_map, "source map differs for {:?}", parser.document);
+    }
+
+    // Lines that start, continue or interrupt most kinds of blocks
+    const EDIT_LINES: &[&str] = &[
+        "",
+        "",
+        "# Heading",
+        "## Heading",
+        "Setext",
+        "===",
+        "---",
+        "Some *text* with a [link][r] and a note[^1]",
+        "more text #tag",
+        "- item",
+        "  - nested item",
+        "- [ ] task",
+        "1. first",
+        "3) third",
+        "    indented",
+        "```rust",
+        "```",
+        "~~~",
+        "> quote",
+        "> [!note] Title",
+        "| a | b |",
+        "| - | - |",
+        "| 1 | 2 |",
+        "[r]: https://example.com",
+        "[r]: https://example.org \"title\"",
+        "[^1]: The note",
+        "[^2]: Another note",
+        "see[^2]",
+        "$$",
+        "x^2",
+        "<details>",
+        "</details>",
+        "[[Note]] and ![[Note]]",
+        "hard  ",
+        "\tTabbed",
+        "#### Deep heading",
+        "trailing \t",
+        "[broken][missing]",
+    ];
+
+    // xorshift, so the edit sequences are the same on every run
+    fn next(seed: &mut u64) -> usize {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed as usize
+    }
+
+    fn random_lines(seed: &mut u64, max: usize) -> Vec<&'static str> {
+        let count = next(seed) % (max + 1);
+        (0..count).map(|_| EDIT_LINES[next(seed) % EDIT_LINES.len()]).collect()
+    }
+
+    fn toc(result: &ParseResult) -> Vec<(String, usize)> {
+        result.toc.iter().map(|entry| (entry.slug.clone(), entry.line)).collect()
+    }
+
+    #[test]
+    fn random_edits_match_full_render() {
+        for mut seed in 1..=40u64 {
+            seed = seed.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            let block_output = seed % 2 == 0;
+            let mut parser = GfmMarkdownParser::new();
+            parser.set_block_output(block_output);
+            parser.render_document(&random_lines(&mut seed, 30).join("\n"));
+
+            for _ in 0..60 {
+                let line_count = parser.document.split('\n').count();
+                let start_line = next(&mut seed) % (line_count + 1);
+                let end_line = (start_line + next(&mut seed) % 4).min(line_count);
+                let lines = random_lines(&mut seed, 4);
+                let result = edit(&mut parser, start_line, end_line, &lines);
+
+                let full = GfmMarkdownParser::new().render_document(&parser.document);
+                let html = if block_output {
+                    result.blocks.iter().map(|block| block.html.as_str()).collect()
+                } else {
+                    result.html.clone()
+                };
+                assert_eq!(html, full.html, "incremental render differs for {:?}", parser.document);
+                assert_eq!(result.source_map, full.source_map, "source map differs for {:?}", parser.document);
+                assert_eq!(result.stats.words, full.stats.words, "stats differ for {:?}", parser.document);
+                assert_eq!(result.tags, full.tags, "tags differ for {:?}", parser.document);
+                assert_eq!(toc(&result), toc(&full), "toc differs for {:?}", parser.document);
+                assert_eq!(result.diagnostics, full.diagnostics, "diagnostics differ for {:?}", parser.document);
             }
-        } else if i > 0 {
-            // Same level as previous - close previous list item
-            html.push_str("</li>");
-        }
-        
-        current_level = item.level;
-        
-        // Add the list item with checkbox support
-        if let Some(checked) = item.checked {
-            let checkbox = if checked {
-                r#"<span class="task-list-item-checkbox checked">
-                    <svg width="16" height="16" viewBox="0 0 16 16" fill="none">
-                        <rect x="1" y="1" width="14" height="14" rx="3" fill="var(--theme-accent)" stroke="var(--theme-accent)" stroke-width="2"/>
-                        <path d="M4 8l2.5 2.5L12 5" stroke="white" stroke-width="2" stroke-linecap="round" stroke-linejoin="round"/>
-                    </svg>
-                  </span> "#
-            } else {
-                r#"<span class="task-list-item-checkbox unchecked">
-                    <svg width="16" height="16" viewBox="0 0 16 16" fill="none">
-                        <rect x="1" y="1" width="14" height="14" rx="3" fill="transparent" stroke="var(--theme-muted-foreground)" stroke-width="2"/>
-                    </svg>
-                  </span> "#
-            };
-            html.push_str(&format!("<li class=\"task-list-item\">{}{}", checkbox, item.content));
-        } else {
-            html.push_str(&format!("<li>{}", item.content));
         }
     }
-    
-    // Close the final list item
-    html.push_str("</li>");
-    
-    // Close all remaining nested lists
-    while let Some(close_tag) = stack.pop() {
-        html.push_str(close_tag);
+
+    fn render(markdown: &str) -> String {
+        strip_source_lines(&GfmMarkdownParser::new().render_document(markdown).html)
     }
-}
 
-#[inline]
-fn count_words(text: &str) -> usize {
-    if text.is_empty() {
-        return 0;
+    #[test]
+    fn nested_lists_keep_their_type_and_start() {
+        assert_eq!(
+            render("3. Step three\n   - note\n   - another\n4. Step four\n   1) sub\n   2) sub"),
+            "<ol start=\"3\">\n<li>Step three\n<ul>\n<li>note</li>\n<li>another</li>\n</ul>\n</li>\n\
+             <li>Step four\n<ol>\n<li>sub</li>\n<li>sub</li>\n</ol>\n</li>\n</ol>\n"
+        );
+        assert_eq!(
+            render("- a\n\n  7) seven\n  8) eight\n- b"),
+            "<ul>\n<li>\n<p>a</p>\n<ol start=\"7\">\n<li>seven</li>\n<li>eight</li>\n</ol>\n</li>\n\
+             <li>\n<p>b</p>\n</li>\n</ul>\n"
+        );
+        // A new delimiter starts a new list
+        assert_eq!(
+            render("1. a\n2) b"),
+            "<ol>\n<li>a</li>\n</ol>\n<ol start=\"2\">\n<li>b</li>\n</ol>\n"
+        );
+        // Only a list starting at 1 can interrupt a paragraph
+        assert_eq!(render("text\n5) five"), "<p>text\n5) five</p>\n");
     }
-    
-    let mut count = 0;
-    let mut in_word = false;
-    
-    for byte in text.bytes() {
-        if byte.is_ascii_whitespace() {
-            in_word = false;
-        } else if !in_word {
-            in_word = true;
-            count += 1;
-        }
-    }
-    
-    count
-}
\ No newline at end of file
+
+    #[test]
+    fn editing_a_list_marker_changes_the_type_of_its_level_only() {
+        let mut parser = GfmMarkdownParser::new();
+        parser.render_document("1. one\n   - a\n   - b\n2. two\n   - c");
+        let result = edit(&mut parser, 1, 3, &["   1) a", "   2) b"]);
+        assert_matches_full_render(&parser, &result);
+        assert_eq!(
+            strip_source_lines(&result.html),
+            "<ol>\n<li>one\n<ol>\n<li>a</li>\n<li>b</li>\n</ol>\n</li>\n\
+             <li>two\n<ul>\n<li>c</li>\n</ul>\n</li>\n</ol>\n"
+        );
+
+        let result = edit(&mut parser, 0, 1, &["- one"]);
+        assert_matches_full_render(&parser, &result);
+        assert!(strip_source_lines(&result.html).starts_with("<ul>\n<li>one\n<ol>\n<li>a</li>"), "{}", result.html);
+    }
+
+    #[test]
+    fn list_items_hold_block_content() {
+        assert_eq!(
+            render("- a\n\n  second paragraph\n\n      code\n  > quote\n- b"),
+            "<ul>\n<li>\n<p>a</p>\n<p>second paragraph</p>\n<pre><code>code\n</code></pre>\n\
+             <blockquote>\n<p>quote</p>\n</blockquote>\n</li>\n<li>\n<p>b</p>\n</li>\n</ul>\n"
+        );
+        assert_eq!(
+            render("1. one\n   ```\n   code\n   ```\n2. two"),
+            "<ol>\n<li>one\n<pre><code>code\n</code></pre>\n</li>\n<li>two</li>\n</ol>\n"
+        );
+        // Content indented less than the item ends it
+        assert_eq!(render("- a\n\nafter"), "<ul>\n<li>a</li>\n</ul>\n<p>after</p>\n");
+    }
+
+    #[test]
+    fn only_loose_lists_wrap_items_in_paragraphs() {
+        assert_eq!(render("- a\n- b"), "<ul>\n<li>a</li>\n<li>b</li>\n</ul>\n");
+        assert_eq!(
+            render("- a\n- b\n\n- c"),
+            "<ul>\n<li>\n<p>a</p>\n</li>\n<li>\n<p>b</p>\n</li>\n<li>\n<p>c</p>\n</li>\n</ul>\n"
+        );
+        // A blank line inside a nested list only loosens that list
+        assert_eq!(
+            render("- a\n  - b\n\n    c\n- d"),
+            "<ul>\n<li>a\n<ul>\n<li>\n<p>b</p>\n<p>c</p>\n</li>\n</ul>\n</li>\n<li>d</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn edits_inside_list_items_keep_the_list_and_its_looseness_right() {
+        let mut parser = GfmMarkdownParser::new();
+        parser.render_document("- a\n- b\n- c\n\nafter");
+
+        // A blank line between items loosens the whole list
+        let result = edit(&mut parser, 1, 1, &[""]);
+        assert_matches_full_render(&parser, &result);
+        assert!(strip_source_lines(&result.html).starts_with("<ul>\n<li>\n<p>a</p>\n</li>"), "{}", result.html);
+
+        // Indented content joins the item above instead of ending the list
+        let result = edit(&mut parser, 1, 2, &["", "  more about a", "", "      code"]);
+        assert_matches_full_render(&parser, &result);
+        assert_eq!(
+            strip_source_lines(&result.html),
+            "<ul>\n<li>\n<p>a</p>\n<p>more about a</p>\n<pre><code>code\n</code></pre>\n</li>\n\
+             <li>\n<p>b</p>\n</li>\n<li>\n<p>c</p>\n</li>\n</ul>\n<p>after</p>\n"
+        );
+
+        // Without the blank lines the list is tight again
+        let result = edit(&mut parser, 1, 5, &["  more about a"]);
+        assert_matches_full_render(&parser, &result);
+        assert_eq!(
+            strip_source_lines(&result.html),
+            "<ul>\n<li>a\nmore about a</li>\n<li>b</li>\n<li>c</li>\n</ul>\n<p>after</p>\n"
+        );
+    }
+
+    #[test]
+    fn blockquotes_are_block_containers() {
+        assert_eq!(render("> one\n> two"), "<blockquote>\n<p>one\ntwo</p>\n</blockquote>\n");
+        assert_eq!(
+            render("> a\nlazy\n>> nested\n> - item\n>   more"),
+            "<blockquote>\n<p>a\nlazy</p>\n<blockquote>\n<p>nested</p>\n</blockquote>\n\
+             <ul>\n<li>item\nmore</li>\n</ul>\n</blockquote>\n"
+        );
+        assert_eq!(
+            render("> ```\n> fenced\n> ```\n>\n>     indented"),
+            "<blockquote>\n<pre><code>fenced\n</code></pre>\n<pre><code>indented\n</code></pre>\n</blockquote>\n"
+        );
+        // A blank line without `>` ends the quote
+        assert_eq!(
+            render("> one\n\n> two"),
+            "<blockquote>\n<p>one</p>\n</blockquote>\n<blockquote>\n<p>two</p>\n</blockquote>\n"
+        );
+    }
+
+    #[test]
+    fn quote_lines_added_by_an_edit_join_the_quote() {
+        let mut parser = GfmMarkdownParser::new();
+        parser.render_document("> one\n\ntext\n\n> three");
+
+        let result = edit(&mut parser, 1, 4, &["> two", ">> nested", "> - item", ">"]);
+        assert_matches_full_render(&parser, &result);
+        assert_eq!(
+            strip_source_lines(&result.html),
+            "<blockquote>\n<p>one\ntwo</p>\n<blockquote>\n<p>nested</p>\n</blockquote>\n\
+             <ul>\n<li>item</li>\n</ul>\n<p>three</p>\n</blockquote>\n"
+        );
+
+        // A blank line without `>` splits it again
+        let result = edit(&mut parser, 5, 5, &[""]);
+        assert_matches_full_render(&parser, &result);
+        assert_eq!(strip_source_lines(&result.html).matches("<blockquote>").count(), 3, "{}", result.html);
+    }
+
+    #[test]
+    fn definitions_before_a_paragraph_do_not_reuse_cached_blocks() {
+        let mut parser = GfmMarkdownParser::new();
+        parser.render_document("    indented");
+        let result = edit(&mut parser, 0, 1, &["[r]: https://z.w", "    indented"]);
+        assert_matches_full_render(&parser, &result);
+        assert!(result.html.starts_with("<p data-source-line=\"2\""), "{}", result.html);
+
+        let result = edit(&mut parser, 0, 1, &[]);
+        assert_matches_full_render(&parser, &result);
+        assert!(result.html.starts_with("<pre"), "{}", result.html);
+    }
+
+    #[test]
+    fn links_to_files_are_checked_again_on_every_render() {
+        let directory = std::env::temp_dir().join(format!("inkdown-lint-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let target = directory.join("other.md");
+        let _ = std::fs::remove_file(&target);
+
+        let mut parser = GfmMarkdownParser::new();
+        parser.set_document_path(Some(directory.join("note.md").to_string_lossy().into_owned()));
+        let broken = |result: &ParseResult| result.diagnostics.iter().any(|d| d.rule == "broken-link");
+        assert!(broken(&parser.render_document("[other](other.md)\n\ntext")));
+
+        std::fs::write(&target, "").unwrap();
+        let result = edit(&mut parser, 2, 3, &["more text"]);
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(!broken(&result), "{:?}", result.diagnostics);
+    }
+
+    #[test]
+    fn block_keys_survive_edits_above_them() {
+        let mut parser = GfmMarkdownParser::new();
+        parser.set_block_output(true);
+        let before = parser.render_document("# Title\n\nIntro text.\n\n- one\n- two\n\n```rust\nfn main() {}\n```");
+        assert_eq!(before.blocks.len(), 4);
+
+        // The new heading takes the `title` anchor, so the old one's key changes
+        let after = edit(&mut parser, 0, 0, &["# Title", "", "New text.", ""]);
+        assert_eq!(after.blocks.len(), 6);
+        assert_ne!(after.blocks[2].key, before.blocks[0].key);
+        assert!(after.blocks[2].html.contains("id=\"title-1\""), "{}", after.blocks[2].html);
+
+        for (old, new) in before.blocks[1..].iter().zip(&after.blocks[3..]) {
+            assert_eq!(new.key, old.key);
+            assert_eq!(new.start_line, old.start_line + 4);
+            assert_eq!(strip_source_lines(&new.html), strip_source_lines(&old.html));
+        }
+    }
+
+    #[test]
+    fn error_diagnostics_are_summed_up_in_error() {
+        let mut parser = GfmMarkdownParser::new();
+        let result = parser.render_document("# Title\n\n### Skipped\n\n```rust\nfn main() {}");
+        assert_eq!(result.error.as_deref(), Some("Line 5: Code fence is never closed"));
+        assert!(result.html.contains("<code class=\"language-rust\">"), "{}", result.html);
+
+        // Warnings alone don't make the document broken
+        let result = edit(&mut parser, 5, 6, &["```"]);
+        assert_eq!(result.error, None);
+        assert!(result.diagnostics.iter().any(|d| d.rule == "heading-increment"), "{:?}", result.diagnostics);
+    }
+
+    #[test]
+    fn edits_parse_only_the_blocks_around_them() {
+        let markdown: Vec<String> = (0..40).map(|n| format!("## Part {}\n\nText {}\n", n, n)).collect();
+        let mut parser = GfmMarkdownParser::new();
+        parser.render_document(&markdown.join("\n"));
+        let contents = |parser: &GfmMarkdownParser| -> Vec<*const u8> {
+            parser.stored_blocks.iter().map(|block| block.blocks[0].content.as_ptr()).collect()
+        };
+        let before = contents(&parser);
+
+        // Line 63 is `Text 15`
+        edit(&mut parser, 62, 63, &["Text fifteen", "", "# Inserted"]);
+        let after = contents(&parser);
+        assert_eq!(after.len(), before.len() + 1);
+        assert_eq!(after[..31], before[..31]);
+        assert_ne!(after[31], before[31]);
+        assert_eq!(after[33..], before[32..]);
+        assert_eq!(parser.stored_blocks[33].blocks[0].start_line, 67);
+    }
+
+    #[test]
+    fn repeated_anchors_are_renumbered_when_earlier_headings_change() {
+        let mut parser = GfmMarkdownParser::new();
+        let result = parser.render_document("# Setup\n\ntext\n\n# Setup\n\n# Setup-1");
+        assert_eq!(toc(&result), [("setup".into(), 1), ("setup-1".into(), 5), ("setup-1-1".into(), 7)]);
+
+        let result = edit(&mut parser, 0, 2, &[]);
+        assert_eq!(toc(&result), [("setup".into(), 3), ("setup-1".into(), 5)]);
+        assert_eq!(
+            strip_source_lines(&result.html),
+            "<p>text</p>\n<h1 id=\"setup\">Setup</h1>\n<h1 id=\"setup-1\">Setup-1</h1>\n"
+        );
+    }
+
+    #[test]
+    fn embedding_blocks_render_again_when_the_embedded_note_changes() {
+        let directory = std::env::temp_dir().join(format!("inkdown-embed-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let note = directory.join("Other.md");
+        std::fs::write(&note, "first").unwrap();
+
+        let mut parser = GfmMarkdownParser::new();
+        let root = directory.to_string_lossy().into_owned();
+        parser.set_workspace(&root, &[note.to_string_lossy().into_owned()]);
+        assert!(parser.render_document("![[Other]]\n\ntext").html.contains("<p>first</p>"));
+
+        std::fs::write(&note, "second").unwrap();
+        let modified = SystemTime::now() + std::time::Duration::from_secs(5);
+        std::fs::File::options().write(true).open(&note).unwrap().set_modified(modified).unwrap();
+        let result = edit(&mut parser, 2, 3, &["more text"]);
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert!(result.html.contains("<p>second</p>"), "{}", result.html);
+    }
+
+    #[test]
+    fn fold_markers_make_callouts_collapsible() {
+        assert_eq!(
+            render("> [!tip]- Folded *title*\n> body"),
+            "<details class=\"alert alert-tip\" data-callout=\"tip\"><summary class=\"alert-summary\">\
+             <span class=\"alert-icon\">💡</span><span class=\"alert-title\">Folded <em>title</em></span></summary>\
+             <div class=\"alert-content\">\n<p>body</p>\n</div></details>\n"
+        );
+        assert!(render("> [!NOTE]+\n> open").starts_with(
+            "<details class=\"alert alert-note\" data-callout=\"note\" open><summary class=\"alert-summary\">\
+             <span class=\"alert-icon\">ℹ️</span><span class=\"alert-title\">Note</span></summary>"
+        ));
+        assert!(render("> [!tip] Not folded").starts_with("<div class=\"alert alert-tip\""));
+    }
+
+    #[test]
+    fn callout_titles() {
+        assert_eq!(
+            render("> [!warning]"),
+            "<div class=\"alert alert-warning\" data-callout=\"warning\"><div class=\"alert-icon\">⚠️</div>\
+             <div class=\"alert-content\"><div class=\"alert-title\">Warning</div>\n</div></div>\n"
+        );
+        assert!(render("> [!tip] Use `cargo` & *more*\n> body").contains(
+            "<div class=\"alert-title\">Use <code>cargo</code> &amp; <em>more</em></div>\n<p>body</p>"
+        ));
+        // Content after the marker line can be set apart by a blank line
+        assert!(render("> [!tip]\n>\n> a").contains("<div class=\"alert-title\">Tip</div>\n<p>a</p>\n</div></div>"));
+        // Only a marker opening the quote makes a callout
+        assert_eq!(render("> text [!tip]"), "<blockquote>\n<p>text [!tip]</p>\n</blockquote>\n");
+        assert_eq!(render("> [!no tip] x"), "<blockquote>\n<p>[!no tip] x</p>\n</blockquote>\n");
+    }
+
+    #[test]
+    fn unknown_callout_types_look_like_notes() {
+        assert_eq!(
+            render("> [!WhatEver] Custom\n> x"),
+            "<div class=\"alert alert-note\" data-callout=\"whatever\"><div class=\"alert-icon\">ℹ️</div>\
+             <div class=\"alert-content\"><div class=\"alert-title\">Custom</div>\n<p>x</p>\n</div></div>\n"
+        );
+        assert!(render("> [!recipe]").contains("<div class=\"alert-title\">Recipe</div>"));
+    }
+
+    #[test]
+    fn custom_callout_types_pick_their_class_and_icon() {
+        let mut parser = GfmMarkdownParser::new();
+        parser.render_document("> [!Recipe] Soup");
+        parser.set_callout_types(vec![
+            CalloutType {
+                name: "Recipe".to_string(),
+                icon: "<b>🍲</b>".to_string(),
+                class: "tip".to_string(),
+            },
+            CalloutType {
+                name: "odd".to_string(),
+                icon: "?".to_string(),
+                class: "my class\"><script>".to_string(),
+            },
+        ]);
+        // Changing the types renders the document again
+        let html = strip_source_lines(&parser.render_document("> [!Recipe] Soup\n\n> [!ODD]").html);
+        assert_eq!(
+            html,
+            "<div class=\"alert alert-tip\" data-callout=\"recipe\"><div class=\"alert-icon\">&lt;b&gt;🍲&lt;/b&gt;</div>\
+             <div class=\"alert-content\"><div class=\"alert-title\">Soup</div>\n</div></div>\n\
+             <div class=\"alert alert-myclassscript\" data-callout=\"odd\"><div class=\"alert-icon\">?</div>\
+             <div class=\"alert-content\"><div class=\"alert-title\">Odd</div>\n</div></div>\n"
+        );
+    }
+
+    // Writes `notes` as `<name>.md` files of a temporary workspace, which the
+    // caller removes, and returns a parser for it
+    fn workspace(folder: &str, notes: &[(&str, &str)]) -> (PathBuf, GfmMarkdownParser) {
+        let directory = std::env::temp_dir().join(format!("inkdown-{}-{}", folder, std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let paths: Vec<String> = notes
+            .iter()
+            .map(|(name, markdown)| {
+                let path = directory.join(format!("{}.md", name));
+                std::fs::write(&path, markdown).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let mut parser = GfmMarkdownParser::new();
+        parser.set_workspace(&directory.to_string_lossy(), &paths);
+        (directory, parser)
+    }
+
+    fn render_note(parser: &mut GfmMarkdownParser, directory: &Path, name: &str) -> String {
+        let path = directory.join(format!("{}.md", name));
+        parser.set_document_path(Some(path.to_string_lossy().into_owned()));
+        strip_source_lines(&parser.render_document(&std::fs::read_to_string(path).unwrap()).html)
+    }
+
+    #[test]
+    fn embeds_stop_at_cycles() {
+        let notes = [("A", "a\n\n![[B]]"), ("B", "b\n\n![[A]]"), ("Self", "![[Self]]")];
+        let (directory, mut parser) = workspace("embed-cycle", &notes);
+        let a = render_note(&mut parser, &directory, "A");
+        let itself = render_note(&mut parser, &directory, "Self");
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(a.matches("<p>a</p>").count(), 1, "{}", a);
+        assert_eq!(a.matches("<p>b</p>").count(), 1, "{}", a);
+        assert!(a.contains("<span class=\"embed-message\">Circular embed</span>"), "{}", a);
+        assert!(itself.starts_with("<div class=\"embed embed-error\">"), "{}", itself);
+        assert!(itself.contains("Circular embed"), "{}", itself);
+    }
+
+    #[test]
+    fn embeds_stop_past_the_maximum_depth() {
+        let notes: Vec<(String, String)> =
+            (0..6).map(|index| (format!("N{}", index), format!("level {}\n\n![[N{}]]", index, index + 1))).collect();
+        let notes: Vec<(&str, &str)> =
+            notes.iter().map(|(name, markdown)| (name.as_str(), markdown.as_str())).collect();
+        let (directory, mut parser) = workspace("embed-depth", &notes);
+        let html = render_note(&mut parser, &directory, "N0");
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        for level in 0..MAX_EMBED_DEPTH {
+            assert!(html.contains(&format!("<p>level {}</p>", level)), "{}", html);
+        }
+        assert!(!html.contains(&format!("<p>level {}</p>", MAX_EMBED_DEPTH)), "{}", html);
+        assert!(html.contains("Embeds are nested too deeply"), "{}", html);
+    }
+
+    #[test]
+    fn embeds_take_a_heading_section_or_a_block() {
+        let note = "# Note\n\nintro\n\n## Setup\n\nin setup\n\n### Details\n\ndeeper\n\n## Usage\n\nafter\n\n\
+                    first line\nsecond line ^para\n\n- item\n- last ^list-1";
+        let host = "![[Note#Setup]]\n\n![[Note#^para]]\n\n![[Note#^list-1]]\n\n![[Note#Missing]]\n\n![[Note#^missing]]";
+        let (directory, mut parser) = workspace("embed-parts", &[("Note", note), ("Host", host)]);
+        let html = render_note(&mut parser, &directory, "Host");
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let contents: Vec<&str> = html
+            .split("<div class=\"embed-content\">\n")
+            .skip(1)
+            .map(|rest| &rest[..rest.find("</div>").unwrap()])
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                "<h2 id=\"embed-note-setup\">Setup</h2>\n<p>in setup</p>\n\
+                 <h3 id=\"embed-note-details\">Details</h3>\n<p>deeper</p>\n",
+                "<p>first line\nsecond line</p>\n",
+                "<ul>\n<li>item</li>\n<li>last</li>\n</ul>\n",
+            ]
+        );
+        assert!(html.contains("Heading &quot;Missing&quot; not found"), "{}", html);
+        assert!(html.contains("Block &quot;^missing&quot; not found"), "{}", html);
+    }
+
+    #[test]
+    fn embedded_notes_keep_their_anchors_and_callout_types_to_themselves() {
+        let note = "# Title\n\nsee[^1] and [[#Title]]\n\n> [!recipe] Soup\n> hot\n\n[^1]: A note.";
+        let (directory, mut parser) = workspace("embed-anchors", &[("Note", note), ("Host", "# Title\n\n![[Note]]")]);
+        parser.set_callout_types(vec![CalloutType {
+            name: "recipe".to_string(),
+            icon: "🍲".to_string(),
+            class: "callout-recipe".to_string(),
+        }]);
+        let html = render_note(&mut parser, &directory, "Host");
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(html.starts_with("<h1 id=\"title\">Title</h1>\n"), "{}", html);
+        assert!(html.contains("<h1 id=\"embed-note-title\">Title</h1>"), "{}", html);
+        assert!(html.contains("href=\"#embed-note-title\""), "{}", html);
+        assert!(html.contains("<a href=\"#embed-note-fn-1\" id=\"embed-note-fnref-1\""), "{}", html);
+        assert!(html.contains("<li id=\"embed-note-fn-1\">"), "{}", html);
+        assert!(html.contains("callout-recipe"), "{}", html);
+    }
+
+    #[test]
+    fn embeds_need_a_note_and_a_line_of_their_own() {
+        let notes = [("Note", "text"), ("Host", "![[Nowhere]]\n\nsee ![[Note]] here")];
+        let (directory, mut parser) = workspace("embed-missing", &notes);
+        let html = render_note(&mut parser, &directory, "Host");
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(html.starts_with("<div class=\"embed embed-unresolved\"><a "), "{}", html);
+        assert!(html.ends_with("<p>see ![[Note]] here</p>\n"), "{}", html);
+        assert!(!html.contains("<p>text</p>"), "{}", html);
+    }
+
+    fn tags(markdown: &str) -> Vec<String> {
+        GfmMarkdownParser::new().render_document(markdown).tags
+    }
+
+    #[test]
+    fn tags_skip_links_code_numbers_and_headings() {
+        assert_eq!(
+            tags("[x](https://a.com/#frag) https://b.com/#auto `#code` #123 #2024 x#y &#35;entity\n\n\
+                  # Heading\n\n```\n#fenced\n```\n\n    #indented"),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            tags("#alpha (#paren) **#bold** #2024/review #nested/tag/ #trail.\n\n## About #heading-tag\n\n- #item"),
+            ["alpha", "paren", "bold", "2024/review", "nested/tag", "trail", "heading-tag", "item"]
+        );
+        assert_eq!(
+            render("#tag and #123"),
+            "<p><span class=\"tag\" data-tag=\"tag\">#tag</span> and #123</p>\n"
+        );
+    }
+
+    #[test]
+    fn tags_merge_with_front_matter() {
+        assert_eq!(
+            tags("---\ntags: [project, \"#Draft\"]\n---\n#draft #new #Project"),
+            ["project", "Draft", "new"]
+        );
+        assert_eq!(tags("---\ntags: one, two three\n---\n#four"), ["one", "two", "three", "four"]);
+        assert_eq!(tags("---\ntags: 3\ntitle: \"#no\"\n---\n#body"), ["body"]);
+        // Broken front matter adds no tags
+        assert_eq!(tags("---\ntags: [open\n---\n#body"), ["body"]);
+    }
+
+    fn paragraph(markdown: &str) -> String {
+        let html = render(markdown);
+        html.strip_prefix("<p>").and_then(|html| html.strip_suffix("</p>\n")).unwrap_or(&html).to_string()
+    }
+
+    #[test]
+    fn autolinks_drop_trailing_punctuation() {
+        assert_eq!(
+            paragraph("Visit www.commonmark.org/help."),
+            "Visit <a href=\"http://www.commonmark.org/help\">www.commonmark.org/help</a>."
+        );
+        assert_eq!(
+            paragraph("https://example.com/path?x=1, and https://example.com/a:!"),
+            "<a href=\"https://example.com/path?x=1\">https://example.com/path?x=1</a>, and \
+             <a href=\"https://example.com/a\">https://example.com/a</a>:!"
+        );
+        assert_eq!(
+            paragraph("www.commonmark.org/he<lp"),
+            "<a href=\"http://www.commonmark.org/he\">www.commonmark.org/he</a>&lt;lp"
+        );
+    }
+
+    #[test]
+    fn autolinks_keep_balanced_parentheses() {
+        assert_eq!(
+            paragraph("(www.example.com/a_(b))"),
+            "(<a href=\"http://www.example.com/a_(b)\">www.example.com/a_(b)</a>)"
+        );
+        assert_eq!(
+            paragraph("www.google.com/search?q=Markup+(business)))"),
+            "<a href=\"http://www.google.com/search?q=Markup+(business)\">\
+             www.google.com/search?q=Markup+(business)</a>))"
+        );
+        // Only unbalanced parentheses at the very end are dropped
+        assert_eq!(
+            paragraph("www.google.com/search?q=(business))+ok"),
+            "<a href=\"http://www.google.com/search?q=(business))+ok\">www.google.com/search?q=(business))+ok</a>"
+        );
+    }
+
+    #[test]
+    fn autolinks_drop_entity_like_suffixes() {
+        assert_eq!(
+            paragraph("www.google.com/search?q=commonmark&hl=en"),
+            "<a href=\"http://www.google.com/search?q=commonmark&amp;hl=en\">\
+             www.google.com/search?q=commonmark&amp;hl=en</a>"
+        );
+        assert_eq!(
+            paragraph("www.google.com/search?q=commonmark&hl;"),
+            "<a href=\"http://www.google.com/search?q=commonmark\">www.google.com/search?q=commonmark</a>&amp;hl;"
+        );
+        // `;c;` doesn't look like an entity
+        assert_eq!(
+            paragraph("www.example.com/a&b;c;"),
+            "<a href=\"http://www.example.com/a&amp;b;c;\">www.example.com/a&amp;b;c;</a>"
+        );
+    }
+
+    #[test]
+    fn autolinks_need_www_or_a_scheme_and_a_valid_domain() {
+        assert_eq!(
+            paragraph("www.a and http://a.b/c"),
+            "<a href=\"http://www.a\">www.a</a> and <a href=\"http://a.b/c\">http://a.b/c</a>"
+        );
+        assert_eq!(paragraph("wwwx.example.com example.com ftp.a.com"), "wwwx.example.com example.com ftp.a.com");
+        assert_eq!(paragraph("www.a_b.c_d.com"), "www.a_b.c_d.com");
+        assert_eq!(
+            paragraph("`www.code.com` [www.label.com](/x)"),
+            "<code>www.code.com</code> <a href=\"/x\">www.label.com</a>"
+        );
+    }
+
+    #[test]
+    fn email_autolinks() {
+        assert_eq!(
+            paragraph("foo@bar.baz. hello+xyz@mail.example, a.b-c_d@a.b"),
+            "<a href=\"mailto:foo@bar.baz\">foo@bar.baz</a>. \
+             <a href=\"mailto:hello+xyz@mail.example\">hello+xyz@mail.example</a>, \
+             <a href=\"mailto:a.b-c_d@a.b\">a.b-c_d@a.b</a>"
+        );
+        // The domain can't end with `-` or `_`, nor have a `+`
+        assert_eq!(
+            paragraph("a.b-c_d@a.b- a.b-c_d@a.b_ hello@mail+xyz.example x@nodot"),
+            "a.b-c_d@a.b- a.b-c_d@a.b_ hello@mail+xyz.example x@nodot"
+        );
+    }
+
+    fn backref(number: usize, index: usize) -> String {
+        format!(
+            "<a href=\"#{}\" class=\"footnote-backref\" data-footnote-backref aria-label=\"Back to reference {}\">↩{}</a>",
+            footnote_reference_id(number, index),
+            number,
+            if index > 1 { format!("<sup>{}</sup>", index) } else { String::new() }
+        )
+    }
+
+    #[test]
+    fn footnotes_are_numbered_by_first_reference() {
+        let html = render("b[^b] a[^a] b again[^b]\n\n[^a]: A.\n[^b]: B.");
+        assert!(html.starts_with(
+            "<p>b<sup class=\"footnote-ref\"><a href=\"#fn-1\" id=\"fnref-1\" data-footnote-ref>1</a></sup> \
+             a<sup class=\"footnote-ref\"><a href=\"#fn-2\" id=\"fnref-2\" data-footnote-ref>2</a></sup> \
+             b again<sup class=\"footnote-ref\"><a href=\"#fn-1\" id=\"fnref-1-2\" data-footnote-ref>1</a></sup></p>\n"
+        ), "{}", html);
+        // Every reference gets a way back, the repeated ones numbered
+        assert!(html.ends_with(&format!(
+            "<section class=\"footnotes\" data-footnotes>\n<ol>\n\
+             <li id=\"fn-1\">\n<p>B. {} {}</p>\n</li>\n\
+             <li id=\"fn-2\">\n<p>A. {}</p>\n</li>\n</ol>\n</section>\n",
+            backref(1, 1),
+            backref(1, 2),
+            backref(2, 1)
+        )), "{}", html);
+    }
+
+    #[test]
+    fn undefined_footnotes_stay_text() {
+        assert_eq!(render("x[^nope]"), "<p>x[^nope]</p>\n");
+        // Unreferenced definitions don't show up either
+        assert_eq!(render("x\n\n[^unused]: Never referenced"), "<p>x</p>\n");
+    }
+
+    #[test]
+    fn footnotes_can_be_defined_inside_containers() {
+        let html = render("- item\n\n  [^in]: Inside\n\n> [^q]: Quoted\n\nuse[^in] and[^q]");
+        assert!(html.starts_with("<ul>\n<li>\n<p>item</p>\n</li>\n</ul>\n<blockquote>\n</blockquote>\n"), "{}", html);
+        assert!(html.contains(&format!("<li id=\"fn-1\">\n<p>Inside {}</p>\n</li>\n", backref(1, 1))), "{}", html);
+        assert!(html.contains(&format!("<li id=\"fn-2\">\n<p>Quoted {}</p>\n</li>\n", backref(2, 1))), "{}", html);
+    }
+
+    #[test]
+    fn heading_anchors_follow_github() {
+        let slugs = |markdown: &str| -> Vec<String> {
+            GfmMarkdownParser::new().render_document(markdown).toc.into_iter().map(|entry| entry.slug).collect()
+        };
+        assert_eq!(
+            slugs("# Hello, World!\n## Déjà vu\n### `code` and *em* [link](x)\n# 日本語 テスト\n# Multiple   spaces\n# foo_bar-baz"),
+            ["hello-world", "déjà-vu", "code-and-em-link", "日本語-テスト", "multiple---spaces", "foo_bar-baz"]
+        );
+        // A suffix that collides with another heading's anchor is bumped again
+        assert_eq!(
+            slugs("# Setup\n# Setup\n# Setup-1\n# Setup 1\n# Setup"),
+            ["setup", "setup-1", "setup-1-1", "setup-1-2", "setup-2"]
+        );
+        assert_eq!(render("## Setup\n\n## Setup"), "<h2 id=\"setup\">Setup</h2>\n<h2 id=\"setup-1\">Setup</h2>\n");
+    }
+}
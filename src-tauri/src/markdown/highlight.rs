@@ -0,0 +1,560 @@
+use crate::markdown::inline::escape_html;
+
+// Lexical rules of one language. Highlighting is a single pass that picks out
+// comments, strings, numbers and words; nothing is parsed, so the result is
+// about as precise as an editor's colouring and never fails on broken code.
+struct Language {
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    // Block comments can contain block comments (Rust)
+    nested_comments: bool,
+    quotes: &'static [u8],
+    // Keywords match regardless of case (SQL)
+    case_insensitive: bool,
+    // `'` only opens a one-character literal, otherwise it is a lifetime (Rust)
+    char_literals: bool,
+    // `r"..."` and `r#"..."#` strings (Rust)
+    raw_strings: bool,
+    // `"""` and `'''` strings (Python)
+    triple_quotes: bool,
+    // `$NAME` variables, and `#` only starts a comment at the start of a word
+    shell: bool,
+    // Strings and words followed by `:` are keys (JSON, YAML)
+    keys: bool,
+    // Words starting with an uppercase letter are types
+    capitalized_types: bool,
+    // Words followed by `(` are function calls
+    calls: bool,
+}
+
+const PLAIN: Language = Language {
+    keywords: &[],
+    literals: &[],
+    line_comments: &[],
+    block_comment: None,
+    nested_comments: false,
+    quotes: &[],
+    case_insensitive: false,
+    char_literals: false,
+    raw_strings: false,
+    triple_quotes: false,
+    shell: false,
+    keys: false,
+    capitalized_types: false,
+    calls: false,
+};
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+        "return", "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use", "where",
+        "while",
+    ],
+    literals: &["true", "false"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    nested_comments: true,
+    quotes: b"\"'",
+    char_literals: true,
+    raw_strings: true,
+    capitalized_types: true,
+    calls: true,
+    ..PLAIN
+};
+
+const TYPESCRIPT: Language = Language {
+    keywords: &[
+        "abstract", "any", "as", "async", "await", "boolean", "break", "case", "catch", "class", "const",
+        "constructor", "continue", "debugger", "declare", "default", "delete", "do", "else", "enum",
+        "export", "extends", "finally", "for", "from", "function", "get", "if", "implements", "import",
+        "in", "instanceof", "interface", "keyof", "let", "new", "number", "of", "private", "protected",
+        "public", "readonly", "return", "set", "static", "string", "super", "switch", "this", "throw",
+        "try", "type", "typeof", "var", "void", "while", "with", "yield",
+    ],
+    literals: &["true", "false", "null", "undefined", "NaN", "Infinity"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: b"\"'`",
+    capitalized_types: true,
+    calls: true,
+    ..PLAIN
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "case", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "match", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield",
+    ],
+    literals: &["True", "False", "None"],
+    line_comments: &["#"],
+    quotes: b"\"'",
+    triple_quotes: true,
+    capitalized_types: true,
+    calls: true,
+    ..PLAIN
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "case", "esac", "for", "while", "until", "do", "done", "in",
+        "function", "select", "return", "exit", "local", "export", "readonly", "declare", "unset",
+        "source", "alias",
+    ],
+    literals: &["true", "false"],
+    line_comments: &["#"],
+    quotes: b"\"'`",
+    shell: true,
+    ..PLAIN
+};
+
+const JSON: Language = Language {
+    literals: &["true", "false", "null"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: b"\"",
+    keys: true,
+    ..PLAIN
+};
+
+const YAML: Language = Language {
+    literals: &["true", "false", "null", "yes", "no"],
+    line_comments: &["#"],
+    quotes: b"\"'",
+    keys: true,
+    ..PLAIN
+};
+
+const SQL: Language = Language {
+    keywords: &[
+        "add", "all", "alter", "and", "as", "asc", "begin", "between", "by", "case", "check", "column",
+        "commit", "constraint", "create", "cross", "default", "delete", "desc", "distinct", "drop",
+        "else", "end", "exists", "foreign", "from", "full", "group", "having", "if", "in", "index",
+        "inner", "insert", "into", "is", "join", "key", "left", "like", "limit", "not", "offset", "on",
+        "or", "order", "outer", "primary", "references", "replace", "returning", "right", "rollback",
+        "select", "set", "table", "then", "transaction", "union", "unique", "update", "values", "view",
+        "when", "where", "with", "integer", "int", "bigint", "varchar", "text", "boolean", "date",
+        "timestamp", "serial", "real",
+    ],
+    literals: &["null", "true", "false"],
+    line_comments: &["--"],
+    block_comment: Some(("/*", "*/")),
+    quotes: b"'\"",
+    case_insensitive: true,
+    calls: true,
+    ..PLAIN
+};
+
+fn language(name: &str) -> Option<&'static Language> {
+    match name.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => Some(&RUST),
+        "typescript" | "ts" | "tsx" | "javascript" | "js" | "jsx" | "mjs" | "cjs" => Some(&TYPESCRIPT),
+        "python" | "py" => Some(&PYTHON),
+        "shell" | "sh" | "bash" | "zsh" => Some(&SHELL),
+        "json" | "jsonc" => Some(&JSON),
+        "yaml" | "yml" => Some(&YAML),
+        "sql" => Some(&SQL),
+        _ => None,
+    }
+}
+
+// Renders `code` as escaped HTML with every token wrapped in a
+// `<span class="hl-...">`. Returns `None` for languages we don't know, which
+// are left to plain escaping.
+pub fn highlight(language_name: &str, code: &str) -> Option<String> {
+    let language = language(language_name)?;
+    let mut highlighter = Highlighter {
+        language,
+        code,
+        bytes: code.as_bytes(),
+        out: String::with_capacity(code.len() * 2),
+        plain_start: 0,
+    };
+    highlighter.run();
+    Some(highlighter.out)
+}
+
+struct Highlighter<'a> {
+    language: &'static Language,
+    code: &'a str,
+    bytes: &'a [u8],
+    out: String,
+    // Start of the text since the last token, which is written unstyled
+    plain_start: usize,
+}
+
+impl Highlighter<'_> {
+    fn run(&mut self) {
+        let mut pos = 0;
+        while pos < self.bytes.len() {
+            pos = match self.token_at(pos) {
+                Some((Some(class), end)) => {
+                    self.emit(class, pos, end);
+                    end
+                }
+                Some((None, end)) => end,
+                None => pos + self.code[pos..].chars().next().map_or(1, char::len_utf8),
+            };
+        }
+        self.flush_plain(self.bytes.len());
+    }
+
+    // Class and end of the token at `pos`; words that are not highlighted
+    // come back without a class so they are skipped as a whole.
+    fn token_at(&self, pos: usize) -> Option<(Option<&'static str>, usize)> {
+        let b = self.bytes[pos];
+        let previous = pos.checked_sub(1).map(|index| self.bytes[index]);
+        let after_word = previous.is_some_and(|p| p.is_ascii_alphanumeric() || p == b'_');
+
+        if let Some(end) = self.comment_end(pos, previous) {
+            return Some((Some("hl-comment"), end));
+        }
+        // An apostrophe inside a YAML plain scalar does not open a string
+        if !(after_word && self.language.keys) {
+            if let Some(end) = self.string_end(pos) {
+                let class = if self.language.keys && self.key_follows(end) { "hl-property" } else { "hl-string" };
+                return Some((Some(class), end));
+            }
+        }
+        if self.language.shell && b == b'$' {
+            return self.variable_end(pos).map(|end| (Some("hl-variable"), end));
+        }
+        if after_word {
+            return None;
+        }
+        if b.is_ascii_digit() {
+            return Some((Some("hl-number"), self.number_end(pos)));
+        }
+        if b.is_ascii_alphabetic() || b == b'_' {
+            let end = self.scan_while(pos, |b| is_word_byte(b, self.language));
+            return Some((self.classify_word(pos, end), end));
+        }
+        None
+    }
+
+    fn comment_end(&self, pos: usize, previous: Option<u8>) -> Option<usize> {
+        let rest = &self.code[pos..];
+        for prefix in self.language.line_comments {
+            if !rest.starts_with(prefix) {
+                continue;
+            }
+            if self.language.shell && !previous.is_none_or(|p| p.is_ascii_whitespace()) {
+                continue;
+            }
+            return Some(pos + rest.find('\n').unwrap_or(rest.len()));
+        }
+
+        let (open, close) = self.language.block_comment?;
+        if !rest.starts_with(open) {
+            return None;
+        }
+        if !self.language.nested_comments {
+            let body = &rest[open.len()..];
+            return Some(pos + open.len() + body.find(close).map(|index| index + close.len()).unwrap_or(body.len()));
+        }
+
+        let bytes = rest.as_bytes();
+        let mut depth = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i..].starts_with(open.as_bytes()) {
+                depth += 1;
+                i += open.len();
+            } else if bytes[i..].starts_with(close.as_bytes()) {
+                depth -= 1;
+                i += close.len();
+                if depth == 0 {
+                    return Some(pos + i);
+                }
+            } else {
+                i += 1;
+            }
+        }
+        Some(pos + bytes.len())
+    }
+
+    fn string_end(&self, pos: usize) -> Option<usize> {
+        let rest = &self.code[pos..];
+        let b = self.bytes[pos];
+
+        if self.language.raw_strings && b == b'r' {
+            let hashes = rest[1..].bytes().take_while(|&b| b == b'#').count();
+            if rest.as_bytes().get(1 + hashes) == Some(&b'"') {
+                let close = format!("\"{}", "#".repeat(hashes));
+                let body = &rest[2 + hashes..];
+                return Some(pos + 2 + hashes + body.find(&close).map(|index| index + close.len()).unwrap_or(body.len()));
+            }
+        }
+
+        if self.language.triple_quotes && (rest.starts_with("\"\"\"") || rest.starts_with("'''")) {
+            let body = &rest[3..];
+            return Some(pos + 3 + body.find(&rest[..3]).map(|index| index + 3).unwrap_or(body.len()));
+        }
+
+        if !self.language.quotes.contains(&b) {
+            return None;
+        }
+
+        if self.language.char_literals && b == b'\'' {
+            // `'a'` and `'\n'` are characters, `'a` on its own is a lifetime
+            let body = &rest[1..];
+            let length = if body.starts_with('\\') {
+                // The escaped character can be a quote itself
+                body.get(2..)?.find('\'')? + 2
+            } else {
+                body.chars().next()?.len_utf8()
+            };
+            return (body.as_bytes().get(length) == Some(&b'\'')).then_some(pos + 1 + length + 1);
+        }
+
+        let mut i = pos + 1;
+        while i < self.bytes.len() {
+            match self.bytes[i] {
+                b'\\' => i += 2,
+                quote if quote == b => return Some(i + 1),
+                _ => i += 1,
+            }
+        }
+        Some(self.bytes.len())
+    }
+
+    fn variable_end(&self, pos: usize) -> Option<usize> {
+        match self.bytes.get(pos + 1)? {
+            b'{' => Some(self.code[pos..].find('}').map(|index| pos + index + 1).unwrap_or(self.bytes.len())),
+            b if b.is_ascii_alphabetic() || *b == b'_' => {
+                Some(self.scan_while(pos + 1, |b| b.is_ascii_alphanumeric() || b == b'_'))
+            }
+            b if b.is_ascii_digit() || matches!(b, b'?' | b'#' | b'@' | b'*' | b'$' | b'!') => Some(pos + 2),
+            _ => None,
+        }
+    }
+
+    fn number_end(&self, pos: usize) -> usize {
+        let mut end = pos;
+        while let Some(&b) = self.bytes.get(end) {
+            let decimal_point = b == b'.' && self.bytes.get(end + 1).is_some_and(u8::is_ascii_digit);
+            if !(b.is_ascii_alphanumeric() || b == b'_' || decimal_point) {
+                break;
+            }
+            end += 1;
+        }
+        end
+    }
+
+    fn classify_word(&self, start: usize, end: usize) -> Option<&'static str> {
+        let word = &self.code[start..end];
+        let language = self.language;
+        let matches = |list: &[&str]| {
+            if language.case_insensitive {
+                list.iter().any(|entry| entry.eq_ignore_ascii_case(word))
+            } else {
+                list.contains(&word)
+            }
+        };
+
+        if language.keys && self.key_follows(end) {
+            Some("hl-property")
+        } else if matches(language.literals) {
+            Some("hl-literal")
+        } else if matches(language.keywords) {
+            Some("hl-keyword")
+        } else if language.calls && self.bytes.get(end) == Some(&b'(') {
+            Some("hl-function")
+        } else if language.capitalized_types && word.as_bytes()[0].is_ascii_uppercase() {
+            Some("hl-type")
+        } else {
+            None
+        }
+    }
+
+    // A key is followed by `:` and then whitespace or the end of the line
+    fn key_follows(&self, end: usize) -> bool {
+        let colon = self.scan_while(end, |b| b == b' ' || b == b'\t');
+        self.bytes.get(colon) == Some(&b':')
+            && self.bytes.get(colon + 1).is_none_or(|b| b.is_ascii_whitespace())
+    }
+
+    fn scan_while(&self, mut pos: usize, predicate: impl Fn(u8) -> bool) -> usize {
+        while pos < self.bytes.len() && predicate(self.bytes[pos]) {
+            pos += 1;
+        }
+        pos
+    }
+
+    fn emit(&mut self, class: &str, start: usize, end: usize) {
+        self.flush_plain(start);
+        self.out.push_str("<span class=\"");
+        self.out.push_str(class);
+        self.out.push_str("\">");
+        self.out.push_str(&escape_html(&self.code[start..end]));
+        self.out.push_str("</span>");
+        self.plain_start = end;
+    }
+
+    fn flush_plain(&mut self, end: usize) {
+        if end > self.plain_start {
+            self.out.push_str(&escape_html(&self.code[self.plain_start..end]));
+        }
+        self.plain_start = end;
+    }
+}
+
+#[inline]
+fn is_word_byte(b: u8, language: &Language) -> bool {
+    // YAML keys are often hyphenated
+    b.is_ascii_alphanumeric() || b == b'_' || (language.keys && b == b'-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hl(language: &str, code: &str) -> String {
+        highlight(language, code).expect("language should be known")
+    }
+
+    // `text` is already escaped
+    fn span(class: &str, text: &str) -> String {
+        format!("<span class=\"hl-{}\">{}</span>", class, text)
+    }
+
+    #[test]
+    fn rust_strings_chars_and_lifetimes() {
+        assert_eq!(
+            hl("rust", r#"let s = "a\"b"; let c = '\''; fn f<'a>(x: &'a str)"#),
+            format!(
+                "{} s = {}; {} c = {}; {} f&lt;&#x27;a&gt;(x: &amp;&#x27;a str)",
+                span("keyword", "let"),
+                span("string", "&quot;a\\&quot;b&quot;"),
+                span("keyword", "let"),
+                span("string", "&#x27;\\&#x27;&#x27;"),
+                span("keyword", "fn")
+            )
+        );
+        assert_eq!(
+            hl("rs", r##"r#"say "hi""# 0x1F 2.5"##),
+            format!(
+                "{} {} {}",
+                span("string", "r#&quot;say &quot;hi&quot;&quot;#"),
+                span("number", "0x1F"),
+                span("number", "2.5")
+            )
+        );
+    }
+
+    #[test]
+    fn rust_block_comments_nest() {
+        assert_eq!(
+            hl("rust", "/* a /* b */ c */ d"),
+            format!("{} d", span("comment", "/* a /* b */ c */"))
+        );
+        // Other languages end the comment at the first `*/`
+        assert_eq!(
+            hl("ts", "/* a /* b */ c */"),
+            format!("{} c */", span("comment", "/* a /* b */"))
+        );
+    }
+
+    #[test]
+    fn typescript_and_python_strings() {
+        assert_eq!(
+            hl("ts", r"const t = `a ${b}`; 'it\'s'; foo(1) // done"),
+            format!(
+                "{} t = {}; {}; {}({}) {}",
+                span("keyword", "const"),
+                span("string", "`a ${b}`"),
+                span("string", "&#x27;it\\&#x27;s&#x27;"),
+                span("function", "foo"),
+                span("number", "1"),
+                span("comment", "// done")
+            )
+        );
+        assert_eq!(
+            hl("python", "\"\"\"Doc \"quoted\" text\"\"\"\nreturn 'a\\'b' # note"),
+            format!(
+                "{}\n{} {} {}",
+                span("string", "&quot;&quot;&quot;Doc &quot;quoted&quot; text&quot;&quot;&quot;"),
+                span("keyword", "return"),
+                span("string", "&#x27;a\\&#x27;b&#x27;"),
+                span("comment", "# note")
+            )
+        );
+    }
+
+    #[test]
+    fn shell_variables_and_comments() {
+        assert_eq!(
+            hl("bash", "echo \"$HOME\" ${PATH} $1 a#b # real"),
+            format!(
+                "echo {} {} {} a#b {}",
+                span("string", "&quot;$HOME&quot;"),
+                span("variable", "${PATH}"),
+                span("variable", "$1"),
+                span("comment", "# real")
+            )
+        );
+    }
+
+    #[test]
+    fn json_and_yaml_keys() {
+        assert_eq!(
+            hl("json", r#"{"key": "va\"lue", "n": -1.5e3, "ok": null}"#),
+            format!(
+                "{{{}: {}, {}: -{}, {}: {}}}",
+                span("property", "&quot;key&quot;"),
+                span("string", "&quot;va\\&quot;lue&quot;"),
+                span("property", "&quot;n&quot;"),
+                span("number", "1.5e3"),
+                span("property", "&quot;ok&quot;"),
+                span("literal", "null")
+            )
+        );
+        // An apostrophe inside a plain scalar doesn't open a string
+        assert_eq!(
+            hl("yml", "first-key: it's plain # c\nsecond: 'quoted'"),
+            format!(
+                "{}: it&#x27;s plain {}\n{}: {}",
+                span("property", "first-key"),
+                span("comment", "# c"),
+                span("property", "second"),
+                span("string", "&#x27;quoted&#x27;")
+            )
+        );
+    }
+
+    #[test]
+    fn sql_keywords_ignore_case() {
+        assert_eq!(
+            hl("sql", "select Count(*) FROM t -- c"),
+            format!(
+                "{} {}(*) {} t {}",
+                span("keyword", "select"),
+                span("function", "Count"),
+                span("keyword", "FROM"),
+                span("comment", "-- c")
+            )
+        );
+    }
+
+    #[test]
+    fn unterminated_tokens_run_to_the_end() {
+        assert_eq!(hl("rust", "\"open \\"), span("string", "&quot;open \\"));
+        assert_eq!(hl("rust", "/* a /* b */"), span("comment", "/* a /* b */"));
+        assert_eq!(hl("python", "'''never <closed>"), span("string", "&#x27;&#x27;&#x27;never &lt;closed&gt;"));
+        assert_eq!(hl("ts", "x /* open"), format!("x {}", span("comment", "/* open")));
+        assert_eq!(hl("sh", "echo ${open"), format!("echo {}", span("variable", "${open")));
+    }
+
+    #[test]
+    fn unknown_languages_are_escaped_as_plain_text() {
+        assert_eq!(highlight("brainfuck", "<a>&"), None);
+        let html = crate::markdown::gfm_parser::GfmMarkdownParser::new()
+            .render_document("```brainfuck\n<a> & \"b\"\n```")
+            .html;
+        assert!(html.contains(">&lt;a&gt; &amp; &quot;b&quot;\n</code></pre>"), "{}", html);
+    }
+}
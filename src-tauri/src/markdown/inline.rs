@@ -0,0 +1,1760 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::markdown::entities;
+use crate::markdown::math::render_math;
+use crate::markdown::sanitize::{is_safe_url, sanitize_html};
+use crate::markdown::parser::ParserOptions;
+use crate::markdown::wikilinks::{NoteIndex, WikiLink};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Code(String),
+    SoftBreak,
+    HardBreak,
+    Html(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Strikethrough(Vec<Inline>),
+    Link { url: String, title: Option<String>, children: Vec<Inline> },
+    Image { url: String, title: Option<String>, children: Vec<Inline> },
+    // TeX source between `$...$`, or `$$...$$` when `display`
+    Math { tex: String, display: bool },
+    // `number` and `index` (which reference to this footnote it is) are
+    // assigned by the block renderer once document order is known
+    FootnoteReference { label: String, number: usize, index: usize },
+    // `[[target#heading|alias]]`; `path` is the note the target resolved to
+    // and `anchor` the heading's slug. An empty target is the current note.
+    WikiLink { target: String, path: Option<String>, anchor: Option<String>, label: String },
+    // `#tag` without the `#`, possibly nested like `project/alpha`
+    Tag(String),
+}
+
+// Target of a `[label]: url "title"` link reference definition
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct LinkReference {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Root,
+    Text(String),
+    Code(String),
+    SoftBreak,
+    HardBreak,
+    Html(String),
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Link { url: String, title: Option<String> },
+    Image { url: String, title: Option<String> },
+    Math { tex: String, display: bool },
+    FootnoteReference(String),
+    WikiLink { target: String, path: Option<String>, anchor: Option<String>, label: String },
+    Tag(String),
+}
+
+#[derive(Debug)]
+struct Node {
+    kind: NodeKind,
+    parent: Option<usize>,
+    first_child: Option<usize>,
+    last_child: Option<usize>,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Delimiter {
+    ch: u8,
+    count: usize,
+    original_count: usize,
+    node: usize,
+    can_open: bool,
+    can_close: bool,
+}
+
+#[derive(Debug)]
+struct Bracket {
+    node: usize,
+    // Subject position right after the `[`
+    position: usize,
+    image: bool,
+    active: bool,
+    bracket_after: bool,
+    previous_delimiter: usize,
+}
+
+const ROOT: usize = 0;
+
+// Inline parser following the CommonMark delimiter-stack algorithm, plus
+// GFM strikethrough. Nodes live in an arena and are linked as a tree so that
+// emphasis and links can re-parent ranges of already parsed siblings.
+pub struct InlineParser<'a> {
+    subject: &'a str,
+    pos: usize,
+    nodes: Vec<Node>,
+    delimiters: Vec<Delimiter>,
+    brackets: Vec<Bracket>,
+    footnotes: Option<&'a HashSet<String>>,
+    references: Option<&'a HashMap<String, LinkReference>>,
+    notes: Option<&'a NoteIndex>,
+    tags: bool,
+    autolinks: bool,
+    strikethrough: bool,
+    math: bool,
+    wikilinks: bool,
+}
+
+impl<'a> InlineParser<'a> {
+    pub fn new(subject: &'a str) -> Self {
+        let subject = subject.trim_matches(|c| c == ' ' || c == '\t' || c == '\n');
+        let mut nodes = Vec::with_capacity(subject.len() / 8 + 1);
+        nodes.push(Node {
+            kind: NodeKind::Root,
+            parent: None,
+            first_child: None,
+            last_child: None,
+            prev: None,
+            next: None,
+        });
+
+        Self {
+            subject,
+            pos: 0,
+            nodes,
+            delimiters: Vec::new(),
+            brackets: Vec::new(),
+            footnotes: None,
+            references: None,
+            notes: None,
+            tags: false,
+            autolinks: false,
+            strikethrough: true,
+            math: true,
+            wikilinks: true,
+        }
+    }
+
+    // Labels (normalized with `normalize_label`) of the footnotes defined in
+    // the document; `[^label]` only becomes a reference when it is defined.
+    pub fn with_footnotes(mut self, footnotes: &'a HashSet<String>) -> Self {
+        self.footnotes = Some(footnotes);
+        self
+    }
+
+    // Link reference definitions of the document, by normalized label, for
+    // `[text][label]`, `[label][]` and `[label]` links
+    pub fn with_references(mut self, references: &'a HashMap<String, LinkReference>) -> Self {
+        self.references = Some(references);
+        self
+    }
+
+    // Workspace notes `[[...]]` links are resolved against; without them
+    // wikilinks are left as plain text.
+    pub fn with_notes(mut self, notes: &'a NoteIndex) -> Self {
+        self.notes = Some(notes);
+        self
+    }
+
+    // Turns the optional syntax on or off to match the parser's options.
+    // Without them `#tags` and bare URLs are plain text.
+    pub fn with_options(mut self, options: &ParserOptions) -> Self {
+        self.tags = options.tags;
+        self.autolinks = options.autolinks;
+        self.strikethrough = options.strikethrough;
+        self.math = options.math;
+        self.wikilinks = options.wikilinks;
+        self
+    }
+
+    pub fn parse(mut self) -> Vec<Inline> {
+        while self.pos < self.subject.len() {
+            self.parse_inline();
+        }
+        self.process_emphasis(0);
+        let inlines = self.collect_children(ROOT);
+        if self.autolinks {
+            extend_autolinks(inlines)
+        } else {
+            inlines
+        }
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.subject.as_bytes().get(self.pos).copied()
+    }
+
+    fn parse_inline(&mut self) {
+        let handled = match self.subject.as_bytes()[self.pos] {
+            b'\n' => self.parse_newline(),
+            b'\\' => self.parse_backslash(),
+            b'`' => self.parse_backticks(),
+            b'$' => self.math && self.parse_math(),
+            b'*' | b'_' => self.handle_delim(),
+            b'~' => self.strikethrough && self.handle_delim(),
+            b'[' => self.parse_open_bracket(),
+            b'!' => self.parse_bang(),
+            b']' => self.parse_close_bracket(),
+            b'<' => self.parse_autolink() || self.parse_html_tag(),
+            b'&' => self.parse_entity(),
+            b'#' => self.parse_tag(),
+            _ => self.parse_string(),
+        };
+
+        if !handled {
+            let ch = self.subject[self.pos..].chars().next().unwrap_or('\u{FFFD}');
+            self.pos += ch.len_utf8();
+            self.push_text(ch.to_string());
+        }
+    }
+
+    fn parse_string(&mut self) -> bool {
+        let bytes = self.subject.as_bytes();
+        let start = self.pos;
+        let mut end = start;
+        while end < bytes.len() && !is_special_byte(bytes[end]) {
+            end += 1;
+        }
+        if end == start {
+            return false;
+        }
+        self.pos = end;
+        self.push_text(self.subject[start..end].to_string());
+        true
+    }
+
+    fn parse_newline(&mut self) -> bool {
+        self.pos += 1;
+
+        let mut hard_break = false;
+        if let Some(last) = self.nodes[ROOT].last_child {
+            if let NodeKind::Text(ref mut text) = self.nodes[last].kind {
+                if text.ends_with(' ') {
+                    hard_break = text.ends_with("  ");
+                    let trimmed_len = text.trim_end_matches(' ').len();
+                    text.truncate(trimmed_len);
+                }
+            }
+        }
+
+        let kind = if hard_break { NodeKind::HardBreak } else { NodeKind::SoftBreak };
+        self.push_node(kind);
+        self.skip_line_start_whitespace();
+        true
+    }
+
+    fn parse_backslash(&mut self) -> bool {
+        self.pos += 1;
+        match self.peek() {
+            Some(b'\n') => {
+                self.pos += 1;
+                self.push_node(NodeKind::HardBreak);
+                self.skip_line_start_whitespace();
+            }
+            Some(c) if c.is_ascii_punctuation() => {
+                self.pos += 1;
+                self.push_text((c as char).to_string());
+            }
+            _ => {
+                self.push_text("\\".to_string());
+            }
+        }
+        true
+    }
+
+    fn parse_backticks(&mut self) -> bool {
+        let bytes = self.subject.as_bytes();
+        let start = self.pos;
+        let mut after_open = start;
+        while after_open < bytes.len() && bytes[after_open] == b'`' {
+            after_open += 1;
+        }
+        let tick_count = after_open - start;
+
+        let mut i = after_open;
+        while i < bytes.len() {
+            if bytes[i] != b'`' {
+                i += 1;
+                continue;
+            }
+            let run_start = i;
+            while i < bytes.len() && bytes[i] == b'`' {
+                i += 1;
+            }
+            if i - run_start == tick_count {
+                let raw = self.subject[after_open..run_start].replace('\n', " ");
+                let content = if raw.len() >= 2
+                    && raw.starts_with(' ')
+                    && raw.ends_with(' ')
+                    && raw.bytes().any(|b| b != b' ')
+                {
+                    raw[1..raw.len() - 1].to_string()
+                } else {
+                    raw
+                };
+                self.pos = i;
+                self.push_node(NodeKind::Code(content));
+                return true;
+            }
+        }
+
+        self.pos = after_open;
+        self.push_text(self.subject[start..after_open].to_string());
+        true
+    }
+
+    // Math is taken verbatim, like a code span, so `_` and `*` inside it are
+    // left alone. An inline `$` must be followed by non-whitespace and its
+    // closing `$` preceded by non-whitespace and not followed by a digit, so
+    // that prices like "$5 and $10" stay text.
+    fn parse_math(&mut self) -> bool {
+        let rest = &self.subject[self.pos..];
+        let display = rest.starts_with("$$");
+        let delimiter = if display { "$$" } else { "$" };
+        let body = &rest[delimiter.len()..];
+        let bytes = body.as_bytes();
+
+        let mut close = None;
+        if display || body.starts_with(|ch: char| !ch.is_whitespace()) {
+            let mut i = 0;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' => i += 2,
+                    b'$' if display => {
+                        if bytes.get(i + 1) == Some(&b'$') {
+                            close = Some(i);
+                            break;
+                        }
+                        i += 1;
+                    }
+                    // `$$` never closes inline math
+                    b'$' if bytes.get(i + 1) == Some(&b'$') => i += 2,
+                    b'$' => {
+                        let after_whitespace = bytes[i - 1].is_ascii_whitespace();
+                        let before_digit = bytes.get(i + 1).is_some_and(u8::is_ascii_digit);
+                        if !after_whitespace && !before_digit {
+                            close = Some(i);
+                            break;
+                        }
+                        i += 1;
+                    }
+                    _ => i += 1,
+                }
+            }
+        }
+
+        match close.filter(|&close| !body[..close].trim().is_empty()) {
+            Some(close) => {
+                let tex = body[..close].trim().to_string();
+                self.pos += delimiter.len() * 2 + close;
+                self.push_node(NodeKind::Math { tex, display });
+            }
+            None => {
+                self.pos += delimiter.len();
+                self.push_text(delimiter.to_string());
+            }
+        }
+        true
+    }
+
+    fn handle_delim(&mut self) -> bool {
+        let bytes = self.subject.as_bytes();
+        let ch = bytes[self.pos];
+        let start = self.pos;
+        let mut end = start;
+        while end < bytes.len() && bytes[end] == ch {
+            end += 1;
+        }
+        let count = end - start;
+
+        let before = self.subject[..start].chars().next_back().unwrap_or('\n');
+        let after = self.subject[end..].chars().next().unwrap_or('\n');
+
+        let after_is_whitespace = after.is_whitespace();
+        let after_is_punctuation = is_punctuation(after);
+        let before_is_whitespace = before.is_whitespace();
+        let before_is_punctuation = is_punctuation(before);
+
+        let left_flanking = !after_is_whitespace
+            && (!after_is_punctuation || before_is_whitespace || before_is_punctuation);
+        let right_flanking = !before_is_whitespace
+            && (!before_is_punctuation || after_is_whitespace || after_is_punctuation);
+
+        let (can_open, can_close) = if ch == b'_' {
+            (
+                left_flanking && (!right_flanking || before_is_punctuation),
+                right_flanking && (!left_flanking || after_is_punctuation),
+            )
+        } else {
+            (left_flanking, right_flanking)
+        };
+
+        self.pos = end;
+        let node = self.push_text(self.subject[start..end].to_string());
+
+        // Strikethrough only pairs runs of one or two tildes
+        if ch == b'~' && count > 2 {
+            return true;
+        }
+
+        if can_open || can_close {
+            self.delimiters.push(Delimiter {
+                ch,
+                count,
+                original_count: count,
+                node,
+                can_open,
+                can_close,
+            });
+        }
+        true
+    }
+
+    fn parse_open_bracket(&mut self) -> bool {
+        if self.parse_wikilink() || self.parse_footnote_reference() {
+            return true;
+        }
+
+        self.pos += 1;
+        let node = self.push_text("[".to_string());
+        self.push_bracket(node, false);
+        true
+    }
+
+    fn parse_footnote_reference(&mut self) -> bool {
+        let Some(footnotes) = self.footnotes else {
+            return false;
+        };
+        let rest = &self.subject[self.pos..];
+        if !rest.starts_with("[^") {
+            return false;
+        }
+
+        let Some(close) = rest.find(']') else {
+            return false;
+        };
+        let label = &rest[2..close];
+        if label.is_empty() || label.contains(|ch: char| ch.is_whitespace() || ch == '[') {
+            return false;
+        }
+
+        let label = normalize_label(label);
+        if !footnotes.contains(&label) {
+            return false;
+        }
+
+        self.pos += close + 1;
+        self.push_node(NodeKind::FootnoteReference(label));
+        true
+    }
+
+    fn parse_wikilink(&mut self) -> bool {
+        let Some(notes) = self.notes.filter(|_| self.wikilinks) else {
+            return false;
+        };
+        let rest = &self.subject[self.pos..];
+        if !rest.starts_with("[[") {
+            return false;
+        }
+
+        let Some(close) = rest.find("]]") else {
+            return false;
+        };
+        let Some(link) = WikiLink::parse(&rest[2..close]) else {
+            return false;
+        };
+
+        let path = if link.target.is_empty() {
+            None
+        } else {
+            notes.resolve(&link.target).map(str::to_string)
+        };
+        let anchor = link.heading.as_deref().map(slugify);
+        let label = link.label();
+        self.pos += close + 2;
+        self.push_node(NodeKind::WikiLink {
+            target: link.target,
+            path,
+            anchor,
+            label,
+        });
+        true
+    }
+
+    // A tag starts after whitespace or an emphasis or bracket opener, so
+    // `a#b` and URL fragments are not tags, and needs at least one character
+    // that is not a digit, so `#123` is not one either.
+    fn parse_tag(&mut self) -> bool {
+        if !self.tags {
+            return false;
+        }
+        let starts_tag = |ch: char| ch.is_whitespace() || matches!(ch, '*' | '_' | '~' | '(' | '[');
+        if self.subject[..self.pos].chars().next_back().is_some_and(|ch| !starts_tag(ch)) {
+            return false;
+        }
+
+        let rest = &self.subject[self.pos + 1..];
+        let end = rest
+            .find(|ch: char| !(ch.is_alphanumeric() || matches!(ch, '_' | '-' | '/')))
+            .unwrap_or(rest.len());
+        let tag = rest[..end].trim_end_matches('/');
+        if tag.starts_with('/') || !tag.chars().any(|ch| !ch.is_numeric() && ch != '/') {
+            return false;
+        }
+
+        self.pos += 1 + tag.len();
+        self.push_node(NodeKind::Tag(tag.to_string()));
+        true
+    }
+
+    fn parse_bang(&mut self) -> bool {
+        // Embeds are rendered by the block renderer when they stand on their
+        // own lines; a note can't be embedded mid-paragraph, so anywhere else
+        // `![[Note]]` stays literal text
+        let rest = &self.subject[self.pos..];
+        if self.wikilinks && self.notes.is_some() && rest.starts_with("![[") {
+            if let Some(close) = rest.find("]]").filter(|&close| WikiLink::parse(&rest[3..close]).is_some()) {
+                let text = rest[..close + 2].to_string();
+                self.pos += text.len();
+                self.push_text(text);
+                return true;
+            }
+        }
+
+        self.pos += 1;
+        if self.peek() == Some(b'[') {
+            self.pos += 1;
+            let node = self.push_text("![".to_string());
+            self.push_bracket(node, true);
+        } else {
+            self.push_text("!".to_string());
+        }
+        true
+    }
+
+    fn push_bracket(&mut self, node: usize, image: bool) {
+        if let Some(previous) = self.brackets.last_mut() {
+            previous.bracket_after = true;
+        }
+        self.brackets.push(Bracket {
+            node,
+            position: self.pos,
+            image,
+            active: true,
+            bracket_after: false,
+            previous_delimiter: self.delimiters.len(),
+        });
+    }
+
+    fn parse_close_bracket(&mut self) -> bool {
+        self.pos += 1;
+        let start = self.pos;
+
+        let Some(opener) = self.brackets.last() else {
+            self.push_text("]".to_string());
+            return true;
+        };
+
+        if !opener.active {
+            self.brackets.pop();
+            self.push_text("]".to_string());
+            return true;
+        }
+
+        let is_image = opener.image;
+        let text_start = opener.position;
+        let bracket_after = opener.bracket_after;
+        let mut target = None;
+
+        if self.peek() == Some(b'(') {
+            self.pos += 1;
+            target = self.parse_inline_link_target();
+            if target.is_none() {
+                self.pos = start;
+            }
+        }
+
+        if target.is_none() {
+            target = self.parse_reference_link(text_start, bracket_after);
+        }
+
+        let Some((url, title)) = target else {
+            self.brackets.pop();
+            self.pos = start;
+            self.push_text("]".to_string());
+            return true;
+        };
+
+        let opener = self.brackets.pop().expect("bracket checked above");
+        let kind = if is_image {
+            NodeKind::Image { url, title }
+        } else {
+            NodeKind::Link { url, title }
+        };
+        let link = self.new_node(kind);
+
+        let mut current = self.nodes[opener.node].next;
+        while let Some(child) = current {
+            current = self.nodes[child].next;
+            self.unlink(child);
+            self.append_child(link, child);
+        }
+        self.append_child(ROOT, link);
+
+        self.process_emphasis(opener.previous_delimiter);
+        self.unlink(opener.node);
+
+        // Links may not contain other links, so earlier link openers die here
+        if !is_image {
+            for bracket in self.brackets.iter_mut() {
+                if !bracket.image {
+                    bracket.active = false;
+                }
+            }
+        }
+
+        true
+    }
+
+    // Looks up `[label]` after the link text, or the link text itself for
+    // collapsed `[text][]` and shortcut `[text]` references. Called right
+    // after the closing `]`; the position is restored when nothing matches.
+    fn parse_reference_link(&mut self, text_start: usize, bracket_after: bool) -> Option<(String, Option<String>)> {
+        let references = self.references?;
+        let text_end = self.pos;
+
+        let mut label = None;
+        if self.peek() == Some(b'[') {
+            label = self.parse_link_label();
+            if label.is_none() {
+                self.pos = text_end;
+            }
+        }
+
+        // Link text containing brackets can't be a label
+        let label = match label {
+            Some(label) if !label.is_empty() => label,
+            _ if !bracket_after => &self.subject[text_start..text_end - 1],
+            _ => {
+                self.pos = text_end;
+                return None;
+            }
+        };
+
+        match references.get(&normalize_label(label)) {
+            Some(reference) => Some((reference.url.clone(), reference.title.clone())),
+            None => {
+                self.pos = text_end;
+                None
+            }
+        }
+    }
+
+    // `[label]`: at most 999 characters, without unescaped brackets
+    fn parse_link_label(&mut self) -> Option<&'a str> {
+        let subject = self.subject;
+        let bytes = subject.as_bytes();
+        let start = self.pos + 1;
+        let mut i = start;
+        while i < bytes.len() && i - start <= 999 {
+            match bytes[i] {
+                b'\\' if i + 1 < bytes.len() && bytes[i + 1].is_ascii_punctuation() => i += 2,
+                b'[' => return None,
+                b']' => {
+                    self.pos = i + 1;
+                    return Some(&subject[start..i]);
+                }
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    // `[label]: destination "title"` at the start of the subject, which has
+    // to end its line
+    fn parse_reference_definition(&mut self) -> Option<(String, LinkReference)> {
+        if self.peek() != Some(b'[') {
+            return None;
+        }
+        let label = self.parse_link_label()?;
+        let label = normalize_label(label);
+        if label.is_empty() || self.peek() != Some(b':') {
+            return None;
+        }
+        self.pos += 1;
+
+        self.skip_spaces_and_newline();
+        let destination_start = self.pos;
+        let destination = self.parse_link_destination()?;
+        if destination.is_empty() && self.subject.as_bytes()[destination_start] != b'<' {
+            return None;
+        }
+
+        let before_title = self.pos;
+        self.skip_spaces_and_newline();
+        let mut title = None;
+        if self.pos > before_title {
+            title = self.parse_link_title();
+        }
+        if title.is_none() || !self.at_line_end() {
+            // The title may be left out, leaving its line to the paragraph
+            title = None;
+            self.pos = before_title;
+            if !self.at_line_end() {
+                return None;
+            }
+        }
+
+        Some((
+            label,
+            LinkReference {
+                url: normalize_uri(&unescape_string(&destination)),
+                title: title.map(|title| unescape_string(&title)),
+            },
+        ))
+    }
+
+    // Skips trailing spaces and the line ending; false when other text follows
+    fn at_line_end(&mut self) -> bool {
+        self.skip_line_start_whitespace();
+        match self.peek() {
+            None => true,
+            Some(b'\n') => {
+                self.pos += 1;
+                true
+            }
+            Some(_) => false,
+        }
+    }
+
+    fn parse_inline_link_target(&mut self) -> Option<(String, Option<String>)> {
+        self.skip_spaces_and_newline();
+        let destination = self.parse_link_destination()?;
+
+        let before_title = self.pos;
+        self.skip_spaces_and_newline();
+        let mut title = None;
+        if self.pos > before_title {
+            let save = self.pos;
+            title = self.parse_link_title();
+            if title.is_none() {
+                self.pos = save;
+            }
+        }
+        self.skip_spaces_and_newline();
+
+        if self.peek() != Some(b')') {
+            return None;
+        }
+        self.pos += 1;
+
+        Some((normalize_uri(&unescape_string(&destination)), title.map(|t| unescape_string(&t))))
+    }
+
+    fn parse_link_destination(&mut self) -> Option<String> {
+        let bytes = self.subject.as_bytes();
+        let start = self.pos;
+
+        if bytes.get(start) == Some(&b'<') {
+            let mut i = start + 1;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'\\' if i + 1 < bytes.len() && bytes[i + 1].is_ascii_punctuation() => i += 2,
+                    b'\n' | b'<' => return None,
+                    b'>' => {
+                        self.pos = i + 1;
+                        return Some(self.subject[start + 1..i].to_string());
+                    }
+                    _ => i += 1,
+                }
+            }
+            return None;
+        }
+
+        let mut open_parens = 0usize;
+        let mut i = start;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if i + 1 < bytes.len() && bytes[i + 1].is_ascii_punctuation() => i += 2,
+                b'(' => {
+                    open_parens += 1;
+                    i += 1;
+                }
+                b')' => {
+                    if open_parens == 0 {
+                        break;
+                    }
+                    open_parens -= 1;
+                    i += 1;
+                }
+                b if b <= b' ' || b == 0x7f => break,
+                _ => i += 1,
+            }
+        }
+
+        if i == start && bytes.get(i) != Some(&b')') {
+            return None;
+        }
+        if open_parens != 0 {
+            return None;
+        }
+
+        self.pos = i;
+        Some(self.subject[start..i].to_string())
+    }
+
+    fn parse_link_title(&mut self) -> Option<String> {
+        let bytes = self.subject.as_bytes();
+        let start = self.pos;
+        let close = match bytes.get(start)? {
+            b'"' => b'"',
+            b'\'' => b'\'',
+            b'(' => b')',
+            _ => return None,
+        };
+
+        let mut i = start + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' if i + 1 < bytes.len() => i += 2,
+                b if b == close => {
+                    self.pos = i + 1;
+                    return Some(self.subject[start + 1..i].to_string());
+                }
+                b'(' if close == b')' => return None,
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    // `<scheme:...>` and `<user@example.com>`
+    fn parse_autolink(&mut self) -> bool {
+        let rest = &self.subject[self.pos + 1..];
+        let Some(end) = rest.find(['>', '<', '\n']) else {
+            return false;
+        };
+        if rest.as_bytes()[end] != b'>' {
+            return false;
+        }
+
+        let inner = &rest[..end];
+        let url = if is_uri_autolink(inner) {
+            normalize_uri(inner)
+        } else if is_email_autolink(inner) {
+            format!("mailto:{}", normalize_uri(inner))
+        } else {
+            return false;
+        };
+
+        self.pos += end + 2;
+        let link = self.push_node(NodeKind::Link { url, title: None });
+        let text = self.new_node(NodeKind::Text(inner.to_string()));
+        self.append_child(link, text);
+        true
+    }
+
+    fn parse_html_tag(&mut self) -> bool {
+        match scan_html_tag(&self.subject.as_bytes()[self.pos..]) {
+            Some(len) => {
+                let html = self.subject[self.pos..self.pos + len].to_string();
+                self.pos += len;
+                self.push_node(NodeKind::Html(html));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn parse_entity(&mut self) -> bool {
+        match decode_entity(&self.subject[self.pos..]) {
+            Some((decoded, len)) => {
+                self.pos += len;
+                self.push_text(decoded);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn skip_line_start_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ') | Some(b'\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_spaces_and_newline(&mut self) {
+        self.skip_line_start_whitespace();
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+            self.skip_line_start_whitespace();
+        }
+    }
+
+    fn process_emphasis(&mut self, stack_bottom: usize) {
+        // Lower bounds for opener searches, one per closer kind, so that
+        // unmatched runs are not rescanned (keeps pathological input linear)
+        let mut openers_bottom = [stack_bottom; 14];
+        let mut closer = stack_bottom;
+
+        while closer < self.delimiters.len() {
+            if !self.delimiters[closer].can_close {
+                closer += 1;
+                continue;
+            }
+
+            let bottom_index = opener_bottom_index(&self.delimiters[closer]);
+            let mut opener_found = None;
+            let mut candidate = closer;
+            while candidate > openers_bottom[bottom_index].max(stack_bottom) {
+                candidate -= 1;
+                if self.delimiters_match(candidate, closer) {
+                    opener_found = Some(candidate);
+                    break;
+                }
+            }
+
+            let Some(opener) = opener_found else {
+                openers_bottom[bottom_index] = closer;
+                if !self.delimiters[closer].can_open {
+                    self.remove_delimiter(closer, &mut openers_bottom);
+                } else {
+                    closer += 1;
+                }
+                continue;
+            };
+
+            let ch = self.delimiters[closer].ch;
+            let used = if ch == b'~' {
+                self.delimiters[closer].count
+            } else if self.delimiters[closer].count >= 2 && self.delimiters[opener].count >= 2 {
+                2
+            } else {
+                1
+            };
+
+            let opener_node = self.delimiters[opener].node;
+            let closer_node = self.delimiters[closer].node;
+            self.delimiters[opener].count -= used;
+            self.delimiters[closer].count -= used;
+            self.shrink_text(opener_node, used);
+            self.shrink_text(closer_node, used);
+
+            let kind = match (ch, used) {
+                (b'~', _) => NodeKind::Strikethrough,
+                (_, 1) => NodeKind::Emphasis,
+                _ => NodeKind::Strong,
+            };
+            let wrapper = self.new_node(kind);
+
+            let mut current = self.nodes[opener_node].next;
+            while let Some(node) = current {
+                if node == closer_node {
+                    break;
+                }
+                current = self.nodes[node].next;
+                self.unlink(node);
+                self.append_child(wrapper, node);
+            }
+            self.insert_after(opener_node, wrapper);
+
+            for _ in opener + 1..closer {
+                self.remove_delimiter(opener + 1, &mut openers_bottom);
+            }
+            closer = opener + 1;
+
+            if self.delimiters[opener].count == 0 {
+                self.unlink(opener_node);
+                self.remove_delimiter(opener, &mut openers_bottom);
+                closer -= 1;
+            }
+
+            if self.delimiters[closer].count == 0 {
+                self.unlink(closer_node);
+                self.remove_delimiter(closer, &mut openers_bottom);
+            }
+        }
+
+        self.delimiters.truncate(stack_bottom);
+    }
+
+    fn delimiters_match(&self, opener: usize, closer: usize) -> bool {
+        let o = &self.delimiters[opener];
+        let c = &self.delimiters[closer];
+        if o.ch != c.ch || !o.can_open {
+            return false;
+        }
+        if c.ch == b'~' {
+            return o.count == c.count;
+        }
+
+        // "Rule of three": a run that can both open and close only pairs with
+        // another run if the sum of their lengths is not a multiple of three
+        let odd_match = (c.can_open || o.can_close)
+            && !c.original_count.is_multiple_of(3)
+            && (o.original_count + c.original_count).is_multiple_of(3);
+        !odd_match
+    }
+
+    fn remove_delimiter(&mut self, index: usize, openers_bottom: &mut [usize; 14]) {
+        self.delimiters.remove(index);
+        for bottom in openers_bottom.iter_mut() {
+            if *bottom > index {
+                *bottom -= 1;
+            }
+        }
+    }
+
+    fn shrink_text(&mut self, node: usize, by: usize) {
+        if let NodeKind::Text(ref mut text) = self.nodes[node].kind {
+            let len = text.len().saturating_sub(by);
+            text.truncate(len);
+        }
+    }
+
+    fn new_node(&mut self, kind: NodeKind) -> usize {
+        self.nodes.push(Node {
+            kind,
+            parent: None,
+            first_child: None,
+            last_child: None,
+            prev: None,
+            next: None,
+        });
+        self.nodes.len() - 1
+    }
+
+    fn push_node(&mut self, kind: NodeKind) -> usize {
+        let node = self.new_node(kind);
+        self.append_child(ROOT, node);
+        node
+    }
+
+    #[inline]
+    fn push_text(&mut self, text: String) -> usize {
+        self.push_node(NodeKind::Text(text))
+    }
+
+    fn append_child(&mut self, parent: usize, child: usize) {
+        self.nodes[child].parent = Some(parent);
+        self.nodes[child].next = None;
+        match self.nodes[parent].last_child {
+            Some(last) => {
+                self.nodes[last].next = Some(child);
+                self.nodes[child].prev = Some(last);
+            }
+            None => {
+                self.nodes[parent].first_child = Some(child);
+                self.nodes[child].prev = None;
+            }
+        }
+        self.nodes[parent].last_child = Some(child);
+    }
+
+    fn insert_after(&mut self, node: usize, sibling: usize) {
+        let parent = self.nodes[node].parent;
+        let next = self.nodes[node].next;
+
+        self.nodes[sibling].parent = parent;
+        self.nodes[sibling].prev = Some(node);
+        self.nodes[sibling].next = next;
+        self.nodes[node].next = Some(sibling);
+
+        match next {
+            Some(next) => self.nodes[next].prev = Some(sibling),
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent].last_child = Some(sibling);
+                }
+            }
+        }
+    }
+
+    fn unlink(&mut self, node: usize) {
+        let prev = self.nodes[node].prev;
+        let next = self.nodes[node].next;
+        let parent = self.nodes[node].parent;
+
+        match prev {
+            Some(prev) => self.nodes[prev].next = next,
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent].first_child = next;
+                }
+            }
+        }
+        match next {
+            Some(next) => self.nodes[next].prev = prev,
+            None => {
+                if let Some(parent) = parent {
+                    self.nodes[parent].last_child = prev;
+                }
+            }
+        }
+
+        self.nodes[node].parent = None;
+        self.nodes[node].prev = None;
+        self.nodes[node].next = None;
+    }
+
+    fn collect_children(&mut self, parent: usize) -> Vec<Inline> {
+        let mut result: Vec<Inline> = Vec::new();
+        let mut current = self.nodes[parent].first_child;
+
+        while let Some(node) = current {
+            current = self.nodes[node].next;
+            let kind = std::mem::replace(&mut self.nodes[node].kind, NodeKind::Root);
+            let inline = match kind {
+                NodeKind::Root => continue,
+                NodeKind::Text(text) => {
+                    if text.is_empty() {
+                        continue;
+                    }
+                    if let Some(Inline::Text(previous)) = result.last_mut() {
+                        previous.push_str(&text);
+                        continue;
+                    }
+                    Inline::Text(text)
+                }
+                NodeKind::Code(code) => Inline::Code(code),
+                NodeKind::SoftBreak => Inline::SoftBreak,
+                NodeKind::HardBreak => Inline::HardBreak,
+                NodeKind::Html(html) => Inline::Html(html),
+                NodeKind::Emphasis => Inline::Emphasis(self.collect_children(node)),
+                NodeKind::Strong => Inline::Strong(self.collect_children(node)),
+                NodeKind::Strikethrough => Inline::Strikethrough(self.collect_children(node)),
+                NodeKind::Link { url, title } => Inline::Link {
+                    url,
+                    title,
+                    children: self.collect_children(node),
+                },
+                NodeKind::Image { url, title } => Inline::Image {
+                    url,
+                    title,
+                    children: self.collect_children(node),
+                },
+                NodeKind::Math { tex, display } => Inline::Math { tex, display },
+                NodeKind::FootnoteReference(label) => Inline::FootnoteReference {
+                    label,
+                    number: 0,
+                    index: 0,
+                },
+                NodeKind::WikiLink { target, path, anchor, label } => Inline::WikiLink { target, path, anchor, label },
+                NodeKind::Tag(tag) => Inline::Tag(tag),
+            };
+            result.push(inline);
+        }
+
+        result
+    }
+}
+
+#[inline]
+fn is_special_byte(b: u8) -> bool {
+    matches!(
+        b,
+        b'\n' | b'\\' | b'`' | b'*' | b'_' | b'~' | b'[' | b']' | b'!' | b'<' | b'&' | b'$' | b'#'
+    )
+}
+
+// Parses a link reference definition at the start of paragraph text,
+// returning its label and target and how many bytes of `text` it took up.
+pub fn parse_reference_definition(text: &str) -> Option<(String, LinkReference, usize)> {
+    let leading = text.len() - text.trim_start_matches([' ', '\t', '\n']).len();
+    let mut parser = InlineParser::new(text);
+    let (label, reference) = parser.parse_reference_definition()?;
+    Some((label, reference, leading + parser.pos))
+}
+
+// Scheme of 2 to 32 characters, then anything but spaces, controls and `<`
+fn is_uri_autolink(text: &str) -> bool {
+    let Some((scheme, rest)) = text.split_once(':') else {
+        return false;
+    };
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+        && scheme.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'.' | b'-'))
+        && !rest.bytes().any(|b| b <= b' ' || b == b'<' || b == 0x7f)
+}
+
+fn is_email_autolink(text: &str) -> bool {
+    let Some((local, domain)) = text.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && local.bytes().all(|b| b.is_ascii_alphanumeric() || b".!#$%&'*+/=?^_`{|}~-".contains(&b))
+        && !domain.is_empty()
+        && domain.split('.').all(|label| {
+            (1..=63).contains(&label.len())
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+// Turns bare URLs and email addresses in text into links, outside of links
+// and code, following the GFM autolink extension.
+fn extend_autolinks(inlines: Vec<Inline>) -> Vec<Inline> {
+    let mut result = Vec::with_capacity(inlines.len());
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => autolink_text(&text, &mut result),
+            Inline::Emphasis(children) => result.push(Inline::Emphasis(extend_autolinks(children))),
+            Inline::Strong(children) => result.push(Inline::Strong(extend_autolinks(children))),
+            Inline::Strikethrough(children) => result.push(Inline::Strikethrough(extend_autolinks(children))),
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+fn autolink_text(text: &str, out: &mut Vec<Inline>) {
+    let mut last = 0;
+    let mut pos = 0;
+    let mut previous: Option<char> = None;
+
+    while let Some(ch) = text[pos..].chars().next() {
+        let at_boundary = match previous {
+            Some(previous) => previous.is_whitespace() || matches!(previous, '*' | '_' | '~' | '('),
+            None => true,
+        };
+        let found = if at_boundary {
+            scan_extended_url(&text[pos..])
+        } else {
+            None
+        }
+        .or_else(|| {
+            // An address starts where its run of local-part characters does
+            if is_email_local_char(ch) && !previous.is_some_and(is_email_local_char) {
+                scan_extended_email(&text[pos..])
+            } else {
+                None
+            }
+        });
+
+        if let Some((len, url)) = found {
+            if last < pos {
+                out.push(Inline::Text(text[last..pos].to_string()));
+            }
+            out.push(Inline::Link {
+                url,
+                title: None,
+                children: vec![Inline::Text(text[pos..pos + len].to_string())],
+            });
+            pos += len;
+            last = pos;
+            previous = text[..pos].chars().next_back();
+            continue;
+        }
+
+        pos += ch.len_utf8();
+        previous = Some(ch);
+    }
+
+    if last < text.len() {
+        out.push(Inline::Text(text[last..].to_string()));
+    }
+}
+
+// `www.` or `http(s)://` followed by a valid domain and an optional path.
+// Returns the length of the link text and its URL.
+fn scan_extended_url(text: &str) -> Option<(usize, String)> {
+    let (prefix, www) = if text.starts_with("www.") {
+        (0, true)
+    } else if text.starts_with("http://") {
+        (7, false)
+    } else if text.starts_with("https://") {
+        (8, false)
+    } else {
+        return None;
+    };
+
+    let domain_len = text[prefix..]
+        .find(|ch: char| !(ch.is_alphanumeric() || matches!(ch, '-' | '_' | '.')))
+        .unwrap_or(text.len() - prefix);
+    let domain = text[prefix..prefix + domain_len].trim_end_matches('.');
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2
+        || labels.iter().any(|label| label.is_empty())
+        || labels[labels.len() - 2..].iter().any(|label| label.contains('_'))
+    {
+        return None;
+    }
+
+    let end = text.find(|ch: char| ch.is_whitespace() || ch == '<').unwrap_or(text.len());
+    let len = trim_autolink_end(&text[..end]);
+    if len < prefix + domain.len() {
+        return None;
+    }
+
+    let link = &text[..len];
+    let url = if www { format!("http://{}", link) } else { link.to_string() };
+    Some((len, normalize_uri(&url)))
+}
+
+// Drops trailing punctuation, unbalanced closing parentheses and entity-like
+// `&name;` suffixes from the end of an extended autolink
+fn trim_autolink_end(link: &str) -> usize {
+    let mut end = link.len();
+    loop {
+        let current = &link[..end];
+        match current.chars().next_back() {
+            Some('?' | '!' | '.' | ',' | ':' | '*' | '_' | '~') => end -= 1,
+            Some(')') if current.matches(')').count() > current.matches('(').count() => end -= 1,
+            Some(';') => {
+                let name = current[..end - 1]
+                    .trim_end_matches(|ch: char| ch.is_ascii_alphanumeric());
+                if name.ends_with('&') && name.len() < end - 1 {
+                    end = name.len() - 1;
+                } else {
+                    return end;
+                }
+            }
+            _ => return end,
+        }
+    }
+}
+
+#[inline]
+fn is_email_local_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || matches!(ch, '.' | '+' | '-' | '_')
+}
+
+fn scan_extended_email(text: &str) -> Option<(usize, String)> {
+    let at = text.find(|ch: char| !is_email_local_char(ch))?;
+    if at == 0 || !text[at..].starts_with('@') {
+        return None;
+    }
+
+    let domain_len = text[at + 1..]
+        .find(|ch: char| !(ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.')))
+        .unwrap_or(text.len() - at - 1);
+    let domain = text[at + 1..at + 1 + domain_len].trim_end_matches('.');
+    if !domain.contains('.') || domain.ends_with(['-', '_']) || domain.starts_with('.') {
+        return None;
+    }
+
+    let len = at + 1 + domain.len();
+    Some((len, format!("mailto:{}", &text[..len])))
+}
+
+#[inline]
+fn opener_bottom_index(delimiter: &Delimiter) -> usize {
+    match delimiter.ch {
+        b'~' => 12 + delimiter.original_count.min(2) - 1,
+        ch => {
+            let base = if ch == b'*' { 0 } else { 6 };
+            base + if delimiter.can_open { 3 } else { 0 } + delimiter.original_count % 3
+        }
+    }
+}
+
+#[inline]
+pub fn is_punctuation(ch: char) -> bool {
+    if ch.is_ascii() {
+        ch.is_ascii_punctuation()
+    } else {
+        !ch.is_alphanumeric() && !ch.is_whitespace() && !ch.is_control()
+    }
+}
+
+// Decodes an entity or numeric character reference at the start of `text`,
+// returning the replacement and the number of bytes consumed.
+pub fn decode_entity(text: &str) -> Option<(String, usize)> {
+    let bytes = text.as_bytes();
+    if bytes.first() != Some(&b'&') {
+        return None;
+    }
+
+    if bytes.get(1) == Some(&b'#') {
+        let (digits_start, radix, max_digits) = match bytes.get(2) {
+            Some(b'x') | Some(b'X') => (3, 16, 6),
+            _ => (2, 10, 7),
+        };
+        let mut end = digits_start;
+        while end < bytes.len() && (bytes[end] as char).is_digit(radix) {
+            end += 1;
+        }
+        let digit_count = end - digits_start;
+        if digit_count == 0 || digit_count > max_digits || bytes.get(end) != Some(&b';') {
+            return None;
+        }
+        let code = u32::from_str_radix(&text[digits_start..end], radix).ok()?;
+        let ch = match code {
+            0 => '\u{FFFD}',
+            _ => char::from_u32(code).unwrap_or('\u{FFFD}'),
+        };
+        return Some((ch.to_string(), end + 1));
+    }
+
+    let mut end = 1;
+    while end < bytes.len() && end <= 32 && bytes[end].is_ascii_alphanumeric() {
+        end += 1;
+    }
+    if end == 1 || bytes.get(end) != Some(&b';') {
+        return None;
+    }
+    entities::lookup(&text[1..end]).map(|decoded| (decoded.to_string(), end + 1))
+}
+
+// Resolves backslash escapes and entities, as used for link destinations,
+// titles and code fence info strings.
+pub fn unescape_string(text: &str) -> String {
+    if !text.contains(['\\', '&']) {
+        return text.to_string();
+    }
+
+    let bytes = text.as_bytes();
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        match bytes[i] {
+            b'\\' if i + 1 < bytes.len() && bytes[i + 1].is_ascii_punctuation() => {
+                result.push(bytes[i + 1] as char);
+                i += 2;
+            }
+            b'&' => match decode_entity(&text[i..]) {
+                Some((decoded, len)) => {
+                    result.push_str(&decoded);
+                    i += len;
+                }
+                None => {
+                    result.push('&');
+                    i += 1;
+                }
+            },
+            _ => {
+                let ch = text[i..].chars().next().unwrap_or('\u{FFFD}');
+                result.push(ch);
+                i += ch.len_utf8();
+            }
+        }
+    }
+    result
+}
+
+// Percent-encodes a link destination, leaving existing `%XX` escapes intact.
+pub fn normalize_uri(uri: &str) -> String {
+    const HEX: &[u8; 16] = b"0123456789ABCDEF";
+    let bytes = uri.as_bytes();
+    let mut result = String::with_capacity(uri.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if b == b'%'
+            && i + 2 < bytes.len()
+            && bytes[i + 1].is_ascii_hexdigit()
+            && bytes[i + 2].is_ascii_hexdigit()
+        {
+            result.push_str(&uri[i..i + 3]);
+            i += 3;
+            continue;
+        }
+        if b.is_ascii_alphanumeric() || b";/?:@&=+$,-_.!~*'()#".contains(&b) {
+            result.push(b as char);
+        } else {
+            result.push('%');
+            result.push(HEX[(b >> 4) as usize] as char);
+            result.push(HEX[(b & 0x0f) as usize] as char);
+        }
+        i += 1;
+    }
+    result
+}
+
+#[inline]
+pub fn escape_html(text: &str) -> String {
+    if !text.as_bytes().iter().any(|&b| matches!(b, b'&' | b'<' | b'>' | b'"' | b'\'')) {
+        return text.to_string();
+    }
+
+    let mut result = String::with_capacity(text.len() + (text.len() >> 3));
+    for ch in text.chars() {
+        match ch {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#x27;"),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+pub fn render_html(inlines: &[Inline], out: &mut String) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text) => out.push_str(&escape_html(text)),
+            Inline::Code(code) => {
+                out.push_str("<code>");
+                out.push_str(&escape_html(code));
+                out.push_str("</code>");
+            }
+            Inline::SoftBreak => out.push('\n'),
+            Inline::HardBreak => out.push_str("<br>\n"),
+            Inline::Html(html) => sanitize_html(html, out),
+            Inline::Emphasis(children) => {
+                out.push_str("<em>");
+                render_html(children, out);
+                out.push_str("</em>");
+            }
+            Inline::Strong(children) => {
+                out.push_str("<strong>");
+                render_html(children, out);
+                out.push_str("</strong>");
+            }
+            Inline::Strikethrough(children) => {
+                out.push_str("<del>");
+                render_html(children, out);
+                out.push_str("</del>");
+            }
+            Inline::Link { url, title, children } => {
+                out.push_str("<a href=\"");
+                if is_safe_url(url, false) {
+                    out.push_str(&escape_html(url));
+                }
+                out.push('"');
+                if let Some(title) = title {
+                    out.push_str(" title=\"");
+                    out.push_str(&escape_html(title));
+                    out.push('"');
+                }
+                out.push('>');
+                render_html(children, out);
+                out.push_str("</a>");
+            }
+            Inline::Image { url, title, children } => {
+                let mut alt = String::new();
+                plain_text(children, &mut alt);
+                out.push_str("<img src=\"");
+                if is_safe_url(url, true) {
+                    out.push_str(&escape_html(url));
+                }
+                out.push_str("\" alt=\"");
+                out.push_str(&escape_html(&alt));
+                out.push('"');
+                if let Some(title) = title {
+                    out.push_str(" title=\"");
+                    out.push_str(&escape_html(title));
+                    out.push('"');
+                }
+                out.push_str(" loading=\"lazy\">");
+            }
+            Inline::Math { tex, display } => render_math(tex, *display, out),
+            Inline::FootnoteReference { number, index, .. } => {
+                out.push_str(&format!(
+                    "<sup class=\"footnote-ref\"><a href=\"#fn-{0}\" id=\"{1}\" data-footnote-ref>{0}</a></sup>",
+                    number,
+                    footnote_reference_id(*number, *index)
+                ));
+            }
+            Inline::WikiLink { target, path, anchor, label } => {
+                if target.is_empty() {
+                    out.push_str("<a class=\"wikilink\" href=\"#");
+                    out.push_str(&escape_html(anchor.as_deref().unwrap_or_default()));
+                    out.push('"');
+                } else if let Some(path) = path {
+                    // Opened by the preview's click handler, not by navigation
+                    out.push_str("<a class=\"wikilink\" data-path=\"");
+                    out.push_str(&escape_html(path));
+                    out.push('"');
+                    if let Some(anchor) = anchor {
+                        out.push_str(" data-heading=\"");
+                        out.push_str(&escape_html(anchor));
+                        out.push('"');
+                    }
+                } else {
+                    out.push_str("<a class=\"wikilink wikilink-unresolved\" data-target=\"");
+                    out.push_str(&escape_html(target));
+                    out.push('"');
+                }
+                out.push('>');
+                out.push_str(&escape_html(label));
+                out.push_str("</a>");
+            }
+            Inline::Tag(tag) => {
+                let tag = escape_html(tag);
+                out.push_str(&format!("<span class=\"tag\" data-tag=\"{0}\">#{0}</span>", tag));
+            }
+        }
+    }
+}
+
+// Id of the `index`-th reference (1-based) to footnote `number`, which the
+// footnote's backlinks point to.
+pub fn footnote_reference_id(number: usize, index: usize) -> String {
+    if index > 1 {
+        format!("fnref-{}-{}", number, index)
+    } else {
+        format!("fnref-{}", number)
+    }
+}
+
+// Normalizes a link or footnote label for matching: case-folded, with runs
+// of whitespace collapsed to a single space.
+pub fn normalize_label(label: &str) -> String {
+    label.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// GitHub-compatible anchor for a heading: lowercased, punctuation dropped and
+// spaces turned into hyphens.
+pub fn slugify(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .filter_map(|ch| match ch {
+            ' ' => Some('-'),
+            '-' | '_' => Some(ch),
+            _ if ch.is_alphanumeric() => Some(ch),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn plain_text(inlines: &[Inline], out: &mut String) {
+    for inline in inlines {
+        match inline {
+            Inline::Text(text)
+            | Inline::Code(text)
+            | Inline::Math { tex: text, .. }
+            | Inline::WikiLink { label: text, .. } => out.push_str(text),
+            Inline::Tag(tag) => {
+                out.push('#');
+                out.push_str(tag);
+            }
+            Inline::SoftBreak | Inline::HardBreak => out.push('\n'),
+            Inline::Html(_) | Inline::FootnoteReference { .. } => {}
+            Inline::Emphasis(children)
+            | Inline::Strong(children)
+            | Inline::Strikethrough(children)
+            | Inline::Link { children, .. }
+            | Inline::Image { children, .. } => plain_text(children, out),
+        }
+    }
+}
+
+// Returns the length of the raw HTML tag, comment, processing instruction,
+// declaration or CDATA section at the start of `bytes`, if any.
+pub fn scan_html_tag(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 3 || bytes[0] != b'<' {
+        return None;
+    }
+
+    match bytes[1] {
+        b'!' => {
+            if bytes[2..].starts_with(b"--") {
+                let rest = &bytes[4..];
+                if rest.starts_with(b">") {
+                    return Some(5);
+                }
+                if rest.starts_with(b"->") {
+                    return Some(6);
+                }
+                find_bytes(rest, b"-->").map(|index| 4 + index + 3)
+            } else if bytes[2..].starts_with(b"[CDATA[") {
+                find_bytes(&bytes[9..], b"]]>").map(|index| 9 + index + 3)
+            } else if bytes[2].is_ascii_alphabetic() {
+                bytes[2..].iter().position(|&b| b == b'>').map(|index| 2 + index + 1)
+            } else {
+                None
+            }
+        }
+        b'?' => find_bytes(&bytes[2..], b"?>").map(|index| 2 + index + 2),
+        _ => scan_open_or_closing_tag(bytes),
+    }
+}
+
+// Matches only `<tag ...>` and `</tag>`, the forms that may start an HTML
+// block of type 7.
+pub fn scan_open_or_closing_tag(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 3 || bytes[0] != b'<' {
+        return None;
+    }
+
+    if bytes[1] == b'/' {
+        let mut i = scan_tag_name(bytes, 2)?;
+        i = skip_ascii_whitespace(bytes, i);
+        return (bytes.get(i) == Some(&b'>')).then_some(i + 1);
+    }
+
+    let mut i = scan_tag_name(bytes, 1)?;
+    loop {
+        let after_whitespace = skip_ascii_whitespace(bytes, i);
+        match bytes.get(after_whitespace) {
+            Some(b'>') => return Some(after_whitespace + 1),
+            Some(b'/') => {
+                return (bytes.get(after_whitespace + 1) == Some(&b'>')).then_some(after_whitespace + 2)
+            }
+            Some(_) if after_whitespace > i => {
+                i = scan_attribute(bytes, after_whitespace)?;
+            }
+            _ => return None,
+        }
+    }
+}
+
+pub fn scan_tag_name(bytes: &[u8], start: usize) -> Option<usize> {
+    if !bytes.get(start)?.is_ascii_alphabetic() {
+        return None;
+    }
+    let mut i = start + 1;
+    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+        i += 1;
+    }
+    Some(i)
+}
+
+pub fn scan_attribute(bytes: &[u8], start: usize) -> Option<usize> {
+    let first = *bytes.get(start)?;
+    if !(first.is_ascii_alphabetic() || first == b'_' || first == b':') {
+        return None;
+    }
+    let mut i = start + 1;
+    while i < bytes.len()
+        && (bytes[i].is_ascii_alphanumeric() || matches!(bytes[i], b'_' | b'.' | b':' | b'-'))
+    {
+        i += 1;
+    }
+
+    let after_name = i;
+    let j = skip_ascii_whitespace(bytes, i);
+    if bytes.get(j) != Some(&b'=') {
+        return Some(after_name);
+    }
+    let j = skip_ascii_whitespace(bytes, j + 1);
+
+    match bytes.get(j)? {
+        quote @ (b'"' | b'\'') => bytes[j + 1..]
+            .iter()
+            .position(|b| b == quote)
+            .map(|index| j + 1 + index + 1),
+        _ => {
+            let mut k = j;
+            while k < bytes.len()
+                && !matches!(bytes[k], b'"' | b'\'' | b'=' | b'<' | b'>' | b'`')
+                && bytes[k] > b' '
+            {
+                k += 1;
+            }
+            (k > j).then_some(k)
+        }
+    }
+}
+
+#[inline]
+pub fn skip_ascii_whitespace(bytes: &[u8], mut i: usize) -> usize {
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+        i += 1;
+    }
+    i
+}
+
+#[inline]
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
@@ -0,0 +1,634 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::markdown::gfm_parser::{split_table_row, GfmBlock, GfmListItem, GfmToken};
+use crate::markdown::inline::{plain_text, Inline, InlineParser, LinkReference};
+use crate::markdown::parser::TextEdit;
+use crate::markdown::wikilinks::NoteIndex;
+
+// Every rule with its default severity. The ids are what the workspace
+// settings use to turn a rule off or change its severity.
+pub const RULES: [(&str, Severity); 6] = [
+    ("unclosed-code-fence", Severity::Error),
+    ("heading-increment", Severity::Warning),
+    ("duplicate-heading", Severity::Warning),
+    ("table-column-count", Severity::Warning),
+    ("broken-link", Severity::Warning),
+    ("trailing-whitespace", Severity::Info),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+// `line` and `column` are 1-based, the column counted in characters. `fix`
+// is an edit of the document that resolves the problem, when there is an
+// unambiguous one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub rule: String,
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fix: Option<TextEdit>,
+}
+
+// Severity of a rule in the workspace settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleLevel {
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LintConfig {
+    pub enabled: bool,
+    // Overrides by rule id; rules left out keep their default severity
+    pub rules: HashMap<String, RuleLevel>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rules: HashMap::new(),
+        }
+    }
+}
+
+impl LintConfig {
+    // `None` when the rule is turned off
+    fn severity(&self, rule: &str) -> Option<Severity> {
+        match self.rules.get(rule) {
+            Some(RuleLevel::Off) => None,
+            Some(RuleLevel::Info) => Some(Severity::Info),
+            Some(RuleLevel::Warning) => Some(Severity::Warning),
+            Some(RuleLevel::Error) => Some(Severity::Error),
+            None => RULES.iter().find(|(id, _)| *id == rule).map(|&(_, severity)| severity),
+        }
+    }
+}
+
+// Lint results of one top-level block, which stay valid while its source
+// is unchanged. Heading rules depend on the headings before the block, so
+// its headings are kept and checked again on every render.
+#[derive(Debug, Clone, Default)]
+pub struct BlockLint {
+    start_line: usize,
+    end_line: usize,
+    diagnostics: Vec<Diagnostic>,
+    headings: Vec<Heading>,
+    // Whether links were checked against files on disk, which can change
+    // while the source doesn't
+    checks_files: bool,
+}
+
+impl BlockLint {
+    pub fn checks_files(&self) -> bool {
+        self.checks_files
+    }
+
+    // Moves the results along with a block that now starts on `start_line`
+    pub fn shift(&mut self, start_line: usize) {
+        let shift = |line: usize| (line + start_line).saturating_sub(self.start_line);
+        for diagnostic in &mut self.diagnostics {
+            diagnostic.line = shift(diagnostic.line);
+            if let Some(fix) = &mut diagnostic.fix {
+                fix.start_line = shift(fix.start_line);
+                fix.end_line = shift(fix.end_line);
+            }
+        }
+        for heading in &mut self.headings {
+            heading.line = shift(heading.line);
+        }
+        self.end_line = shift(self.end_line);
+        self.start_line = start_line;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Heading {
+    level: u8,
+    // Plain text, without markup
+    text: String,
+    line: usize,
+}
+
+// Checks a parsed document against the enabled rules, one top-level block at
+// a time, then `finish` adds the rules that span blocks. `lines` are the
+// document's source lines, which the block positions refer to.
+pub struct Linter<'a> {
+    config: &'a LintConfig,
+    lines: &'a [&'a str],
+    references: Option<&'a HashMap<String, LinkReference>>,
+    notes: Option<&'a NoteIndex>,
+    // Folder of the note, which relative links are resolved against
+    directory: Option<&'a Path>,
+    diagnostics: Vec<Diagnostic>,
+    headings: Vec<Heading>,
+    // Files checked so far, so each is looked up on disk once per render
+    existing_files: HashMap<PathBuf, bool>,
+    checks_files: bool,
+}
+
+impl<'a> Linter<'a> {
+    pub fn new(config: &'a LintConfig, lines: &'a [&'a str]) -> Self {
+        Self {
+            config,
+            lines,
+            references: None,
+            notes: None,
+            directory: None,
+            diagnostics: Vec::new(),
+            headings: Vec::new(),
+            existing_files: HashMap::new(),
+            checks_files: false,
+        }
+    }
+
+    // Link reference definitions, so `[text][label]` links are checked too
+    pub fn with_references(mut self, references: &'a HashMap<String, LinkReference>) -> Self {
+        self.references = Some(references);
+        self
+    }
+
+    // Workspace notes `[[...]]` links should resolve to
+    pub fn with_notes(mut self, notes: &'a NoteIndex) -> Self {
+        self.notes = Some(notes);
+        self
+    }
+
+    // Path of the note on disk; relative links of unsaved notes aren't checked
+    pub fn with_document_path(mut self, path: &'a Path) -> Self {
+        self.directory = path.parent();
+        self
+    }
+
+    // Checks the top-level block on lines `start_line..=end_line`, which
+    // converts to `block` unless it only holds reference definitions
+    pub fn lint_block(&mut self, block: Option<&GfmBlock>, start_line: usize, end_line: usize) -> BlockLint {
+        let mut code_lines = vec![false; (end_line + 1).saturating_sub(start_line)];
+        if let Some(block) = block {
+            self.lint_blocks(std::slice::from_ref(block));
+            mark_code_lines(std::slice::from_ref(block), start_line, &mut code_lines);
+        }
+        for (offset, &code) in code_lines.iter().enumerate() {
+            if !code {
+                self.lint_trailing_whitespace(start_line + offset);
+            }
+        }
+
+        BlockLint {
+            start_line,
+            end_line,
+            diagnostics: std::mem::take(&mut self.diagnostics),
+            headings: std::mem::take(&mut self.headings),
+            checks_files: std::mem::take(&mut self.checks_files),
+        }
+    }
+
+    // Checks the headings of the document's blocks, in order, and the blank
+    // lines between the blocks
+    pub fn finish<'b>(mut self, blocks: impl IntoIterator<Item = &'b BlockLint>) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let mut previous_level = None;
+        let mut first_lines = HashMap::new();
+        let mut next_line = 1;
+
+        for block in blocks {
+            for line in next_line..block.start_line {
+                self.lint_trailing_whitespace(line);
+            }
+            next_line = next_line.max(block.end_line + 1);
+
+            for heading in &block.headings {
+                self.lint_heading(heading, previous_level, &mut first_lines);
+                previous_level = Some(heading.level);
+            }
+            diagnostics.extend_from_slice(&block.diagnostics);
+        }
+        for line in next_line..=self.lines.len() {
+            self.lint_trailing_whitespace(line);
+        }
+
+        diagnostics.append(&mut self.diagnostics);
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.line, diagnostic.column));
+        diagnostics
+    }
+
+    fn lint_blocks(&mut self, blocks: &[GfmBlock]) {
+        for block in blocks {
+            match &block.token {
+                GfmToken::Heading { level, text } => {
+                    let mut plain = String::new();
+                    plain_text(&InlineParser::new(text).parse(), &mut plain);
+                    self.headings.push(Heading {
+                        level: *level,
+                        text: plain.trim().to_string(),
+                        line: block.start_line,
+                    });
+                    self.lint_links(text, block.start_line, block.end_line);
+                }
+                GfmToken::Paragraph(text) => self.lint_links(text, block.start_line, block.end_line),
+                GfmToken::CodeBlock { .. } => self.lint_code_fence(block),
+                GfmToken::Table { headers, rows, .. } => {
+                    self.lint_table(headers.len(), block);
+                    for cell in headers.iter().chain(rows.iter().flatten()) {
+                        self.lint_links(cell, block.start_line, block.end_line);
+                    }
+                }
+                GfmToken::Embed(links) => {
+                    for link in links.iter().filter(|link| !link.target.is_empty()) {
+                        self.lint_wikilink(&link.target, block.start_line, block.end_line);
+                    }
+                }
+                GfmToken::List { items, .. } => self.lint_list_items(items),
+                GfmToken::Blockquote(content)
+                | GfmToken::Callout { content, .. }
+                | GfmToken::FootnoteDefinition { content, .. } => self.lint_blocks(content),
+                GfmToken::Math(_) | GfmToken::HtmlBlock(_) | GfmToken::HorizontalRule => {}
+            }
+        }
+    }
+
+    fn lint_list_items(&mut self, items: &[GfmListItem]) {
+        for item in items {
+            self.lint_blocks(&item.content);
+        }
+    }
+
+    fn report(&mut self, rule: &str, line: usize, column: usize, message: String, fix: Option<TextEdit>) {
+        if let Some(severity) = self.config.severity(rule) {
+            self.diagnostics.push(Diagnostic {
+                rule: rule.to_string(),
+                severity,
+                line,
+                column,
+                message,
+                fix,
+            });
+        }
+    }
+
+    fn line(&self, line: usize) -> &'a str {
+        self.lines.get(line.wrapping_sub(1)).copied().unwrap_or("")
+    }
+
+    // `first_lines` has the first line of every heading text seen so far
+    fn lint_heading(
+        &mut self,
+        heading: &Heading,
+        previous_level: Option<u8>,
+        first_lines: &mut HashMap<String, usize>,
+    ) {
+        let (level, line) = (heading.level, heading.line);
+        let source = self.line(line);
+        let start = content_start(source);
+        let column = char_column(source, start);
+
+        if let Some(previous) = previous_level.filter(|&previous| level > previous + 1) {
+            // Only ATX headings can be fixed by changing their `#`s
+            let hashes = source[start..].bytes().take_while(|&b| b == b'#').count();
+            let fix = (hashes == level as usize).then(|| TextEdit {
+                start_line: line - 1,
+                end_line: line,
+                lines: vec![format!(
+                    "{}{}{}",
+                    &source[..start],
+                    "#".repeat(previous as usize + 1),
+                    &source[start + hashes..]
+                )],
+            });
+            self.report(
+                "heading-increment",
+                line,
+                column,
+                format!("Heading level jumps from h{} to h{}", previous, level),
+                fix,
+            );
+        }
+
+        let key = heading.text.to_lowercase();
+        if key.is_empty() {
+            return;
+        }
+        match first_lines.get(&key) {
+            Some(&first) => self.report(
+                "duplicate-heading",
+                line,
+                column,
+                format!("Duplicate heading \"{}\", first used on line {}", heading.text, first),
+                None,
+            ),
+            None => {
+                first_lines.insert(key, line);
+            }
+        }
+    }
+
+    // Fenced code runs to the end of its container when the closing fence is
+    // missing, swallowing everything after it
+    fn lint_code_fence(&mut self, block: &GfmBlock) {
+        let opening = self.line(block.start_line);
+        let start = content_start(opening);
+        let Some((fence_char, fence_length)) = code_fence(&opening[start..]) else {
+            return;
+        };
+
+        let closing = self.line(block.end_line);
+        let closing = closing[quote_prefix_len(closing)..].trim_end();
+        let closed = block.end_line > block.start_line
+            && closing.len() >= fence_length
+            && closing.bytes().all(|b| b == fence_char);
+        if closed {
+            return;
+        }
+
+        // A closing fence can only be added safely outside of containers
+        let fix = opening[..start].trim().is_empty().then(|| {
+            let last = (block.start_line..=block.end_line)
+                .rev()
+                .find(|&line| !self.line(line).trim().is_empty())
+                .unwrap_or(block.start_line);
+            TextEdit {
+                start_line: last,
+                end_line: last,
+                lines: vec![format!("{}{}", &opening[..start], (fence_char as char).to_string().repeat(fence_length))],
+            }
+        });
+        self.report(
+            "unclosed-code-fence",
+            block.start_line,
+            char_column(opening, start),
+            "Code fence is never closed".to_string(),
+            fix,
+        );
+    }
+
+    // Rows are the lines after the header and delimiter row
+    fn lint_table(&mut self, columns: usize, block: &GfmBlock) {
+        for line in block.start_line + 2..=block.end_line {
+            let source = self.line(line);
+            let start = quote_prefix_len(source);
+            if source[start..].trim().is_empty() {
+                continue;
+            }
+            let cells = split_table_row(&source[start..]).len();
+            if cells != columns {
+                self.report(
+                    "table-column-count",
+                    line,
+                    char_column(source, start),
+                    format!(
+                        "Table row has {} cell{}, but the header has {}",
+                        cells,
+                        if cells == 1 { "" } else { "s" },
+                        columns
+                    ),
+                    None,
+                );
+            }
+        }
+    }
+
+    fn lint_links(&mut self, text: &str, start_line: usize, end_line: usize) {
+        // Every kind of link starts with a bracket
+        if !text.contains('[') {
+            return;
+        }
+
+        let mut parser = InlineParser::new(text);
+        if let Some(references) = self.references {
+            parser = parser.with_references(references);
+        }
+        if let Some(notes) = self.notes {
+            parser = parser.with_notes(notes);
+        }
+        let inlines = parser.parse();
+        self.lint_inline_links(&inlines, start_line, end_line);
+    }
+
+    fn lint_inline_links(&mut self, inlines: &[Inline], start_line: usize, end_line: usize) {
+        for inline in inlines {
+            match inline {
+                Inline::Link { url, children, .. } | Inline::Image { url, children, .. } => {
+                    self.lint_url(url, start_line, end_line);
+                    self.lint_inline_links(children, start_line, end_line);
+                }
+                Inline::WikiLink { target, path: None, .. } if !target.is_empty() => {
+                    self.lint_wikilink(target, start_line, end_line);
+                }
+                Inline::Emphasis(children) | Inline::Strong(children) | Inline::Strikethrough(children) => {
+                    self.lint_inline_links(children, start_line, end_line);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn lint_url(&mut self, url: &str, start_line: usize, end_line: usize) {
+        let Some(directory) = self.directory else {
+            return;
+        };
+        let path = url.split(['#', '?']).next().unwrap_or("");
+        if path.is_empty() || path.starts_with('/') || has_scheme(path) {
+            return;
+        }
+
+        let path = percent_decode(path);
+        let target = directory.join(&path);
+        // Obsidian-style links may leave out the `.md`
+        if self.exists(&target) || (target.extension().is_none() && self.exists(&target.with_extension("md"))) {
+            return;
+        }
+
+        let (line, column) = self.locate(&path, url, start_line, end_line);
+        self.report(
+            "broken-link",
+            line,
+            column,
+            format!("Link target \"{}\" does not exist", path),
+            None,
+        );
+    }
+
+    fn exists(&mut self, path: &Path) -> bool {
+        self.checks_files = true;
+        if let Some(&exists) = self.existing_files.get(path) {
+            return exists;
+        }
+        let exists = path.exists();
+        self.existing_files.insert(path.to_path_buf(), exists);
+        exists
+    }
+
+    fn lint_wikilink(&mut self, target: &str, start_line: usize, end_line: usize) {
+        // Without a workspace no wikilink resolves
+        let Some(notes) = self.notes.filter(|notes| !notes.is_empty()) else {
+            return;
+        };
+        if notes.resolve(target).is_some() {
+            return;
+        }
+
+        let (line, column) = self.locate(&format!("[[{}", target), target, start_line, end_line);
+        self.report(
+            "broken-link",
+            line,
+            column,
+            format!("No note named \"{}\" in the workspace", target),
+            None,
+        );
+    }
+
+    // Position of the first of `needles` in the block's lines, or of the
+    // block itself when the text was written differently (escaped, say)
+    fn locate(&self, needle: &str, fallback: &str, start_line: usize, end_line: usize) -> (usize, usize) {
+        for needle in [needle, fallback] {
+            for line in start_line..=end_line {
+                let source = self.line(line);
+                if let Some(index) = source.find(needle) {
+                    return (line, char_column(source, index));
+                }
+            }
+        }
+        let source = self.line(start_line);
+        (start_line, char_column(source, content_start(source)))
+    }
+
+    // Two trailing spaces are a hard line break, so they're allowed after text
+    fn lint_trailing_whitespace(&mut self, line: usize) {
+        let source = self.line(line);
+        let trimmed = source.trim_end_matches([' ', '\t']);
+        let trailing = &source[trimmed.len()..];
+        if trailing.is_empty() || (trailing == "  " && !trimmed.trim().is_empty()) {
+            return;
+        }
+        self.report(
+            "trailing-whitespace",
+            line,
+            char_column(source, trimmed.len()),
+            "Trailing whitespace".to_string(),
+            Some(TextEdit {
+                start_line: line - 1,
+                end_line: line,
+                lines: vec![trimmed.to_string()],
+            }),
+        );
+    }
+}
+
+// Lines of code blocks and math, where whitespace is content.
+// `code_lines[0]` is line `first_line`.
+fn mark_code_lines(blocks: &[GfmBlock], first_line: usize, code_lines: &mut [bool]) {
+    for block in blocks {
+        match &block.token {
+            GfmToken::CodeBlock { .. } | GfmToken::Math(_) | GfmToken::HtmlBlock(_) => {
+                let end = (block.end_line + 1 - first_line).min(code_lines.len());
+                let start = block.start_line - first_line;
+                if start < end {
+                    code_lines[start..end].fill(true);
+                }
+            }
+            GfmToken::List { items, .. } => {
+                for item in items {
+                    mark_code_lines(&item.content, first_line, code_lines);
+                }
+            }
+            GfmToken::Blockquote(content)
+            | GfmToken::Callout { content, .. }
+            | GfmToken::FootnoteDefinition { content, .. } => mark_code_lines(content, first_line, code_lines),
+            _ => {}
+        }
+    }
+}
+
+// Fence character and length of a line opening fenced code
+fn code_fence(line: &str) -> Option<(u8, usize)> {
+    let fence_char = *line.as_bytes().first().filter(|&&b| b == b'`' || b == b'~')?;
+    let length = line.bytes().take_while(|&b| b == fence_char).count();
+    (length >= 3).then_some((fence_char, length))
+}
+
+// Length of the indentation and `>` markers before a line's content
+fn quote_prefix_len(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t', '>']).len()
+}
+
+// Offset of a line's content after quote markers and list markers
+fn content_start(line: &str) -> usize {
+    let bytes = line.as_bytes();
+    let mut i = quote_prefix_len(line);
+    loop {
+        let marker = match bytes.get(i) {
+            Some(b'-' | b'*' | b'+') => 1,
+            Some(b'0'..=b'9') => {
+                let digits = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+                if !matches!(bytes.get(i + digits), Some(b'.' | b')')) {
+                    return i;
+                }
+                digits + 1
+            }
+            _ => return i,
+        };
+        if !matches!(bytes.get(i + marker), Some(b' ' | b'\t')) {
+            return i;
+        }
+        i += marker;
+        i += line.len() - i - line[i..].trim_start_matches([' ', '\t', '>']).len();
+    }
+}
+
+fn char_column(line: &str, offset: usize) -> usize {
+    line[..offset].chars().count() + 1
+}
+
+// `mailto:`, `https:` and the like, as opposed to a relative path
+fn has_scheme(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.len() > 1
+                && scheme.starts_with(|ch: char| ch.is_ascii_alphabetic())
+                && scheme.chars().all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+// Undoes the `%XX` escapes `normalize_uri` adds to link destinations
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match text.get(i + 1..i + 3) {
+            Some(hex) if bytes[i] == b'%' && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                u8::from_str_radix(hex, 16).ok()
+            }
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,791 @@
+use crate::markdown::inline::escape_html;
+
+// Converts TeX math into MathML. This covers the subset people write in notes
+// (scripts, fractions, roots, Greek letters and symbols, fonts, accents,
+// `\left`/`\right` and matrix-like environments), not TeX macros. Formulas
+// that can't be read are shown as their source instead.
+pub fn render_math(tex: &str, display: bool, out: &mut String) {
+    let mut parser = MathParser {
+        source: tex,
+        pos: 0,
+        display,
+        variant: None,
+    };
+
+    match parser.parse() {
+        Ok(mathml) => {
+            out.push_str(if display { "<math display=\"block\">" } else { "<math>" });
+            out.push_str("<semantics>");
+            out.push_str(&mathml);
+            out.push_str("<annotation encoding=\"application/x-tex\">");
+            out.push_str(&escape_html(tex));
+            out.push_str("</annotation></semantics></math>");
+        }
+        Err(error) => {
+            out.push_str("<code class=\"math-error\" title=\"");
+            out.push_str(&escape_html(&error));
+            out.push_str("\">");
+            out.push_str(&escape_html(tex));
+            out.push_str("</code>");
+        }
+    }
+}
+
+// What ended a row of math: the end of the input, a closing brace, a column
+// or row separator, `\end{name}` or `\right` with its delimiter.
+enum Terminator {
+    End,
+    Brace,
+    Column,
+    Row,
+    EndEnvironment(String),
+    Right(String),
+}
+
+struct Atom {
+    mathml: String,
+    // Scripts go above and below in display math (`\sum`, `\lim`)
+    limits: bool,
+}
+
+impl Atom {
+    fn new(mathml: String) -> Self {
+        Self { mathml, limits: false }
+    }
+}
+
+struct MathParser<'a> {
+    source: &'a str,
+    pos: usize,
+    display: bool,
+    // `mathvariant` set by font commands such as `\mathbf`
+    variant: Option<&'static str>,
+}
+
+impl MathParser<'_> {
+    fn parse(&mut self) -> Result<String, String> {
+        let rows = self.parse_table(None)?;
+        if rows.len() == 1 && rows[0].len() == 1 {
+            return Ok(rows.into_iter().flatten().next().unwrap_or_default());
+        }
+        // Top-level `\\` and `&` lay the formula out as an aligned table
+        Ok(render_table(&rows, Some("right left")))
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.peek() {
+            if !ch.is_whitespace() {
+                break;
+            }
+            self.pos += ch.len_utf8();
+        }
+    }
+
+    // Rows and cells up to `\end{environment}`, or to the end of the input
+    // when there is no environment.
+    fn parse_table(&mut self, environment: Option<&str>) -> Result<Vec<Vec<String>>, String> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        loop {
+            let (cell, terminator) = self.parse_row()?;
+            row.push(cell);
+            match terminator {
+                Terminator::Column => {}
+                Terminator::Row => rows.push(std::mem::take(&mut row)),
+                Terminator::End => match environment {
+                    None => break,
+                    Some(name) => return Err(format!("Missing \\end{{{}}}", name)),
+                },
+                Terminator::EndEnvironment(name) => {
+                    if environment != Some(name.as_str()) {
+                        return Err(format!("Unexpected \\end{{{}}}", name));
+                    }
+                    break;
+                }
+                Terminator::Brace => return Err("Unexpected }".to_string()),
+                Terminator::Right(_) => return Err("\\right without \\left".to_string()),
+            }
+        }
+
+        // A trailing `\\` does not start another row
+        if !(row.len() == 1 && row[0] == "<mrow></mrow>") || rows.is_empty() {
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    fn parse_row(&mut self) -> Result<(String, Terminator), String> {
+        let mut items = Vec::new();
+        let terminator = loop {
+            self.skip_whitespace();
+            let Some(ch) = self.peek() else {
+                break Terminator::End;
+            };
+            match ch {
+                '}' => {
+                    self.pos += 1;
+                    break Terminator::Brace;
+                }
+                '&' => {
+                    self.pos += 1;
+                    break Terminator::Column;
+                }
+                '\\' if self.source[self.pos..].starts_with("\\\\") => {
+                    self.pos += 2;
+                    break Terminator::Row;
+                }
+                '\\' if self.peek_command() == "end" => {
+                    self.read_command();
+                    break Terminator::EndEnvironment(self.read_braced_text()?);
+                }
+                '\\' if self.peek_command() == "right" => {
+                    self.read_command();
+                    break Terminator::Right(self.read_delimiter()?);
+                }
+                _ => {
+                    let atom = self.parse_atom()?;
+                    items.push(self.parse_scripts(atom)?);
+                }
+            }
+        };
+        Ok((mrow(items), terminator))
+    }
+
+    fn parse_scripts(&mut self, atom: Atom) -> Result<String, String> {
+        let mut subscript = None;
+        let mut superscript: Option<String> = None;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some('_') if subscript.is_none() => {
+                    self.pos += 1;
+                    subscript = Some(self.parse_argument()?);
+                }
+                Some('^') if superscript.is_none() => {
+                    self.pos += 1;
+                    superscript = Some(self.parse_argument()?);
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    let prime = "<mo>′</mo>".to_string();
+                    superscript = Some(match superscript {
+                        Some(existing) => format!("<mrow>{}{}</mrow>", existing, prime),
+                        None => prime,
+                    });
+                }
+                _ => break,
+            }
+        }
+
+        let base = atom.mathml;
+        let above_below = atom.limits && self.display;
+        Ok(match (subscript, superscript) {
+            (None, None) => base,
+            (Some(sub), None) if above_below => format!("<munder>{}{}</munder>", base, sub),
+            (None, Some(sup)) if above_below => format!("<mover>{}{}</mover>", base, sup),
+            (Some(sub), Some(sup)) if above_below => format!("<munderover>{}{}{}</munderover>", base, sub, sup),
+            (Some(sub), None) => format!("<msub>{}{}</msub>", base, sub),
+            (None, Some(sup)) => format!("<msup>{}{}</msup>", base, sup),
+            (Some(sub), Some(sup)) => format!("<msubsup>{}{}{}</msubsup>", base, sub, sup),
+        })
+    }
+
+    // The argument of a command or script: a braced group or a single atom.
+    // Only one digit is taken, so `x^10` is `x^1` followed by `0` as in TeX.
+    fn parse_argument(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err("Missing argument".to_string()),
+            Some(ch) if ch.is_ascii_digit() => {
+                self.pos += 1;
+                Ok(format!("<mn>{}</mn>", ch))
+            }
+            Some(_) => Ok(self.parse_atom()?.mathml),
+        }
+    }
+
+    fn parse_group(&mut self) -> Result<String, String> {
+        match self.parse_row()? {
+            (row, Terminator::Brace) => Ok(row),
+            _ => Err("Missing }".to_string()),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Atom, String> {
+        let ch = self.peek().ok_or("Missing argument")?;
+        match ch {
+            '{' => {
+                self.pos += 1;
+                self.parse_group().map(Atom::new)
+            }
+            '}' => Err("Unexpected }".to_string()),
+            '\\' => self.parse_command(),
+            _ if ch.is_ascii_digit() || (ch == '.' && self.source[self.pos + 1..].starts_with(|c: char| c.is_ascii_digit())) => {
+                let start = self.pos;
+                let bytes = self.source.as_bytes();
+                while self.pos < bytes.len()
+                    && (bytes[self.pos].is_ascii_digit()
+                        || (bytes[self.pos] == b'.' && bytes.get(self.pos + 1).is_some_and(u8::is_ascii_digit)))
+                {
+                    self.pos += 1;
+                }
+                Ok(Atom::new(format!("<mn>{}</mn>", &self.source[start..self.pos])))
+            }
+            _ if ch.is_alphabetic() => {
+                self.pos += ch.len_utf8();
+                Ok(Atom::new(self.identifier(&ch.to_string())))
+            }
+            '~' => {
+                self.pos += 1;
+                Ok(Atom::new(mspace("0.2778em")))
+            }
+            _ => {
+                self.pos += ch.len_utf8();
+                let operator = match ch {
+                    '-' => "−".to_string(),
+                    '*' => "∗".to_string(),
+                    _ => escape_html(&ch.to_string()),
+                };
+                Ok(Atom::new(format!("<mo>{}</mo>", operator)))
+            }
+        }
+    }
+
+    fn identifier(&self, name: &str) -> String {
+        match self.variant {
+            Some(variant) => format!("<mi mathvariant=\"{}\">{}</mi>", variant, escape_html(name)),
+            None => format!("<mi>{}</mi>", escape_html(name)),
+        }
+    }
+
+    fn parse_command(&mut self) -> Result<Atom, String> {
+        let name = self.read_command();
+        let atom = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let numerator = self.parse_argument()?;
+                let denominator = self.parse_argument()?;
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let top = self.parse_argument()?;
+                let bottom = self.parse_argument()?;
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    top, bottom
+                )
+            }
+            "sqrt" => match self.read_optional_argument()? {
+                Some(index) => {
+                    let radicand = self.parse_argument()?;
+                    format!("<mroot>{}{}</mroot>", radicand, index)
+                }
+                None => format!("<msqrt>{}</msqrt>", self.parse_argument()?),
+            },
+            "left" => {
+                let open = self.read_delimiter()?;
+                let (content, terminator) = self.parse_row()?;
+                let Terminator::Right(close) = terminator else {
+                    return Err("\\left without \\right".to_string());
+                };
+                format!("<mrow>{}{}{}</mrow>", fence(&open), content, fence(&close))
+            }
+            "begin" => {
+                let environment = self.read_braced_text()?;
+                self.parse_environment(&environment)?
+            }
+            "text" | "textrm" | "textnormal" | "mbox" | "textit" | "textbf" => {
+                let text = self.read_braced_text()?;
+                // Spaces at the edges of `<mtext>` would be collapsed
+                format!("<mtext>{}</mtext>", escape_html(&text).replace(' ', "\u{a0}"))
+            }
+            "operatorname" => {
+                let text = self.read_braced_text()?;
+                format!("<mi>{}</mi>", escape_html(text.trim()))
+            }
+            "mathrm" | "mathbf" | "mathit" | "mathbb" | "mathcal" | "mathfrak" | "mathsf" | "mathtt"
+            | "boldsymbol" | "bm" => {
+                let variant = match name.as_str() {
+                    "mathrm" => "normal",
+                    "mathbf" => "bold",
+                    "mathit" => "italic",
+                    "mathbb" => "double-struck",
+                    "mathcal" => "script",
+                    "mathfrak" => "fraktur",
+                    "mathsf" => "sans-serif",
+                    "mathtt" => "monospace",
+                    _ => "bold-italic",
+                };
+                let previous = self.variant.replace(variant);
+                let argument = self.parse_argument();
+                self.variant = previous;
+                argument?
+            }
+            "overline" | "underline" | "overbrace" | "underbrace" | "hat" | "widehat" | "bar" | "vec"
+            | "dot" | "ddot" | "tilde" | "widetilde" | "check" | "breve" | "acute" | "grave" => {
+                let base = self.parse_argument()?;
+                let (accent, under) = match name.as_str() {
+                    "overline" | "bar" => ("¯", false),
+                    "underline" => ("_", true),
+                    "overbrace" => ("⏞", false),
+                    "underbrace" => ("⏟", true),
+                    "hat" | "widehat" => ("^", false),
+                    "vec" => ("→", false),
+                    "dot" => ("˙", false),
+                    "ddot" => ("¨", false),
+                    "check" => ("ˇ", false),
+                    "breve" => ("˘", false),
+                    "acute" => ("´", false),
+                    "grave" => ("`", false),
+                    _ => ("~", false),
+                };
+                if under {
+                    format!("<munder>{}<mo>{}</mo></munder>", base, accent)
+                } else {
+                    format!("<mover accent=\"true\">{}<mo>{}</mo></mover>", base, accent)
+                }
+            }
+            "," | "thinspace" => mspace("0.1667em"),
+            ":" | ">" | "medspace" => mspace("0.2222em"),
+            ";" | "thickspace" => mspace("0.2778em"),
+            " " => mspace("0.25em"),
+            "!" => mspace("-0.1667em"),
+            "quad" => mspace("1em"),
+            "qquad" => mspace("2em"),
+            "{" | "}" | "$" | "%" | "&" | "#" | "_" => format!("<mo>{}</mo>", escape_html(&name)),
+            "|" => "<mo>‖</mo>".to_string(),
+            "displaystyle" | "textstyle" | "limits" | "nolimits" => String::new(),
+            _ => {
+                if let Some(function) = function_name(&name) {
+                    return Ok(Atom {
+                        mathml: format!("<mi>{}</mi>", function),
+                        limits: matches!(function, "lim" | "limsup" | "liminf" | "max" | "min" | "sup" | "inf" | "Pr"),
+                    });
+                }
+                if let Some((symbol, limits)) = large_operator(&name) {
+                    return Ok(Atom {
+                        mathml: format!("<mo>{}</mo>", symbol),
+                        limits,
+                    });
+                }
+                match symbol(&name) {
+                    Some(Symbol::Identifier(symbol)) => self.identifier(symbol),
+                    Some(Symbol::Upright(symbol)) => format!("<mi mathvariant=\"normal\">{}</mi>", symbol),
+                    Some(Symbol::Operator(symbol)) => format!("<mo>{}</mo>", symbol),
+                    None => format!("<merror><mtext>\\{}</mtext></merror>", escape_html(&name)),
+                }
+            }
+        };
+        Ok(Atom::new(atom))
+    }
+
+    fn parse_environment(&mut self, environment: &str) -> Result<String, String> {
+        let (open, close, columns) = match environment {
+            "matrix" | "smallmatrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left left")),
+            "aligned" | "align" | "align*" | "split" | "gathered" | "gather" | "gather*" => {
+                ("", "", Some("right left"))
+            }
+            "array" => {
+                // The column specification is not needed for MathML
+                self.read_braced_text()?;
+                ("", "", None)
+            }
+            _ => return Err(format!("Unknown environment {}", environment)),
+        };
+
+        let rows = self.parse_table(Some(environment))?;
+        let table = render_table(&rows, columns);
+        if open.is_empty() && close.is_empty() {
+            return Ok(table);
+        }
+        Ok(format!("<mrow>{}{}{}</mrow>", fence(open), table, fence(close)))
+    }
+
+    fn peek_command(&self) -> &str {
+        let rest = &self.source[self.pos + 1..];
+        let length = rest.find(|ch: char| !ch.is_ascii_alphabetic()).unwrap_or(rest.len());
+        &rest[..length]
+    }
+
+    // Reads `\name`, or a single non-letter like `\,` or `\{`
+    fn read_command(&mut self) -> String {
+        self.pos += 1;
+        let rest = &self.source[self.pos..];
+        let length = match rest.find(|ch: char| !ch.is_ascii_alphabetic()) {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(length) => length,
+            None => rest.len(),
+        };
+        self.pos += length;
+        rest[..length].to_string()
+    }
+
+    // Raw text of a braced argument, as used by `\text` and environment names
+    fn read_braced_text(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err("Missing {".to_string());
+        }
+        self.read_balanced('{', '}').ok_or_else(|| "Missing }".to_string())
+    }
+
+    // `[...]` after `\sqrt`, rendered on its own
+    fn read_optional_argument(&mut self) -> Result<Option<String>, String> {
+        self.skip_whitespace();
+        if self.peek() != Some('[') {
+            return Ok(None);
+        }
+        let source = self.read_balanced('[', ']').ok_or_else(|| "Missing ]".to_string())?;
+        let mut parser = MathParser {
+            source: &source,
+            pos: 0,
+            display: self.display,
+            variant: self.variant,
+        };
+        parser.parse().map(Some)
+    }
+
+    fn read_balanced(&mut self, open: char, close: char) -> Option<String> {
+        let start = self.pos + open.len_utf8();
+        let mut depth = 0;
+        for (offset, ch) in self.source[self.pos..].char_indices() {
+            if ch == open {
+                depth += 1;
+            } else if ch == close {
+                depth -= 1;
+                if depth == 0 {
+                    let end = self.pos + offset;
+                    self.pos = end + close.len_utf8();
+                    return Some(self.source[start..end].to_string());
+                }
+            }
+        }
+        None
+    }
+
+    // The delimiter after `\left` or `\right`; `.` means none
+    fn read_delimiter(&mut self) -> Result<String, String> {
+        self.skip_whitespace();
+        let ch = self.peek().ok_or("Missing delimiter")?;
+        if ch != '\\' {
+            self.pos += ch.len_utf8();
+            return Ok(if ch == '.' { String::new() } else { ch.to_string() });
+        }
+
+        let name = self.read_command();
+        match name.as_str() {
+            "{" | "}" => Ok(name),
+            "|" | "Vert" => Ok("‖".to_string()),
+            "vert" => Ok("|".to_string()),
+            _ => match symbol(&name) {
+                Some(Symbol::Operator(symbol)) => Ok(symbol.to_string()),
+                _ => Err(format!("Unknown delimiter \\{}", name)),
+            },
+        }
+    }
+}
+
+fn mrow(items: Vec<String>) -> String {
+    if items.len() == 1 {
+        return items.into_iter().next().unwrap_or_default();
+    }
+    format!("<mrow>{}</mrow>", items.concat())
+}
+
+fn mspace(width: &str) -> String {
+    format!("<mspace width=\"{}\"></mspace>", width)
+}
+
+fn fence(delimiter: &str) -> String {
+    if delimiter.is_empty() {
+        return String::new();
+    }
+    format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", escape_html(delimiter))
+}
+
+fn render_table(rows: &[Vec<String>], columns: Option<&str>) -> String {
+    let mut table = match columns {
+        Some(columns) => format!("<mtable columnalign=\"{}\">", columns),
+        None => "<mtable>".to_string(),
+    };
+    for row in rows {
+        table.push_str("<mtr>");
+        for cell in row {
+            table.push_str("<mtd>");
+            table.push_str(cell);
+            table.push_str("</mtd>");
+        }
+        table.push_str("</mtr>");
+    }
+    table.push_str("</mtable>");
+    table
+}
+
+fn function_name(name: &str) -> Option<&'static str> {
+    const FUNCTIONS: &[&str] = &[
+        "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+        "coth", "log", "ln", "lg", "exp", "det", "dim", "gcd", "deg", "hom", "ker", "arg", "lim",
+        "limsup", "liminf", "max", "min", "sup", "inf", "Pr",
+    ];
+    FUNCTIONS.iter().find(|&&function| function == name).copied()
+}
+
+// Big operators, and whether their scripts go above and below in display math
+fn large_operator(name: &str) -> Option<(&'static str, bool)> {
+    Some(match name {
+        "sum" => ("∑", true),
+        "prod" => ("∏", true),
+        "coprod" => ("∐", true),
+        "bigcup" => ("⋃", true),
+        "bigcap" => ("⋂", true),
+        "bigoplus" => ("⨁", true),
+        "bigotimes" => ("⨂", true),
+        "bigvee" => ("⋁", true),
+        "bigwedge" => ("⋀", true),
+        "int" => ("∫", false),
+        "iint" => ("∬", false),
+        "iiint" => ("∭", false),
+        "oint" => ("∮", false),
+        _ => return None,
+    })
+}
+
+enum Symbol {
+    Identifier(&'static str),
+    // Uppercase Greek letters are upright, unlike other identifiers
+    Upright(&'static str),
+    Operator(&'static str),
+}
+
+fn symbol(name: &str) -> Option<Symbol> {
+    use Symbol::*;
+
+    Some(match name {
+        "alpha" => Identifier("α"),
+        "beta" => Identifier("β"),
+        "gamma" => Identifier("γ"),
+        "delta" => Identifier("δ"),
+        "epsilon" => Identifier("ϵ"),
+        "varepsilon" => Identifier("ε"),
+        "zeta" => Identifier("ζ"),
+        "eta" => Identifier("η"),
+        "theta" => Identifier("θ"),
+        "vartheta" => Identifier("ϑ"),
+        "iota" => Identifier("ι"),
+        "kappa" => Identifier("κ"),
+        "lambda" => Identifier("λ"),
+        "mu" => Identifier("μ"),
+        "nu" => Identifier("ν"),
+        "xi" => Identifier("ξ"),
+        "omicron" => Identifier("ο"),
+        "pi" => Identifier("π"),
+        "varpi" => Identifier("ϖ"),
+        "rho" => Identifier("ρ"),
+        "varrho" => Identifier("ϱ"),
+        "sigma" => Identifier("σ"),
+        "varsigma" => Identifier("ς"),
+        "tau" => Identifier("τ"),
+        "upsilon" => Identifier("υ"),
+        "phi" => Identifier("ϕ"),
+        "varphi" => Identifier("φ"),
+        "chi" => Identifier("χ"),
+        "psi" => Identifier("ψ"),
+        "omega" => Identifier("ω"),
+        "Gamma" => Upright("Γ"),
+        "Delta" => Upright("Δ"),
+        "Theta" => Upright("Θ"),
+        "Lambda" => Upright("Λ"),
+        "Xi" => Upright("Ξ"),
+        "Pi" => Upright("Π"),
+        "Sigma" => Upright("Σ"),
+        "Upsilon" => Upright("Υ"),
+        "Phi" => Upright("Φ"),
+        "Psi" => Upright("Ψ"),
+        "Omega" => Upright("Ω"),
+        "infty" => Identifier("∞"),
+        "partial" => Identifier("∂"),
+        "nabla" => Identifier("∇"),
+        "emptyset" | "varnothing" => Identifier("∅"),
+        "hbar" => Identifier("ℏ"),
+        "ell" => Identifier("ℓ"),
+        "aleph" => Identifier("ℵ"),
+        "Re" => Identifier("ℜ"),
+        "Im" => Identifier("ℑ"),
+        "wp" => Identifier("℘"),
+        "pm" => Operator("±"),
+        "mp" => Operator("∓"),
+        "times" => Operator("×"),
+        "div" => Operator("÷"),
+        "cdot" => Operator("⋅"),
+        "cdots" => Operator("⋯"),
+        "ldots" | "dots" => Operator("…"),
+        "vdots" => Operator("⋮"),
+        "ddots" => Operator("⋱"),
+        "ast" => Operator("∗"),
+        "star" => Operator("⋆"),
+        "circ" => Operator("∘"),
+        "bullet" => Operator("∙"),
+        "leq" | "le" => Operator("≤"),
+        "geq" | "ge" => Operator("≥"),
+        "neq" | "ne" => Operator("≠"),
+        "approx" => Operator("≈"),
+        "equiv" => Operator("≡"),
+        "sim" => Operator("∼"),
+        "simeq" => Operator("≃"),
+        "cong" => Operator("≅"),
+        "propto" => Operator("∝"),
+        "ll" => Operator("≪"),
+        "gg" => Operator("≫"),
+        "in" => Operator("∈"),
+        "notin" => Operator("∉"),
+        "ni" => Operator("∋"),
+        "subset" => Operator("⊂"),
+        "supset" => Operator("⊃"),
+        "subseteq" => Operator("⊆"),
+        "supseteq" => Operator("⊇"),
+        "cup" => Operator("∪"),
+        "cap" => Operator("∩"),
+        "setminus" => Operator("∖"),
+        "wedge" | "land" => Operator("∧"),
+        "vee" | "lor" => Operator("∨"),
+        "neg" | "lnot" => Operator("¬"),
+        "forall" => Operator("∀"),
+        "exists" => Operator("∃"),
+        "nexists" => Operator("∄"),
+        "to" | "rightarrow" => Operator("→"),
+        "leftarrow" | "gets" => Operator("←"),
+        "leftrightarrow" => Operator("↔"),
+        "Rightarrow" => Operator("⇒"),
+        "Leftarrow" => Operator("⇐"),
+        "Leftrightarrow" | "iff" => Operator("⇔"),
+        "implies" => Operator("⟹"),
+        "mapsto" => Operator("↦"),
+        "uparrow" => Operator("↑"),
+        "downarrow" => Operator("↓"),
+        "mid" => Operator("∣"),
+        "parallel" => Operator("∥"),
+        "perp" | "bot" => Operator("⊥"),
+        "top" => Operator("⊤"),
+        "angle" => Operator("∠"),
+        "oplus" => Operator("⊕"),
+        "otimes" => Operator("⊗"),
+        "langle" => Operator("⟨"),
+        "rangle" => Operator("⟩"),
+        "lfloor" => Operator("⌊"),
+        "rfloor" => Operator("⌋"),
+        "lceil" => Operator("⌈"),
+        "rceil" => Operator("⌉"),
+        "lvert" | "rvert" => Operator("|"),
+        "lVert" | "rVert" => Operator("‖"),
+        "colon" => Operator(":"),
+        "prime" => Operator("′"),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // MathML of an inline formula, without the wrapper and the annotation
+    fn mathml(tex: &str) -> String {
+        let mut out = String::new();
+        render_math(tex, false, &mut out);
+        let body = out.strip_prefix("<math><semantics>").unwrap_or_else(|| panic!("not rendered: {}", out));
+        body[..body.find("<annotation").unwrap()].to_string()
+    }
+
+    fn error(tex: &str) -> String {
+        let mut out = String::new();
+        render_math(tex, false, &mut out);
+        assert!(out.starts_with("<code class=\"math-error\""), "rendered: {}", out);
+        out
+    }
+
+    #[test]
+    fn fractions_and_roots() {
+        assert_eq!(mathml("\\frac{a}{b}"), "<mfrac><mi>a</mi><mi>b</mi></mfrac>");
+        assert_eq!(mathml("\\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+        assert_eq!(mathml("\\sqrt{x}"), "<msqrt><mi>x</mi></msqrt>");
+        assert_eq!(mathml("\\sqrt[3]{x}"), "<mroot><mi>x</mi><mn>3</mn></mroot>");
+    }
+
+    #[test]
+    fn sub_and_superscripts() {
+        assert_eq!(mathml("x_i^2"), "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>");
+        assert_eq!(
+            mathml("x^{n+1}_0"),
+            "<msubsup><mi>x</mi><mn>0</mn><mrow><mi>n</mi><mo>+</mo><mn>1</mn></mrow></msubsup>"
+        );
+        // Limits go above and below only in display math
+        let mut display = String::new();
+        render_math("\\sum_i x", true, &mut display);
+        assert!(display.starts_with("<math display=\"block\"><semantics><mrow><munder><mo>∑</mo><mi>i</mi></munder>"));
+        assert_eq!(mathml("\\sum_i x"), "<mrow><msub><mo>∑</mo><mi>i</mi></msub><mi>x</mi></mrow>");
+    }
+
+    #[test]
+    fn left_and_right_delimiters() {
+        let fence = |delimiter: &str| format!("<mo fence=\"true\" stretchy=\"true\">{}</mo>", delimiter);
+        assert_eq!(
+            mathml("\\left( \\frac12 \\right]"),
+            format!("<mrow>{}<mfrac><mn>1</mn><mn>2</mn></mfrac>{}</mrow>", fence("("), fence("]"))
+        );
+        // `.` is an invisible delimiter
+        assert_eq!(mathml("\\left. x \\right|"), format!("<mrow><mi>x</mi>{}</mrow>", fence("|")));
+    }
+
+    #[test]
+    fn matrix_and_cases_environments() {
+        assert_eq!(
+            mathml("\\begin{matrix}a & b\\\\ c & d\\end{matrix}"),
+            "<mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr>\
+             <mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>"
+        );
+        assert_eq!(
+            mathml("\\begin{cases} 1 & x > 0 \\\\ 0 & \\text{otherwise} \\end{cases}"),
+            "<mrow><mo fence=\"true\" stretchy=\"true\">{</mo><mtable columnalign=\"left left\">\
+             <mtr><mtd><mn>1</mn></mtd><mtd><mrow><mi>x</mi><mo>&gt;</mo><mn>0</mn></mrow></mtd></mtr>\
+             <mtr><mtd><mn>0</mn></mtd><mtd><mtext>otherwise</mtext></mtd></mtr></mtable></mrow>"
+        );
+    }
+
+    #[test]
+    fn unknown_commands_are_marked() {
+        assert_eq!(mathml("\\foo x"), "<mrow><merror><mtext>\\foo</mtext></merror><mi>x</mi></mrow>");
+    }
+
+    #[test]
+    fn broken_formulas_show_their_source() {
+        assert!(error("\\frac{a").contains("title=\"Missing }\">\\frac{a</code>"));
+        assert!(error("a}").contains("title=\"Unexpected }\""));
+        assert!(error("\\begin{matrix}a").contains("title=\"Missing \\end{matrix}\""));
+        assert!(error("\\left(x").contains("title=\"\\left without \\right\""));
+        assert!(error("x \\right)").contains("title=\"\\right without \\left\""));
+        assert!(error("x^").contains("title=\"Missing argument\""));
+        // Escaped like any other text
+        assert!(error("{<b> &").contains("\">{&lt;b&gt; &amp;</code>"));
+    }
+
+    #[test]
+    fn html_is_escaped() {
+        assert_eq!(mathml("\\text{<b>&}"), "<mtext>&lt;b&gt;&amp;</mtext>");
+        let mut out = String::new();
+        render_math("a < b", false, &mut out);
+        assert_eq!(
+            out,
+            "<math><semantics><mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow>\
+             <annotation encoding=\"application/x-tex\">a &lt; b</annotation></semantics></math>"
+        );
+    }
+}
@@ -1,4 +1,16 @@
-pub mod basic_parser;
+pub mod ast;
+#[cfg(test)]
+mod commonmark_spec;
+pub mod entities;
+pub mod formatter;
+pub mod front_matter;
 pub mod gfm_parser;
+pub mod highlight;
+pub mod inline;
+pub mod lint;
+pub mod math;
 pub mod parser;
-
+pub mod sanitize;
+pub mod stats;
+pub mod tasks;
+pub mod wikilinks;
@@ -1,11 +1,129 @@
 
+use std::sync::Mutex;
+
 use serde::{Deserialize, Serialize};
 
+use crate::markdown::gfm_parser::GfmMarkdownParser;
+use crate::markdown::lint::Diagnostic;
+use crate::markdown::stats::DocumentStats;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ParseResult {
     pub html: String,
     pub word_count: usize,
+    pub stats: DocumentStats,
+    // Start and end line of every element that carries `data-source-line`,
+    // in document order
+    pub source_map: Vec<SourceMapEntry>,
+    // Every heading in document order, for the outline panel
+    pub toc: Vec<TocEntry>,
+    // Every distinct tag, from the front matter and `#tags` in the text
+    pub tags: Vec<String>,
+    // YAML front matter as a JSON object, when the note has a valid one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub front_matter: Option<serde_json::Value>,
+    // Version of the document kept by the persistent parser, sent back with
+    // the next edit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    // The output block by block, when that was asked for; `html` is left
+    // empty then
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocks: Vec<HtmlBlock>,
+    // Lint findings in document order, see `lint`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<Diagnostic>,
+    // The error-level diagnostics as text. The document is still rendered;
+    // this only says it is broken in a way the output can't show.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
 }
 
+// A top-level block of the output. `key` is a hash of everything the block
+// renders from except its position, so the preview can keep blocks whose key
+// is unchanged and only shift their `data-source-line` attributes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HtmlBlock {
+    pub key: String,
+    pub start_line: usize,
+    pub html: String,
+}
+
+// Source lines are 1-based and inclusive
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceMapEntry {
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+// `slug` is the heading's `id`, so `#slug` links to it; `line` is 1-based
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    pub slug: String,
+    pub line: usize,
+}
+
+// Replaces lines `start_line..end_line` (zero-based, end exclusive) of the
+// previous document version with `lines`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextEdit {
+    pub start_line: usize,
+    pub end_line: usize,
+    pub lines: Vec<String>,
+}
+
+// A callout type from the workspace settings. `class` picks the
+// `alert-<class>` colour class, so built-in colours like `tip` can be reused.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalloutType {
+    pub name: String,
+    pub icon: String,
+    pub class: String,
+}
+
+// Syntax on top of CommonMark, each of which can be turned off on its own.
+// All of it is on by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParserOptions {
+    pub tables: bool,
+    pub task_lists: bool,
+    pub strikethrough: bool,
+    // Bare `https://`, `www.` and email addresses
+    pub autolinks: bool,
+    pub footnotes: bool,
+    // `> [!note]` callouts
+    pub alerts: bool,
+    // `$...$` and `$$...$$`
+    pub math: bool,
+    // `[[...]]` links and `![[...]]` embeds
+    pub wikilinks: bool,
+    pub tags: bool,
+    // YAML between `---` lines at the top of the note
+    pub front_matter: bool,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            tables: true,
+            task_lists: true,
+            strikethrough: true,
+            autolinks: true,
+            footnotes: true,
+            alerts: true,
+            math: true,
+            wikilinks: true,
+            tags: true,
+            front_matter: true,
+        }
+    }
+}
+
+// Parser kept in Tauri managed state so its caches survive between renders
+#[derive(Default)]
+pub struct MarkdownState {
+    pub gfm: Mutex<GfmMarkdownParser>,
+}
@@ -0,0 +1,322 @@
+use crate::markdown::inline::{
+    decode_entity, escape_html, scan_attribute, scan_html_tag, scan_tag_name, skip_ascii_whitespace,
+};
+
+// Raw HTML allowed through the sanitizer, with the attributes each tag may
+// keep. Everything else is escaped and shows up as text in the preview.
+const ALLOWED_TAGS: &[(&str, &[&str])] = &[
+    ("details", &["open"]),
+    ("summary", &[]),
+    ("kbd", &[]),
+    ("sub", &[]),
+];
+
+const SAFE_URL_SCHEMES: &[&str] = &["http", "https", "mailto", "tel", "file"];
+
+const SAFE_IMAGE_DATA: &[&str] = &[
+    "data:image/png",
+    "data:image/gif",
+    "data:image/jpeg",
+    "data:image/webp",
+];
+
+// Writes raw HTML from a note (an HTML block or an inline tag) into `out`.
+// Allowlisted tags are re-emitted in a normalized form with only their
+// allowed attributes, comments are dropped, and anything else is escaped.
+pub fn sanitize_html(raw: &str, out: &mut String) {
+    let bytes = raw.as_bytes();
+    let mut i = 0;
+    let mut text_start = 0;
+
+    while i < bytes.len() {
+        if bytes[i] != b'<' {
+            i += 1;
+            continue;
+        }
+
+        let Some(len) = scan_html_tag(&bytes[i..]) else {
+            i += 1;
+            continue;
+        };
+
+        push_text(&raw[text_start..i], out);
+        let tag = &raw[i..i + len];
+        if !tag.starts_with("<!--") && !write_allowed_tag(tag, out) {
+            out.push_str(&escape_html(tag));
+        }
+
+        i += len;
+        text_start = i;
+    }
+
+    push_text(&raw[text_start..], out);
+}
+
+// Checks a link or image destination against the allowed URL schemes.
+// Relative paths and fragments are always allowed.
+pub fn is_safe_url(url: &str, image: bool) -> bool {
+    let cleaned: String = url
+        .chars()
+        .filter(|ch| !ch.is_ascii_whitespace() && !ch.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    let Some(colon) = cleaned.find(':') else {
+        return true;
+    };
+    // A colon after the first path, query or fragment separator is not a scheme
+    if cleaned[..colon].contains(['/', '?', '#']) {
+        return true;
+    }
+
+    let scheme = &cleaned[..colon];
+    // Windows drive letters (`C:\notes\image.png`) look like one-letter schemes
+    if scheme.len() == 1 && scheme.as_bytes()[0].is_ascii_alphabetic() {
+        return true;
+    }
+
+    if SAFE_URL_SCHEMES.contains(&scheme) {
+        return true;
+    }
+    image && SAFE_IMAGE_DATA.iter().any(|prefix| cleaned.starts_with(prefix))
+}
+
+fn write_allowed_tag(tag: &str, out: &mut String) -> bool {
+    let bytes = tag.as_bytes();
+    let closing = bytes.get(1) == Some(&b'/');
+    let name_start = if closing { 2 } else { 1 };
+    let Some(name_end) = scan_tag_name(bytes, name_start) else {
+        return false;
+    };
+
+    let name = tag[name_start..name_end].to_ascii_lowercase();
+    let Some(&(_, allowed_attributes)) = ALLOWED_TAGS.iter().find(|(allowed, _)| *allowed == name) else {
+        return false;
+    };
+
+    if closing {
+        out.push_str("</");
+        out.push_str(&name);
+        out.push('>');
+        return true;
+    }
+
+    out.push('<');
+    out.push_str(&name);
+
+    // Allowed attributes are all boolean, so only their names are kept
+    let mut i = name_end;
+    loop {
+        let start = skip_ascii_whitespace(bytes, i);
+        if start == i {
+            break;
+        }
+        let Some(end) = scan_attribute(bytes, start) else {
+            break;
+        };
+
+        let attribute = &tag[start..end];
+        let attribute_name = attribute
+            .split(|ch: char| ch == '=' || ch.is_ascii_whitespace())
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if allowed_attributes.contains(&attribute_name.as_str()) {
+            out.push(' ');
+            out.push_str(&attribute_name);
+        }
+        i = end;
+    }
+
+    out.push('>');
+    true
+}
+
+// Escapes text between tags, keeping entity references that are already valid.
+fn push_text(text: &str, out: &mut String) {
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&escape_html(&rest[..pos]));
+        match decode_entity(&rest[pos..]) {
+            Some((_, len)) => {
+                out.push_str(&rest[pos..pos + len]);
+                rest = &rest[pos + len..];
+            }
+            None => {
+                out.push_str("&amp;");
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    out.push_str(&escape_html(rest));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::gfm_parser::GfmMarkdownParser;
+
+    fn render(markdown: &str) -> String {
+        GfmMarkdownParser::new().render_document(markdown).html
+    }
+
+    // Walks every tag in the rendered HTML and fails on anything that could
+    // run script: unexpected elements, event handlers or unsafe URLs. The
+    // preview's own icons are inline SVG, so that is checked separately.
+    fn assert_inert(markdown: &str) {
+        let html = render(markdown);
+        let mut rest = html.as_str();
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..].find('>').map_or(rest.len(), |end| start + end + 1);
+            let tag = rest[start..end].to_ascii_lowercase();
+            rest = &rest[end..];
+
+            let name: String = tag[1..].trim_start_matches('/').chars().take_while(char::is_ascii_alphanumeric).collect();
+            assert!(
+                !["script", "iframe", "style", "object", "embed", "base", "form"].contains(&name.as_str()),
+                "{:?} rendered a <{}> element: {}",
+                markdown,
+                name,
+                html
+            );
+
+            for attribute in tag.split_whitespace().skip(1) {
+                let (attribute_name, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+                let value = value.trim_matches(|ch| ch == '"' || ch == '>');
+                assert!(
+                    !attribute_name.starts_with("on") && attribute_name != "style" && attribute_name != "srcdoc",
+                    "{:?} kept the {} attribute: {}",
+                    markdown,
+                    attribute_name,
+                    html
+                );
+                if ["href", "src", "xlink:href", "action"].contains(&attribute_name) {
+                    assert!(
+                        is_safe_url(value, attribute_name == "src"),
+                        "{:?} kept an unsafe URL: {}",
+                        markdown,
+                        html
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn script_urls_are_dropped() {
+        let corpus = [
+            "[a](javascript:alert(1))",
+            "[a](JaVaScRiPt:alert(1))",
+            "[a](  javascript:alert(1))",
+            "[a](<javascript:alert(1)>)",
+            "[a](java\\script:alert(1))",
+            "[a](&#106;avascript:alert(1))",
+            "[a](&#x6A;avascript&#x3A;alert(1))",
+            "[a](java&#x09;script:alert(1))",
+            "[a](&Tab;javascript:alert(1))",
+            "[a](jav&NewLine;ascript:alert(1))",
+            "[a](vbscript:msgbox(1))",
+            "[a](VBScript:msgbox(1))",
+            "[a](&#x76;bscript:msgbox(1))",
+            "<javascript:alert(1)>",
+            "<VBSCRIPT:msgbox(1)>",
+            "![i](javascript:alert(1))",
+            "[x][a]\n\n[a]: javascript:alert(1)",
+            "[x][a]\n\n[a]: &#x6A;avascript:alert(1)",
+            "<a href=\"javascript:alert(1)\">x</a>",
+        ];
+        for markdown in corpus {
+            assert_inert(markdown);
+        }
+
+        assert!(!is_safe_url("JaVaScRiPt:alert(1)", false));
+        assert!(!is_safe_url("java\tscript:alert(1)", false));
+        assert!(!is_safe_url("\u{1}javascript:alert(1)", false));
+        assert!(!is_safe_url("vbscript:msgbox(1)", true));
+        assert!(is_safe_url("notes/javascript:alert.md", false));
+    }
+
+    #[test]
+    fn event_handlers_are_dropped() {
+        let corpus = [
+            "<img src=x onerror=alert(1)>",
+            "<details open ontoggle=alert(1)>x</details>",
+            "<details/open/ontoggle=alert(1)>",
+            "<kbd onclick=\"alert(1)\">k</kbd>",
+            "<summary ONMOUSEOVER='alert(1)'>s</summary>",
+            "<sub style=\"background:url(javascript:alert(1))\">x</sub>",
+            "<body onload=alert(1)>",
+            "<iframe srcdoc=\"<script>alert(1)</script>\">",
+        ];
+        for markdown in corpus {
+            assert_inert(markdown);
+        }
+
+        let mut out = String::new();
+        sanitize_html("<details open ontoggle=alert(1)>", &mut out);
+        assert_eq!(out, "<details open>");
+    }
+
+    #[test]
+    fn svg_and_math_are_escaped() {
+        let corpus = [
+            "<svg onload=alert(1)>",
+            "<svg><script>alert(1)</script></svg>",
+            "<svg><a xlink:href=\"javascript:alert(1)\"><text>x</text></a></svg>",
+            "<svg><animate attributeName=href to=javascript:alert(1) /></svg>",
+            "<math><mi xlink:href=\"javascript:alert(1)\">x</mi></math>",
+            "<math><maction actiontype=statusline href=javascript:alert(1)>x</maction></math>",
+            "text <svg/onload=alert(1)> text",
+        ];
+        for markdown in corpus {
+            assert_inert(markdown);
+            let html = render(markdown);
+            assert!(!html.contains("<svg") && !html.contains("<math"), "{:?} rendered raw markup: {}", markdown, html);
+        }
+    }
+
+    #[test]
+    fn only_image_data_urls_are_kept() {
+        let corpus = [
+            "[a](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+            "[a](DATA:text/html,<script>alert(1)</script>)",
+            "[a](data:image/png;base64,iVBORw0KGgo=)",
+            "![i](data:text/html,<script>alert(1)</script>)",
+            "![i](data:image/svg+xml;base64,PHN2ZyBvbmxvYWQ9YWxlcnQoMSk+)",
+            "![i](data:application/javascript,alert(1))",
+        ];
+        for markdown in corpus {
+            assert_inert(markdown);
+            assert!(!render(markdown).contains("data:"), "{:?} kept a data URL", markdown);
+        }
+
+        let image = render("![i](data:image/png;base64,iVBORw0KGgo=)");
+        assert!(image.contains("src=\"data:image/png;base64,iVBORw0KGgo=\""), "{}", image);
+    }
+
+    #[test]
+    fn raw_html_in_containers_is_sanitized() {
+        let corpus = [
+            "- <script>alert(1)</script>\n- <img src=x onerror=alert(1)>",
+            "1. item\n\n   <svg onload=alert(1)>\n",
+            "- [ ] <iframe src=javascript:alert(1)>",
+            "> <script>alert(1)</script>",
+            "> - <details ontoggle=alert(1) open>x</details>",
+            "| a | b |\n| - | - |\n| <script>alert(1)</script> | <img src=x onerror=alert(1)> |",
+            "| <svg onload=alert(1)> |\n| --- |\n| [x](javascript:alert(1)) |",
+            "x[^1]\n\n[^1]: <script>alert(1)</script> <iframe src=javascript:alert(1)>",
+            "x[^n]\n\n[^n]: Note\n\n    <img src=x onerror=alert(1)>\n    [a](vbscript:msgbox(1))",
+            "> [!note] <svg onload=alert(1)>\n> <script>alert(1)</script>",
+        ];
+        for markdown in corpus {
+            assert_inert(markdown);
+        }
+    }
+
+    #[test]
+    fn allowed_tags_lose_other_attributes() {
+        let mut out = String::new();
+        sanitize_html("<KBD class=x title=\"y\">Ctrl</KBD><!-- <script>alert(1)</script> -->", &mut out);
+        assert_eq!(out, "<kbd>Ctrl</kbd>");
+    }
+}
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Average reading speeds: words of space-separated scripts, and characters
+// of Chinese and Japanese, where every character is read on its own
+const WORDS_PER_MINUTE: usize = 200;
+const CJK_CHARACTERS_PER_MINUTE: usize = 500;
+
+// Statistics of the plain text of a document, without markup
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentStats {
+    // Words by Unicode segmentation, where each Chinese or Japanese
+    // character is a word of its own
+    pub words: usize,
+    pub cjk_characters: usize,
+    pub characters: usize,
+    pub characters_without_spaces: usize,
+    pub sentences: usize,
+    // Rounded up, so any text takes at least a minute
+    pub reading_time_minutes: usize,
+    // Words outside Chinese and Japanese text, read at `WORDS_PER_MINUTE`
+    #[serde(skip)]
+    other_words: usize,
+}
+
+impl DocumentStats {
+    pub fn add_text(&mut self, text: &str) {
+        let mut in_sentence = false;
+        let mut chars = text.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            self.characters += 1;
+            if ch.is_whitespace() {
+                continue;
+            }
+            self.characters_without_spaces += 1;
+
+            if is_cjk(ch) {
+                self.cjk_characters += 1;
+            }
+            if ch.is_alphanumeric() {
+                in_sentence = true;
+            } else if in_sentence && is_sentence_end(ch) {
+                // `3.14` doesn't end a sentence, but `。` is never followed by a space
+                let full_width = matches!(ch, '。' | '！' | '？' | '｡');
+                if full_width || !chars.peek().is_some_and(|next| next.is_alphanumeric()) {
+                    self.sentences += 1;
+                    in_sentence = false;
+                }
+            }
+        }
+
+        // Headings, list items and cells often have no final punctuation
+        if in_sentence {
+            self.sentences += 1;
+        }
+        // Segmentation keeps runs of kana together, so CJK segments are
+        // counted by character
+        for word in text.unicode_words() {
+            match word.chars().filter(|&ch| is_cjk(ch)).count() {
+                0 => {
+                    self.words += 1;
+                    self.other_words += 1;
+                }
+                cjk => self.words += cjk,
+            }
+        }
+        self.reading_time_minutes = self.reading_time();
+    }
+
+    pub fn add(&mut self, other: &DocumentStats) {
+        self.words += other.words;
+        self.cjk_characters += other.cjk_characters;
+        self.characters += other.characters;
+        self.characters_without_spaces += other.characters_without_spaces;
+        self.sentences += other.sentences;
+        self.other_words += other.other_words;
+        self.reading_time_minutes = self.reading_time();
+    }
+
+    fn reading_time(&self) -> usize {
+        let seconds = self.other_words * 60 / WORDS_PER_MINUTE + self.cjk_characters * 60 / CJK_CHARACTERS_PER_MINUTE;
+        if self.words == 0 {
+            0
+        } else {
+            seconds.div_ceil(60).max(1)
+        }
+    }
+}
+
+// Han ideographs and kana, which are written without spaces between words.
+// Hangul is left out since Korean separates words with spaces.
+fn is_cjk(ch: char) -> bool {
+    matches!(ch,
+        '\u{3040}'..='\u{30FF}'     // Hiragana and Katakana
+        | '\u{31F0}'..='\u{31FF}'   // Katakana phonetic extensions
+        | '\u{3400}'..='\u{4DBF}'   // CJK extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK unified ideographs
+        | '\u{F900}'..='\u{FAFF}'   // CJK compatibility ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Halfwidth Katakana
+        | '\u{20000}'..='\u{2FA1F}' // CJK extensions B and later
+    )
+}
+
+fn is_sentence_end(ch: char) -> bool {
+    matches!(ch, '.' | '!' | '?' | '…' | '。' | '！' | '？' | '｡')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(text: &str) -> DocumentStats {
+        let mut stats = DocumentStats::default();
+        stats.add_text(text);
+        stats
+    }
+
+    #[test]
+    fn counts_words_across_scripts() {
+        let mixed = stats("Hello 世界");
+        assert_eq!((mixed.words, mixed.cjk_characters, mixed.other_words), (3, 2, 1));
+
+        // Katakana runs are one segment, but each character is read on its own
+        let kana = stats("カタカナ and ひらがな");
+        assert_eq!((kana.words, kana.cjk_characters, kana.other_words), (9, 8, 1));
+
+        // Korean separates words with spaces
+        let korean = stats("안녕하세요 세계");
+        assert_eq!((korean.words, korean.cjk_characters, korean.other_words), (2, 0, 2));
+
+        let numbers = stats("東京2020で3.14");
+        assert_eq!((numbers.words, numbers.cjk_characters, numbers.other_words), (5, 3, 2));
+    }
+
+    #[test]
+    fn reading_time_counts_both_scripts() {
+        // 200 English words take a minute and 400 kana another 48 seconds
+        let text = format!("{}{}", "カタカナ ".repeat(100), "word ".repeat(200));
+        assert_eq!(stats(&text).reading_time_minutes, 2);
+        assert_eq!(stats(&"word ".repeat(200)).reading_time_minutes, 1);
+        assert_eq!(stats(&"漢".repeat(1000)).reading_time_minutes, 2);
+        assert_eq!(stats("").reading_time_minutes, 0);
+    }
+
+    #[test]
+    fn added_stats_match_the_whole_text() {
+        let mut added = stats("カタカナの文章。");
+        added.add(&stats(" Some English words."));
+        assert_eq!(added, stats("カタカナの文章。 Some English words."));
+    }
+}
@@ -0,0 +1,86 @@
+// Flips the checkbox of the task item whose marker is on `line` (1-based).
+// `expected` is the line as the caller last saw it; when the document has
+// changed there since, it is left alone rather than clobbering the edit.
+pub fn toggle_task(document: &str, line: usize, expected: &str) -> Result<String, String> {
+    let mut offset = 0;
+    let mut current = None;
+    for (index, text) in document.split_inclusive('\n').enumerate() {
+        if index + 1 == line {
+            current = Some(text.trim_end_matches(['\n', '\r']));
+            break;
+        }
+        offset += text.len();
+    }
+
+    let current = current.ok_or_else(|| format!("Line {} is outside the document", line))?;
+    if current != expected.trim_end_matches('\r') {
+        return Err(format!("Line {} has changed since it was rendered", line));
+    }
+    let checkbox = checkbox_offset(current).ok_or_else(|| format!("Line {} is not a task item", line))?;
+
+    let position = offset + checkbox;
+    let flipped = if document.as_bytes()[position] == b' ' { "x" } else { " " };
+    let mut updated = String::with_capacity(document.len());
+    updated.push_str(&document[..position]);
+    updated.push_str(flipped);
+    updated.push_str(&document[position + 1..]);
+    Ok(updated)
+}
+
+// Offset of the character between the brackets of `[ ]` or `[x]`, after any
+// quote markers and list markers (`- - [ ]` nests lists on one line)
+fn checkbox_offset(line: &str) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    let mut saw_marker = false;
+
+    loop {
+        while matches!(bytes.get(i), Some(b' ' | b'\t' | b'>')) {
+            i += 1;
+        }
+        match bytes.get(i)? {
+            b'-' | b'*' | b'+' => i += 1,
+            b'0'..=b'9' => {
+                let digits = bytes[i..].iter().take_while(|b| b.is_ascii_digit()).count();
+                i += digits;
+                if digits > 9 || !matches!(bytes.get(i), Some(b'.' | b')')) {
+                    return None;
+                }
+                i += 1;
+            }
+            b'[' if saw_marker => break,
+            _ => return None,
+        }
+        if !matches!(bytes.get(i), Some(b' ' | b'\t')) {
+            return None;
+        }
+        saw_marker = true;
+    }
+
+    let is_checkbox = matches!(bytes.get(i + 1), Some(b' ' | b'x' | b'X'))
+        && bytes.get(i + 2) == Some(&b']')
+        && matches!(bytes.get(i + 3), Some(b' ' | b'\t'));
+    is_checkbox.then_some(i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flips_only_the_checkbox() {
+        assert_eq!(toggle_task("- [ ] a\n- [x] b\n", 1, "- [ ] a"), Ok("- [x] a\n- [x] b\n".to_string()));
+        assert_eq!(toggle_task("> 1. [X] a", 1, "> 1. [X] a"), Ok("> 1. [ ] a".to_string()));
+        assert_eq!(toggle_task("- - [ ] nested", 1, "- - [ ] nested"), Ok("- - [x] nested".to_string()));
+    }
+
+    #[test]
+    fn refuses_lines_that_changed() {
+        // The same task further down doesn't count: line 1 is what was clicked
+        let document = "new line\n- [ ] same\n- [ ] same";
+        assert!(toggle_task(document, 1, "- [ ] same").is_err());
+        assert!(toggle_task(document, 4, "- [ ] same").is_err());
+        assert!(toggle_task("[ ] not an item", 1, "[ ] not an item").is_err());
+        assert!(toggle_task("- [ ]not a task", 1, "- [ ]not a task").is_err());
+    }
+}
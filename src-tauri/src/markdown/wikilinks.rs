@@ -0,0 +1,116 @@
+const MARKDOWN_EXTENSIONS: [&str; 4] = ["md", "markdown", "mdown", "mkd"];
+
+// Notes of the open workspace, used to resolve `[[Note]]` links by name the
+// way Obsidian does: a link may name just the note or a trailing part of its
+// path, and when several notes match, the one closest to the root wins.
+#[derive(Debug, Default)]
+pub struct NoteIndex {
+    // (lowercased path relative to the root without extension, full path),
+    // sorted by depth and then by name so the first match is the preferred one
+    notes: Vec<(String, String)>,
+}
+
+impl NoteIndex {
+    // `root` and `paths` use '/' separators, as `scan_directory` produces them
+    pub fn new(root: &str, paths: &[String]) -> Self {
+        let root = root.trim_end_matches('/');
+        let mut notes: Vec<(String, String)> = paths
+            .iter()
+            .map(|path| {
+                let relative = path
+                    .strip_prefix(root)
+                    .map(|rest| rest.trim_start_matches('/'))
+                    .unwrap_or(path);
+                (note_key(relative), path.clone())
+            })
+            .collect();
+
+        notes.sort_by(|(a, _), (b, _)| {
+            a.matches('/').count().cmp(&b.matches('/').count()).then_with(|| a.cmp(b))
+        });
+        Self { notes }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.is_empty()
+    }
+
+    // Full path of the note a wikilink target points to
+    pub fn resolve(&self, target: &str) -> Option<&str> {
+        let key = note_key(target.trim().replace('\\', "/").trim_start_matches('/'));
+        if key.is_empty() {
+            return None;
+        }
+
+        self.notes
+            .iter()
+            .find(|(note, _)| {
+                note == &key
+                    || (note.ends_with(key.as_str()) && note[..note.len() - key.len()].ends_with('/'))
+            })
+            .map(|(_, path)| path.as_str())
+    }
+}
+
+// A parsed `[[target#heading|alias]]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WikiLink {
+    pub target: String,
+    pub heading: Option<String>,
+    pub alias: Option<String>,
+}
+
+impl WikiLink {
+    // Parses the text between `[[` and `]]`
+    pub fn parse(inner: &str) -> Option<Self> {
+        if inner.contains(['[', ']', '\n']) {
+            return None;
+        }
+
+        let (link, alias) = match inner.split_once('|') {
+            Some((link, alias)) => (link, Some(alias.trim()).filter(|alias| !alias.is_empty())),
+            None => (inner, None),
+        };
+        let (target, heading) = match link.split_once('#') {
+            Some((target, heading)) => (target, Some(heading.trim()).filter(|heading| !heading.is_empty())),
+            None => (link, None),
+        };
+
+        let target = target.trim();
+        if target.is_empty() && heading.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            target: target.to_string(),
+            heading: heading.map(str::to_string),
+            alias: alias.map(str::to_string),
+        })
+    }
+
+    // Text shown for the link: the alias, or `Note > Heading` like Obsidian
+    pub fn label(&self) -> String {
+        if let Some(alias) = &self.alias {
+            return alias.clone();
+        }
+        match &self.heading {
+            Some(heading) if self.target.is_empty() => heading.clone(),
+            Some(heading) => format!("{} > {}", self.target, heading),
+            None => self.target.clone(),
+        }
+    }
+}
+
+fn note_key(path: &str) -> String {
+    let without_extension = match path.rsplit_once('.') {
+        Some((stem, extension))
+            if !stem.is_empty()
+                && !extension.contains('/')
+                && MARKDOWN_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()) =>
+        {
+            stem
+        }
+        _ => path,
+    };
+    without_extension.to_lowercase()
+}
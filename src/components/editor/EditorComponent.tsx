@@ -5,6 +5,8 @@ import { useAppearance } from '../../contexts/AppearanceContext';
 
 export interface EditorComponentProps {
   initialContent?: string;
+  // Caminho do arquivo aberto, usado para verificar links relativos
+  filePath?: string;
   themeName?: 'light' | 'dark';
   plugins?: string[];
   readOnly?: boolean;
@@ -28,6 +30,7 @@ export interface EditorComponentHandle {
 
 export const EditorComponent = forwardRef<EditorComponentHandle, EditorComponentProps>(({
   initialContent = '',
+  filePath,
   themeName,
   plugins = [],
   readOnly = false,
@@ -111,6 +114,7 @@ export const EditorComponent = forwardRef<EditorComponentHandle, EditorComponent
         previewRef.current = new MarkdownPreview({
           container: previewContainerRef.current,
           theme: finalTheme,
+          filePath,
         });
         // Initial content sync
         previewRef.current.updateFromContent(initialContent);
@@ -121,7 +125,7 @@ export const EditorComponent = forwardRef<EditorComponentHandle, EditorComponent
     } catch (error) {
       onError?.(error as Error);
     }
-  }, [editorConfig, handleStateChange, showPreview, finalTheme, initialContent, onError]);
+  }, [editorConfig, handleStateChange, showPreview, finalTheme, initialContent, filePath, onError]);
 
   useEffect(() => {
     initializeEditor();
@@ -183,6 +187,7 @@ export const EditorComponent = forwardRef<EditorComponentHandle, EditorComponent
         previewRef.current = new MarkdownPreview({
           container: previewContainerRef.current,
           theme: finalTheme,
+          filePath,
         });
         // Sync current content immediately
         const currentContent = editorRef.current?.getContent() || initialContent;
@@ -215,12 +220,16 @@ export const EditorComponent = forwardRef<EditorComponentHandle, EditorComponent
         });
       }
     }
-  }, [showPreview, finalTheme, initialContent]);
+  }, [showPreview, finalTheme, initialContent, filePath]);
 
   useEffect(() => {
     handlePreviewToggle();
   }, [handlePreviewToggle]);
 
+  useEffect(() => {
+    previewRef.current?.updateConfig({ filePath });
+  }, [filePath]);
+
 
   useImperativeHandle(ref, () => ({
     getContent: () => editorRef.current?.getContent() || '',
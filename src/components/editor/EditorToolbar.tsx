@@ -1,14 +1,24 @@
 import { memo } from 'react';
-import { BookOpen, Edit3 } from 'lucide-react';
+import { AlertTriangle, BookOpen, Edit3, ListTree } from 'lucide-react';
 
 interface EditorToolbarProps {
   isPreviewMode: boolean;
   onTogglePreview: () => void;
+  isOutlineOpen?: boolean;
+  onToggleOutline?: () => void;
+  isProblemsOpen?: boolean;
+  onToggleProblems?: () => void;
+  problemCount?: number;
 }
 
 export const EditorToolbar = memo(function EditorToolbar({  
   isPreviewMode, 
-  onTogglePreview 
+  onTogglePreview,
+  isOutlineOpen = false,
+  onToggleOutline,
+  isProblemsOpen = false,
+  onToggleProblems,
+  problemCount = 0
 }: EditorToolbarProps) {
   return (
     <div 
@@ -29,6 +39,35 @@ export const EditorToolbar = memo(function EditorToolbar({
       </button>
       
       <div className="flex-1" />
+
+      {isPreviewMode && onToggleProblems && (
+        <button
+          onClick={onToggleProblems}
+          className="flex items-center gap-2 px-3 py-1.5 rounded-md text-sm font-medium transition-colors hover:opacity-80"
+          style={{
+            color: 'var(--theme-secondary-foreground)',
+            backgroundColor: isProblemsOpen ? 'var(--theme-secondary)' : undefined
+          }}
+          title={isProblemsOpen ? "Ocultar Problemas" : "Mostrar Problemas"}
+        >
+          <AlertTriangle size={16} />
+          {problemCount > 0 && <span className="text-xs">{problemCount}</span>}
+        </button>
+      )}
+
+      {isPreviewMode && onToggleOutline && (
+        <button
+          onClick={onToggleOutline}
+          className="flex items-center gap-2 px-3 py-1.5 rounded-md text-sm font-medium transition-colors hover:opacity-80"
+          style={{
+            color: 'var(--theme-secondary-foreground)',
+            backgroundColor: isOutlineOpen ? 'var(--theme-secondary)' : undefined
+          }}
+          title={isOutlineOpen ? "Ocultar Sumário" : "Mostrar Sumário"}
+        >
+          <ListTree size={16} />
+        </button>
+      )}
     </div>
   );
 });
\ No newline at end of file
@@ -0,0 +1,48 @@
+import { memo } from 'react';
+import type { TocEntry } from './preview/MarkdownPreview';
+
+interface OutlinePanelProps {
+  entries: TocEntry[];
+  onSelect: (entry: TocEntry) => void;
+}
+
+// Sumário da nota, montado a partir do `toc` devolvido pelo parser
+export const OutlinePanel = memo(function OutlinePanel({
+  entries,
+  onSelect
+}: OutlinePanelProps) {
+  const topLevel = Math.min(...entries.map((entry) => entry.level));
+
+  return (
+    <nav
+      className="w-56 shrink-0 overflow-auto px-3 py-2 text-sm"
+      style={{
+        borderLeft: '1px solid var(--theme-border)',
+        backgroundColor: 'var(--inkdown-editor-bg)'
+      }}
+      aria-label="Sumário"
+    >
+      {entries.length === 0 ? (
+        <p className="text-xs theme-text-muted">Nenhum título nesta nota</p>
+      ) : (
+        <ul>
+          {entries.map((entry) => (
+            <li key={`${entry.slug}:${entry.line}`}>
+              <button
+                onClick={() => onSelect(entry)}
+                className="w-full truncate rounded py-0.5 text-left transition-colors hover:opacity-80"
+                style={{
+                  paddingLeft: `${(entry.level - topLevel) * 12}px`,
+                  color: 'var(--theme-secondary-foreground)'
+                }}
+                title={entry.text}
+              >
+                {entry.text}
+              </button>
+            </li>
+          ))}
+        </ul>
+      )}
+    </nav>
+  );
+});
@@ -0,0 +1,74 @@
+import { memo } from 'react';
+import { AlertCircle, AlertTriangle, Info, Wrench } from 'lucide-react';
+import type { Diagnostic } from './preview/MarkdownPreview';
+
+interface ProblemsPanelProps {
+  diagnostics: Diagnostic[];
+  onSelect: (diagnostic: Diagnostic) => void;
+  onFix: (diagnostic: Diagnostic) => void;
+}
+
+const severityIcons = {
+  error: { icon: AlertCircle, color: 'var(--theme-destructive)' },
+  warning: { icon: AlertTriangle, color: 'var(--theme-accent)' },
+  info: { icon: Info, color: 'var(--theme-muted-foreground)' }
+};
+
+// Problemas encontrados pelo lint na última renderização da nota
+export const ProblemsPanel = memo(function ProblemsPanel({
+  diagnostics,
+  onSelect,
+  onFix
+}: ProblemsPanelProps) {
+  return (
+    <aside
+      className="w-72 shrink-0 overflow-auto px-3 py-2 text-sm"
+      style={{
+        borderLeft: '1px solid var(--theme-border)',
+        backgroundColor: 'var(--inkdown-editor-bg)'
+      }}
+      aria-label="Problemas"
+    >
+      {diagnostics.length === 0 ? (
+        <p className="text-xs theme-text-muted">Nenhum problema nesta nota</p>
+      ) : (
+        <ul>
+          {diagnostics.map((diagnostic) => {
+            const { icon: Icon, color } = severityIcons[diagnostic.severity];
+            return (
+              <li
+                key={`${diagnostic.rule}:${diagnostic.line}:${diagnostic.column}`}
+                className="flex items-start gap-1"
+              >
+                <button
+                  onClick={() => onSelect(diagnostic)}
+                  className="flex min-w-0 flex-1 items-start gap-2 rounded py-0.5 text-left transition-colors hover:opacity-80"
+                  style={{ color: 'var(--theme-secondary-foreground)' }}
+                  title={diagnostic.rule}
+                >
+                  <Icon size={14} className="mt-0.5 shrink-0" style={{ color }} />
+                  <span className="min-w-0">
+                    {diagnostic.message}
+                    <span className="ml-1 text-xs theme-text-muted">
+                      {diagnostic.line}:{diagnostic.column}
+                    </span>
+                  </span>
+                </button>
+                {diagnostic.fix && (
+                  <button
+                    onClick={() => onFix(diagnostic)}
+                    className="shrink-0 rounded p-1 transition-colors hover:opacity-80"
+                    style={{ color: 'var(--theme-secondary-foreground)' }}
+                    title="Corrigir"
+                  >
+                    <Wrench size={14} />
+                  </button>
+                )}
+              </li>
+            );
+          })}
+        </ul>
+      )}
+    </aside>
+  );
+});
@@ -1,15 +1,75 @@
 import { invoke } from '@tauri-apps/api/core';
 import { cacheUtils } from '../../../utils/localStorage';
+import { resolveMarkdownSyntax } from '../../../types/config';
 
-interface ParseResult {
+interface SourceMapEntry {
+  start_line: number;
+  end_line: number;
+}
+
+export interface TocEntry {
+  level: number;
+  text: string;
+  slug: string;
+  line: number;
+}
+
+export interface DocumentStats {
+  words: number;
+  cjk_characters: number;
+  characters: number;
+  characters_without_spaces: number;
+  sentences: number;
+  reading_time_minutes: number;
+}
+
+interface TextEdit {
+  start_line: number;
+  end_line: number;
+  lines: string[];
+}
+
+// Linhas e colunas começam em 1; `fix` é uma edição que resolve o problema
+export interface Diagnostic {
+  rule: string;
+  severity: 'info' | 'warning' | 'error';
+  line: number;
+  column: number;
+  message: string;
+  fix?: TextEdit;
+}
+
+// Bloco de nível superior do preview; `key` só muda quando o conteúdo muda
+interface HtmlBlock {
+  key: string;
+  start_line: number;
+  html: string;
+}
+
+interface RenderedBlock {
+  key: string;
+  startLine: number;
+  nodes: ChildNode[];
+}
+
+export interface ParseResult {
   html: string;
   word_count: number;
+  stats: DocumentStats;
+  source_map: SourceMapEntry[];
+  toc: TocEntry[];
+  tags: string[];
+  front_matter?: Record<string, unknown>;
+  version?: number;
+  blocks?: HtmlBlock[];
+  diagnostics?: Diagnostic[];
   error?: string;
 }
 
 export interface PreviewConfig {
   container: HTMLElement;
   theme?: 'light' | 'dark';
+  filePath?: string;
 }
 
 export class MarkdownPreview {
@@ -19,6 +79,16 @@ export class MarkdownPreview {
   private updateTimeout?: number;
   private lastContent: string = '';
   private lastHtml: string = '';  
+  // Blocos exibidos, na ordem do documento, com os nós de cada um
+  private renderedBlocks: RenderedBlock[] = [];
+  // Versão do documento mantido pelo parser no backend
+  private documentVersion?: number;
+  // Sintaxe e tipos de callout enviados por último ao parser
+  private parserOptionsKey?: string;
+  private calloutTypesKey?: string;
+  private lintConfigKey?: string;
+  private diagnosticsKey?: string;
+  private outlineKey?: string;
 
   constructor(config: PreviewConfig) {
     this.config = config;
@@ -33,7 +103,57 @@ export class MarkdownPreview {
   private createPreviewElement(): void {
     this.previewElement = document.createElement('div');
     this.previewElement.className = 'markdown-preview-content';
+    this.previewElement.addEventListener('click', this.handleClick);
     this.container.appendChild(this.previewElement);
+    window.addEventListener('inkdown-task-toggled', this.handleTaskToggled);
+  }
+
+  // Wikilinks resolvidos carregam o caminho da nota em data-path
+  private handleClick = (event: MouseEvent): void => {
+    const target = event.target as HTMLElement;
+    const checkbox = target.closest<HTMLElement>('.task-list-item-checkbox');
+    if (checkbox) {
+      this.toggleTask(checkbox);
+      return;
+    }
+
+    const link = target.closest<HTMLAnchorElement>('a.wikilink[data-path]');
+    if (!link) return;
+
+    event.preventDefault();
+    window.dispatchEvent(new CustomEvent('inkdown-open-note', {
+      detail: { path: link.dataset.path, heading: link.dataset.heading }
+    }));
+  };
+
+  // O backend já renderizou o arquivo com a tarefa marcada; o editor recebe
+  // o mesmo texto em seguida, que assim não precisa ser renderizado de novo
+  private handleTaskToggled = (event: Event): void => {
+    const { path, content, result } = (event as CustomEvent<{
+      path: string;
+      content: string;
+      result: ParseResult;
+    }>).detail;
+    if (path !== this.config.filePath) return;
+
+    if (this.updateTimeout) {
+      clearTimeout(this.updateTimeout);
+    }
+    this.showResult(content, result);
+  };
+
+  // Tarefas de notas embutidas não têm linha de origem e ficam só para leitura
+  private toggleTask(checkbox: HTMLElement): void {
+    const item = checkbox.closest<HTMLElement>('li[data-source-line]');
+    if (!item || checkbox.closest('.embed')) return;
+
+    const line = Number(item.dataset.sourceLine);
+    const expected = this.lastContent.split('\n')[line - 1];
+    if (expected === undefined) return;
+
+    window.dispatchEvent(new CustomEvent('inkdown-toggle-task', {
+      detail: { line, expected }
+    }));
   }
 
   public updateFromContent(content: string): void {
@@ -58,59 +178,211 @@ export class MarkdownPreview {
       }
 
       if (!content.trim()) {
-        if (this.lastHtml !== '') {
+        if (this.lastHtml !== '' || this.renderedBlocks.length > 0) {
           this.previewElement.innerHTML = '';
           this.lastHtml = '';
+          this.renderedBlocks = [];
         }
         this.lastContent = content;
+        this.documentVersion = undefined;
+        this.publishDiagnostics([]);
+        this.publishOutline([]);
         return;
       }
 
-      // Buscar configuração GFM do cache
-      const workspaceConfig = cacheUtils.getWorkspaceConfig();
-      const gfmEnabled = workspaceConfig?.githubMarkdown ?? false;
-      
-      console.log('🔍 Cache workspaceConfig:', workspaceConfig);
-      console.log('🔍 GFM Enabled from cache:', gfmEnabled);
-
-      // Chamar parser correto baseado na configuração do cache
-      const result: ParseResult = gfmEnabled 
-        ? await invoke('parse_markdown_gfm', { markdown: content })
-        : await invoke('parse_markdown_basic', { markdown: content });
-
-      if (result.error) {
-        console.error('Markdown parsing error:', result.error);
-        const errorHtml = `<div class="error">Erro ao processar markdown: ${result.error}</div>`;
-        // Only update if different
-        if (this.lastHtml !== errorHtml) {
-          this.previewElement.innerHTML = errorHtml;
-          this.lastHtml = errorHtml;
-        }
-        this.lastContent = content;
-        return;
-      }
-
-      // Only update DOM if HTML has actually changed
-      if (result.html !== this.lastHtml) {
-        // Use requestAnimationFrame for smoother updates
-        requestAnimationFrame(() => {
-          this.previewElement.innerHTML = result.html;
-        });
-        this.lastHtml = result.html;
-      }
-      
-      this.lastContent = content;
+      const result = await this.parseMarkdown(content);
+      this.showResult(content, result);
     } catch (error) {
       console.error('Error updating preview:', error);
+      this.documentVersion = undefined;
       const errorHtml = `<div class="error">Erro ao renderizar preview: ${error}</div>`;
       if (this.lastHtml !== errorHtml) {
         this.previewElement.innerHTML = errorHtml;
         this.lastHtml = errorHtml;
+        this.renderedBlocks = [];
       }
       this.lastContent = content;
     }
   }
 
+  // Exibe o resultado do parser para `content`, o texto que ele renderizou
+  private showResult(content: string, result: ParseResult): void {
+    this.documentVersion = result.version;
+
+    // `error` resume os diagnósticos de erro; o documento é renderizado mesmo
+    // assim e eles aparecem no painel de problemas
+    this.publishDiagnostics(result.diagnostics ?? []);
+    this.publishOutline(result.toc);
+
+    // Mensagem de erro anterior sai antes dos blocos entrarem
+    if (this.lastHtml !== '') {
+      this.previewElement.innerHTML = '';
+      this.lastHtml = '';
+    }
+    this.patchBlocks(result.blocks ?? []);
+
+    this.lastContent = content;
+  }
+
+  // Substitui só os blocos cuja chave mudou, comparando a partir do início e
+  // do fim do documento; os demais mantêm rolagem, imagens e seleção
+  private patchBlocks(blocks: HtmlBlock[]): void {
+    const previous = this.renderedBlocks;
+
+    let prefix = 0;
+    while (
+      prefix < previous.length &&
+      prefix < blocks.length &&
+      previous[prefix].key === blocks[prefix].key
+    ) {
+      prefix++;
+    }
+
+    let suffix = 0;
+    while (
+      suffix < previous.length - prefix &&
+      suffix < blocks.length - prefix &&
+      previous[previous.length - 1 - suffix].key === blocks[blocks.length - 1 - suffix].key
+    ) {
+      suffix++;
+    }
+
+    for (const block of previous.slice(prefix, previous.length - suffix)) {
+      block.nodes.forEach((node) => node.remove());
+    }
+
+    const head = previous.slice(0, prefix);
+    const tail = previous.slice(previous.length - suffix);
+    const anchor = tail.find((block) => block.nodes.length > 0)?.nodes[0] ?? null;
+    const inserted = blocks.slice(prefix, blocks.length - suffix).map((block) => {
+      const template = document.createElement('template');
+      template.innerHTML = block.html;
+      const nodes = Array.from(template.content.childNodes);
+      this.previewElement.insertBefore(template.content, anchor);
+      return { key: block.key, startLine: block.start_line, nodes };
+    });
+
+    // Blocos mantidos podem ter mudado de linha
+    head.forEach((block, index) => this.moveBlock(block, blocks[index].start_line));
+    tail.forEach((block, index) => this.moveBlock(block, blocks[blocks.length - suffix + index].start_line));
+
+    this.renderedBlocks = [...head, ...inserted, ...tail];
+  }
+
+  private moveBlock(block: RenderedBlock, startLine: number): void {
+    const shift = startLine - block.startLine;
+    if (shift === 0) return;
+
+    for (const node of block.nodes) {
+      if (!(node instanceof Element)) continue;
+      const elements = [node, ...Array.from(node.querySelectorAll('[data-source-line]'))];
+      for (const element of elements) {
+        for (const attribute of ['data-source-line', 'data-source-end-line']) {
+          const value = element.getAttribute(attribute);
+          if (value !== null) {
+            element.setAttribute(attribute, String(Number(value) + shift));
+          }
+        }
+      }
+    }
+    block.startLine = startLine;
+  }
+
+  // Avisa outros componentes quando os diagnósticos do documento mudam
+  private publishDiagnostics(diagnostics: Diagnostic[]): void {
+    const diagnosticsKey = JSON.stringify([this.config.filePath, diagnostics]);
+    if (diagnosticsKey === this.diagnosticsKey) return;
+
+    this.diagnosticsKey = diagnosticsKey;
+    window.dispatchEvent(new CustomEvent('inkdown-diagnostics', {
+      detail: { path: this.config.filePath, diagnostics }
+    }));
+  }
+
+  // Idem para o sumário, usado pelo painel de títulos
+  private publishOutline(toc: TocEntry[]): void {
+    const outlineKey = JSON.stringify([this.config.filePath, toc]);
+    if (outlineKey === this.outlineKey) return;
+
+    this.outlineKey = outlineKey;
+    window.dispatchEvent(new CustomEvent('inkdown-outline', {
+      detail: { path: this.config.filePath, toc }
+    }));
+  }
+
+  private async parseMarkdown(content: string): Promise<ParseResult> {
+    const workspaceConfig = cacheUtils.getWorkspaceConfig();
+    const parserOptions = resolveMarkdownSyntax(workspaceConfig);
+    const parserOptionsKey = JSON.stringify(parserOptions);
+    if (parserOptionsKey !== this.parserOptionsKey) {
+      await invoke('set_parser_options', { options: parserOptions });
+      this.parserOptionsKey = parserOptionsKey;
+    }
+
+    const calloutTypes = workspaceConfig?.calloutTypes ?? [];
+    const calloutTypesKey = JSON.stringify(calloutTypes);
+    if (calloutTypesKey !== this.calloutTypesKey) {
+      await invoke('set_callout_types', { types: calloutTypes });
+      this.calloutTypesKey = calloutTypesKey;
+    }
+
+    const lintConfig = workspaceConfig?.lint ?? {};
+    const lintConfigKey = JSON.stringify(lintConfig);
+    if (lintConfigKey !== this.lintConfigKey) {
+      await invoke('set_lint_config', { config: lintConfig });
+      this.lintConfigKey = lintConfigKey;
+    }
+
+    if (this.documentVersion !== undefined && this.lastContent) {
+      const edit = this.computeLineEdit(this.lastContent, content);
+      try {
+        return await invoke<ParseResult>('update_markdown_gfm', {
+          previousVersion: this.documentVersion,
+          edit,
+          blocks: true,
+        });
+      } catch (error) {
+        // Versão desatualizada: envia o documento inteiro novamente
+        console.warn('Incremental preview update failed:', error);
+      }
+    }
+
+    return invoke<ParseResult>('parse_markdown_gfm', {
+      markdown: content,
+      path: this.config.filePath ?? null,
+      blocks: true,
+    });
+  }
+
+  private computeLineEdit(previous: string, next: string): TextEdit {
+    const oldLines = previous.split('\n');
+    const newLines = next.split('\n');
+
+    let prefix = 0;
+    while (
+      prefix < oldLines.length &&
+      prefix < newLines.length &&
+      oldLines[prefix] === newLines[prefix]
+    ) {
+      prefix++;
+    }
+
+    let suffix = 0;
+    while (
+      suffix < oldLines.length - prefix &&
+      suffix < newLines.length - prefix &&
+      oldLines[oldLines.length - 1 - suffix] === newLines[newLines.length - 1 - suffix]
+    ) {
+      suffix++;
+    }
+
+    return {
+      start_line: prefix,
+      end_line: oldLines.length - suffix,
+      lines: newLines.slice(prefix, newLines.length - suffix),
+    };
+  }
+
   public setTheme(theme: 'light' | 'dark'): void {
     this.config.theme = theme;
     // O tema agora é controlado através de CSS custom properties
@@ -118,6 +390,10 @@ export class MarkdownPreview {
   }
 
   public updateConfig(config: Partial<PreviewConfig>): void {
+    // Outro arquivo: o documento inteiro é enviado de novo junto com o caminho
+    if ('filePath' in config && config.filePath !== this.config.filePath) {
+      this.documentVersion = undefined;
+    }
     Object.assign(this.config, config);
     // Re-render current content with new config
     if (this.lastContent) {
@@ -129,6 +405,8 @@ export class MarkdownPreview {
     if (this.updateTimeout) {
       clearTimeout(this.updateTimeout);
     }
+    this.previewElement.removeEventListener('click', this.handleClick);
+    window.removeEventListener('inkdown-task-toggled', this.handleTaskToggled);
     if (this.container.contains(this.previewElement)) {
       this.container.removeChild(this.previewElement);
     }
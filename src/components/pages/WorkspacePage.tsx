@@ -1,4 +1,4 @@
-import { useState, useCallback, memo, useRef, useMemo, lazy, Suspense } from "react";
+import { useState, useCallback, useEffect, memo, useRef, useMemo, lazy, Suspense } from "react";
 import { useNavigate } from "react-router-dom";
 import { useDirectory } from "../../contexts/DirectoryContext";
 import { useSidebarResize } from "../../hooks/useSidebarResize";
@@ -39,6 +39,19 @@ export const WorkspacePage = memo(function WorkspacePage() {
     setSelectedFile(filePath);
   }, []);
 
+  // Disparado pelo preview ao clicar num wikilink
+  useEffect(() => {
+    const handleOpenNote = (event: Event) => {
+      const { path } = (event as CustomEvent<{ path?: string }>).detail;
+      if (path) {
+        setSelectedFile(path);
+      }
+    };
+
+    window.addEventListener('inkdown-open-note', handleOpenNote);
+    return () => window.removeEventListener('inkdown-open-note', handleOpenNote);
+  }, []);
+
   const handleFilePathChange = useCallback((newPath: string) => {
     setSelectedFile(newPath);
   }, []);
@@ -5,8 +5,12 @@ import {
   EditorComponentHandle,
 } from "../editor/EditorComponent";
 import { EditorToolbar } from "../editor/EditorToolbar";
+import { OutlinePanel } from "../editor/OutlinePanel";
+import { ProblemsPanel } from "../editor/ProblemsPanel";
+import type { Diagnostic, ParseResult, TocEntry } from "../editor/preview/MarkdownPreview";
 import { Title } from "../editor/Title";
 import { useError } from "../../contexts/ErrorContext";
+import { cacheUtils } from "../../utils/localStorage";
 
 interface WindowContentProps {
   selectedFile: string;
@@ -30,6 +34,10 @@ export const WindowContent = memo(function WindowContent({
   const [isLoading, setIsLoading] = useState(false);
   const [isModified, setIsModified] = useState(false);
   const [isPreviewMode, setIsPreviewMode] = useState(false);
+  const [isOutlineOpen, setIsOutlineOpen] = useState(false);
+  const [outline, setOutline] = useState<TocEntry[]>([]);
+  const [isProblemsOpen, setIsProblemsOpen] = useState(false);
+  const [diagnostics, setDiagnostics] = useState<Diagnostic[]>([]);
   const editorRef = useRef<EditorComponentHandle>(null);
   const { showError } = useError();
 
@@ -122,13 +130,131 @@ export const WindowContent = memo(function WindowContent({
     setIsPreviewMode((prev) => !prev);
   }, []);
 
-  const performSave = useCallback(() => {
+  const toggleOutline = useCallback(() => {
+    setIsOutlineOpen((prev) => !prev);
+  }, []);
+
+  // Sumário publicado pelo preview a cada renderização do arquivo aberto
+  useEffect(() => {
+    setOutline([]);
+    const handleOutline = (event: Event) => {
+      const { path, toc } = (event as CustomEvent<{ path?: string; toc: TocEntry[] }>).detail;
+      if (path === selectedFile) setOutline(toc);
+    };
+
+    window.addEventListener("inkdown-outline", handleOutline);
+    return () => window.removeEventListener("inkdown-outline", handleOutline);
+  }, [selectedFile]);
+
+  const handleSelectHeading = useCallback((entry: TocEntry) => {
+    const heading = document.querySelector<HTMLElement>(
+      `.markdown-preview-content [id="${CSS.escape(entry.slug)}"]`
+    );
+    heading?.scrollIntoView({ behavior: "smooth", block: "start" });
+  }, []);
+
+  const toggleProblems = useCallback(() => {
+    setIsProblemsOpen((prev) => !prev);
+  }, []);
+
+  // Diagnósticos do lint, publicados pelo preview junto com o sumário
+  useEffect(() => {
+    setDiagnostics([]);
+    const handleDiagnostics = (event: Event) => {
+      const { path, diagnostics } = (event as CustomEvent<{ path?: string; diagnostics: Diagnostic[] }>).detail;
+      if (path === selectedFile) setDiagnostics(diagnostics);
+    };
+
+    window.addEventListener("inkdown-diagnostics", handleDiagnostics);
+    return () => window.removeEventListener("inkdown-diagnostics", handleDiagnostics);
+  }, [selectedFile]);
+
+  // Rola o preview até o último elemento que começa antes da linha do problema
+  const handleSelectDiagnostic = useCallback((diagnostic: Diagnostic) => {
+    const elements = document.querySelectorAll<HTMLElement>(".markdown-preview-content [data-source-line]");
+    let target: HTMLElement | undefined;
+    for (const element of Array.from(elements)) {
+      if (Number(element.dataset.sourceLine) > diagnostic.line) break;
+      target = element;
+    }
+    target?.scrollIntoView({ behavior: "smooth", block: "center" });
+  }, []);
+
+  // A correção substitui linhas do texto que o preview renderizou por último
+  const handleApplyFix = useCallback((diagnostic: Diagnostic) => {
+    const { fix } = diagnostic;
+    if (!fix || !editorRef.current) return;
+
+    const lines = editorRef.current.getContent().split("\n");
+    lines.splice(fix.start_line, fix.end_line - fix.start_line, ...fix.lines);
+    editorRef.current.setContent(lines.join("\n"));
+  }, []);
+
+  const performSave = useCallback(async () => {
     if (editorRef.current) {
-      const currentContent = editorRef.current.getContent();
+      let currentContent = editorRef.current.getContent();
+
+      const workspaceConfig = cacheUtils.getWorkspaceConfig();
+      if (workspaceConfig?.formatOnSave) {
+        try {
+          const formatted = await invoke<string>("format_markdown", {
+            markdown: currentContent,
+            options: {
+              wrap: workspaceConfig.formatLineWrap ?? "preserve",
+              line_width: workspaceConfig.formatLineWidth ?? 80,
+            },
+          });
+          if (formatted !== currentContent) {
+            editorRef.current?.setContent(formatted);
+            currentContent = formatted;
+          }
+        } catch (err) {
+          // Salva o conteúdo original se a formatação falhar
+          console.error("Error formatting file:", err);
+        }
+      }
+
       handleSave(currentContent);
     }
   }, [handleSave]);
 
+  // Disparado pelo preview ao clicar numa tarefa: o backend marca a tarefa
+  // direto no arquivo e o editor recebe o conteúdo gravado
+  useEffect(() => {
+    const handleToggleTask = async (event: Event) => {
+      const { line, expected } = (event as CustomEvent<{ line: number; expected: string }>).detail;
+      if (!selectedFile || !editorRef.current) return;
+
+      // Alterações pendentes vão para o disco antes, sem formatar, para as
+      // linhas do preview serem as do arquivo
+      if (isModified && !(await saveFileContent(selectedFile, editorRef.current.getContent()))) return;
+
+      let result: ParseResult;
+      let content: string;
+      try {
+        result = await invoke<ParseResult>("toggle_task", { path: selectedFile, line, expected, blocks: true });
+        content = await invoke<string>("read_file", { path: selectedFile });
+      } catch (err) {
+        showError({
+          title: "Não foi possível atualizar a tarefa",
+          message: "A linha da tarefa mudou desde a última renderização. Aguarde a prévia atualizar e tente novamente.",
+          details: err instanceof Error ? err.message : String(err)
+        });
+        return;
+      }
+
+      window.dispatchEvent(new CustomEvent("inkdown-task-toggled", {
+        detail: { path: selectedFile, content, result }
+      }));
+      editorRef.current?.setContent(content);
+      setFileContent(content);
+      setIsModified(false);
+    };
+
+    window.addEventListener("inkdown-toggle-task", handleToggleTask);
+    return () => window.removeEventListener("inkdown-toggle-task", handleToggleTask);
+  }, [selectedFile, isModified, saveFileContent, showError]);
+
   useEffect(() => {
     onSaveRef.current = performSave;
     return () => {
@@ -219,18 +345,32 @@ export const WindowContent = memo(function WindowContent({
       <EditorToolbar
         isPreviewMode={isPreviewMode}
         onTogglePreview={togglePreviewMode}
+        isOutlineOpen={isOutlineOpen}
+        onToggleOutline={toggleOutline}
+        isProblemsOpen={isProblemsOpen}
+        onToggleProblems={toggleProblems}
+        problemCount={diagnostics.length}
       />
 
-      <div className="theme-editor">
-        <EditorComponent
-          ref={editorRef}
-          initialContent={fileContent}
-          themeName={resolvedTheme}
-          showPreview={isPreviewMode}
-          onContentChange={handleContentChange}
-          onSave={handleSave}
-          onError={handleError}
-        />
+      <div className="theme-editor flex">
+        <div className="flex-1 min-w-0">
+          <EditorComponent
+            ref={editorRef}
+            initialContent={fileContent}
+            filePath={selectedFile}
+            themeName={resolvedTheme}
+            showPreview={isPreviewMode}
+            onContentChange={handleContentChange}
+            onSave={handleSave}
+            onError={handleError}
+          />
+        </div>
+        {isPreviewMode && isOutlineOpen && (
+          <OutlinePanel entries={outline} onSelect={handleSelectHeading} />
+        )}
+        {isPreviewMode && isProblemsOpen && (
+          <ProblemsPanel diagnostics={diagnostics} onSelect={handleSelectDiagnostic} onFix={handleApplyFix} />
+        )}
       </div>
     </div>
   );
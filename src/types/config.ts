@@ -78,6 +78,63 @@ export interface KeyboardShortcut {
   shortcut: string;
 }
 
+// Callout type for `> [!name]`; `class` selects the `alert-<class>` colour
+export interface CalloutType {
+  name: string;
+  icon: string;
+  class: string;
+}
+
+// Extensões de sintaxe do parser, espelhando ParserOptions em parser.rs
+export interface MarkdownSyntax {
+  tables: boolean;
+  task_lists: boolean;
+  strikethrough: boolean;
+  autolinks: boolean;
+  footnotes: boolean;
+  alerts: boolean;
+  math: boolean;
+  wikilinks: boolean;
+  tags: boolean;
+  front_matter: boolean;
+}
+
+// Predefinições escolhidas por `githubMarkdown`
+export const GITHUB_MARKDOWN_SYNTAX: MarkdownSyntax = {
+  tables: true,
+  task_lists: true,
+  strikethrough: true,
+  autolinks: true,
+  footnotes: true,
+  alerts: true,
+  math: true,
+  wikilinks: true,
+  tags: true,
+  front_matter: true,
+};
+
+// CommonMark puro; o front matter continua sendo lido por ser metadado da nota
+export const BASIC_MARKDOWN_SYNTAX: MarkdownSyntax = {
+  tables: false,
+  task_lists: false,
+  strikethrough: false,
+  autolinks: false,
+  footnotes: false,
+  alerts: false,
+  math: false,
+  wikilinks: false,
+  tags: false,
+  front_matter: true,
+};
+
+// Severidade de cada regra de lint; regras omitidas usam a padrão
+export type LintRuleLevel = 'off' | 'info' | 'warning' | 'error';
+
+export interface LintConfig {
+  enabled?: boolean;
+  rules?: Record<string, LintRuleLevel>;
+}
+
 export interface WorkspaceConfig {
   workspace_path: string | null;
   vimMode?: boolean;
@@ -86,7 +143,19 @@ export interface WorkspaceConfig {
   readOnly: boolean;
   sidebarVisible?: boolean;
   githubMarkdown?: boolean;
+  // Liga ou desliga extensões individuais por cima da predefinição
+  markdownSyntax?: Partial<MarkdownSyntax>;
   pasteUrlsAsLinks?: boolean;
   devMode?: boolean;
+  calloutTypes?: CalloutType[];
+  formatOnSave?: boolean;
+  formatLineWrap?: 'preserve' | 'unwrap' | 'wrap';
+  formatLineWidth?: number;
+  lint?: LintConfig;
   shortcuts: KeyboardShortcut[];
-}
\ No newline at end of file
+}
+
+export function resolveMarkdownSyntax(config?: Partial<WorkspaceConfig> | null): MarkdownSyntax {
+  const preset = config?.githubMarkdown ? GITHUB_MARKDOWN_SYNTAX : BASIC_MARKDOWN_SYNTAX;
+  return { ...preset, ...config?.markdownSyntax };
+}
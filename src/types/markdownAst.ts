@@ -0,0 +1,44 @@
+// Shape of `parse_markdown_ast`, mirroring src-tauri/src/markdown/ast.rs.
+// `version` changes whenever a node or field changes shape.
+export const MARKDOWN_AST_VERSION = 1;
+
+export interface MarkdownAst {
+  version: number;
+  front_matter?: Record<string, unknown>;
+  children: AstNode[];
+}
+
+// Source lines are 1-based and inclusive
+export interface AstPosition {
+  start_line: number;
+  end_line: number;
+}
+
+export type CellAlignment = 'left' | 'center' | 'right' | 'none';
+
+export interface EmbedLink {
+  target: string;
+  heading: string | null;
+  alias: string | null;
+}
+
+// Text fields hold inline markdown as written
+export type AstNodeKind =
+  | { type: 'heading'; level: number; text: string }
+  | { type: 'paragraph'; text: string }
+  | { type: 'code_block'; language: string | null; code: string }
+  | { type: 'math'; tex: string }
+  | { type: 'html'; html: string }
+  | { type: 'list'; ordered: boolean; start: number; tight: boolean }
+  | { type: 'list_item'; checked: boolean | null }
+  | { type: 'table'; alignments: CellAlignment[]; headers: string[]; rows: string[][] }
+  | { type: 'blockquote' }
+  | { type: 'callout'; kind: string; title: string | null; folded: boolean | null }
+  | { type: 'footnote_definition'; label: string }
+  | { type: 'embed'; links: EmbedLink[] }
+  | { type: 'thematic_break' };
+
+export type AstNode = AstNodeKind & {
+  position: AstPosition;
+  children?: AstNode[];
+};